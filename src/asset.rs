@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::time::Duration;
+use anyhow::Result;
+use regex::Regex;
+use reqwest::StatusCode;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::backup::BodyFormat;
+use crate::dom::{self, Node};
+use crate::html::is_yuque_cdn;
+use crate::lake::{decode_card_value, map_card_strings};
+use crate::utils::{error_status, ApiError};
+use crate::yuque::Yuque;
+
+pub static ASSET_DIR: &str = "assets";
+pub static ASSET_INDEX_FILE: &str = "index.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AssetKind {
+    Image,
+    Attachment,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AssetRef {
+    pub url: String,
+    pub kind: AssetKind,
+}
+
+static MARKDOWN_LINK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"(!?)\[[^\]]*\]\((https?://[^)\s]+)"#).unwrap());
+
+static ASSET_TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"<(img|a|card)\b[^>]*>"#).unwrap());
+
+fn is_remote(url: &str) -> bool {
+    url.starts_with("https://") || url.starts_with("http://")
+}
+
+fn is_attachment(url: &str) -> bool {
+    is_remote(url) && url.contains("/attachments/")
+}
+
+fn push(refs: &mut Vec<AssetRef>, url: &str, kind: AssetKind) {
+    if is_remote(url) && !refs.iter().any(|r| r.url == url) {
+        refs.push(AssetRef { url: url.to_string(), kind });
+    }
+}
+
+fn lake_card_kind(name: &str) -> Option<(&'static str, AssetKind)> {
+    match name {
+        "image" => Some(("src", AssetKind::Image)),
+        "file" => Some(("src", AssetKind::Attachment)),
+        "diagram" | "board" | "puml" | "plantuml" | "mermaid" => Some(("url", AssetKind::Image)),
+        _ => None,
+    }
+}
+
+/// Returns the remote images and attachments referenced by a body.
+pub fn extract(body: &str, format: BodyFormat) -> Vec<AssetRef> {
+    let mut refs = Vec::new();
+    if format == BodyFormat::Markdown {
+        for cap in MARKDOWN_LINK.captures_iter(body) {
+            match &cap[1] {
+                "!" => push(&mut refs, &cap[2], AssetKind::Image),
+                _ if is_attachment(&cap[2]) => push(&mut refs, &cap[2], AssetKind::Attachment),
+                _ => {}
+            }
+        }
+    }
+    // Markdown bodies may embed html too.
+    for cap in ASSET_TAG.captures_iter(body) {
+        let Some(Node::Element(el)) = dom::parse(&cap[0]).into_iter().next() else { continue };
+        match el.tag.as_str() {
+            "img" => push(&mut refs, el.attr("src").unwrap_or_default(), AssetKind::Image),
+            "a" => {
+                let href = el.attr("href").unwrap_or_default();
+                if is_attachment(href) {
+                    push(&mut refs, href, AssetKind::Attachment);
+                }
+            }
+            _ => {
+                let Some((field, kind)) = lake_card_kind(el.attr("name").unwrap_or_default()) else { continue };
+                let value = decode_card_value(el.attr("value").unwrap_or_default());
+                if let Some(url) = value.get(field).and_then(Value::as_str) {
+                    push(&mut refs, url, kind);
+                }
+            }
+        }
+    }
+    refs
+}
+
+/// Replaces asset URLs in a body, including those inside encoded Lake cards.
+pub fn rewrite(body: &str, urls: &HashMap<String, String>) -> String {
+    if urls.is_empty() {
+        return body.to_string();
+    }
+    let body = map_card_strings(body, |s| urls.get(s).cloned());
+    // Longest first, so a URL that prefixes another one does not clobber it.
+    let mut sorted: Vec<_> = urls.iter().collect();
+    sorted.sort_by_key(|(url, _)| std::cmp::Reverse(url.len()));
+    let mut out = body;
+    for (url, local) in sorted {
+        out = out.replace(url.as_str(), local);
+    }
+    out
+}
+
+pub(crate) fn extension(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let name = path.rsplit('/').next().unwrap_or_default();
+    match name.rsplit_once('.') {
+        Some((_, ext)) if !ext.is_empty() && ext.len() <= 5 && ext.chars().all(|c| c.is_ascii_alphanumeric()) => format!(".{}", ext.to_lowercase()),
+        _ => String::new(),
+    }
+}
+
+pub(crate) fn media_type(name: &str) -> &'static str {
+    match extension(name).as_str() {
+        ".png" => "image/png",
+        ".jpg" | ".jpeg" => "image/jpeg",
+        ".gif" => "image/gif",
+        ".svg" => "image/svg+xml",
+        ".webp" => "image/webp",
+        ".bmp" => "image/bmp",
+        ".pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// The content-addressed path of an asset: `assets/<sha256><ext>`.
+pub fn content_path(url: &str, data: &[u8]) -> String {
+    format!("{}/{}{}", ASSET_DIR, hex::encode(Sha256::digest(data)), extension(url))
+}
+
+/// Relative link from the file at `from` to `to`, both `/` separated and
+/// relative to the same root.
+pub fn relative_link(from: &str, to: &str) -> String {
+    let from_dir: Vec<&str> = from.split('/').filter(|s| !s.is_empty()).collect();
+    let from_dir = &from_dir[..from_dir.len().saturating_sub(1)];
+    let to: Vec<&str> = to.split('/').filter(|s| !s.is_empty()).collect();
+    let common = from_dir.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut parts = vec![".."; from_dir.len() - common];
+    parts.extend(&to[common..]);
+    parts.join("/")
+}
+
+/// Downloaded assets under a local root, indexed by their source URL.
+#[derive(Debug, Clone, Default)]
+pub struct AssetStore {
+    pub root: PathBuf,
+    /// URL to path relative to `root`.
+    pub index: HashMap<String, String>,
+}
+
+impl AssetStore {
+    /// Opens a store, picking up the index of earlier runs.
+    pub fn open<P: AsRef<Path>>(root: P) -> Result<AssetStore> {
+        let root = root.as_ref().to_path_buf();
+        let index_path = root.join(ASSET_DIR).join(ASSET_INDEX_FILE);
+        let index = match std::fs::read(&index_path) {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(AssetStore { root, index })
+    }
+
+    pub fn get(&self, url: &str) -> Option<&str> {
+        self.index.get(url).map(String::as_str)
+    }
+
+    pub fn insert(&mut self, url: &str, data: &[u8]) -> Result<String> {
+        let path = content_path(url, data);
+        let full = self.root.join(&path);
+        if !full.exists() {
+            std::fs::create_dir_all(full.parent().unwrap())?;
+            std::fs::write(&full, data)?;
+        }
+        self.index.insert(url.to_string(), path.clone());
+        Ok(path)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let dir = self.root.join(ASSET_DIR);
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join(ASSET_INDEX_FILE), serde_json::to_vec_pretty(&self.index)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    /// Attempts after the first failure.
+    pub retries: u32,
+    /// Delay before the first retry, doubled on each further one.
+    pub backoff: Duration,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        DownloadOptions { retries: 3, backoff: Duration::from_millis(500) }
+    }
+}
+
+/// Whether `url` is served by Yuque, the only hosts trusted with the token.
+fn is_yuque_host(url: &str) -> bool {
+    let Ok(url) = reqwest::Url::parse(url) else { return false };
+    let Some(host) = url.host_str().filter(|_| matches!(url.scheme(), "https" | "http")) else { return false };
+    host == "yuque.com" || host.ends_with(".yuque.com") || is_yuque_cdn(&format!("https://{}/", host))
+}
+
+/// Connection errors, rate limiting and server errors are worth another try.
+fn retryable(err: &anyhow::Error) -> bool {
+    error_status(err).is_none_or(|status| status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error())
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LocalizedBody {
+    pub body: String,
+    /// URLs that could not be downloaded and were left as they are.
+    pub failed: Vec<String>,
+}
+
+impl Yuque {
+    /// Downloads `url`, with the token only when it is a Yuque host.
+    pub async fn download(&self, url: &str) -> Result<Vec<u8>> {
+        let client = if is_yuque_host(url) { &self.client } else { &self.anonymous };
+        let resp = client.get(url).send().await?;
+        if resp.status().is_success() {
+            Ok(resp.bytes().await?.to_vec())
+        } else {
+            Err(ApiError::from_response(resp).await.into())
+        }
+    }
+
+    pub async fn download_with_retry(&self, url: &str, options: &DownloadOptions) -> Result<Vec<u8>> {
+        let mut delay = options.backoff;
+        let mut attempt = 0;
+        loop {
+            match self.download(url).await {
+                Ok(data) => return Ok(data),
+                Err(e) if attempt >= options.retries || !retryable(&e) => return Err(e),
+                Err(_) => {
+                    attempt += 1;
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+            }
+        }
+    }
+
+    /// Downloads the assets of a body into `store` and rewrites their URLs
+    /// to links relative to `doc_path`, the doc's path under the store root.
+    pub async fn localize_assets(&self, store: &mut AssetStore, body: &str, format: BodyFormat, doc_path: &str, options: &DownloadOptions) -> Result<LocalizedBody> {
+        let mut urls = HashMap::new();
+        let mut failed = Vec::new();
+        for asset in extract(body, format) {
+            let path = match store.get(&asset.url) {
+                Some(path) => path.to_string(),
+                None => match self.download_with_retry(&asset.url, options).await {
+                    Ok(data) => store.insert(&asset.url, &data)?,
+                    Err(_) => {
+                        failed.push(asset.url);
+                        continue;
+                    }
+                },
+            };
+            urls.insert(asset.url, relative_link(doc_path, &path));
+        }
+        Ok(LocalizedBody { body: rewrite(body, &urls), failed })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use reqwest::StatusCode;
+    use crate::asset::{extract, is_yuque_host, relative_link, retryable, rewrite, AssetKind, AssetStore};
+    use crate::utils::{is_not_found, ApiError};
+    use crate::backup::BodyFormat;
+    use crate::lake::{encode_card_value, parse, Block};
+
+    #[test]
+    fn test_retryable() {
+        let error = |status: StatusCode| anyhow::Error::from(ApiError { status, body: String::new() });
+        assert!(retryable(&error(StatusCode::TOO_MANY_REQUESTS)));
+        assert!(retryable(&error(StatusCode::BAD_GATEWAY)));
+        assert!(!retryable(&error(StatusCode::FORBIDDEN)));
+        assert!(retryable(&anyhow::anyhow!("connection reset")));
+        assert!(is_not_found(&error(StatusCode::NOT_FOUND)));
+        assert!(!is_not_found(&anyhow::anyhow!("404 Not Found")));
+        assert_eq!(error(StatusCode::NOT_FOUND).to_string(), "404 Not Found ");
+    }
+
+    #[test]
+    fn test_is_yuque_host() {
+        assert!(is_yuque_host("https://www.yuque.com/attachments/yuque/0/f.pdf"));
+        assert!(is_yuque_host("https://cdn.nlark.com/yuque/0/a.png"));
+        assert!(!is_yuque_host("https://example.com/a.png"));
+        assert!(!is_yuque_host("https://yuque.com.example.com/a.png"));
+        assert!(!is_yuque_host("https://cdn.nlark.com@example.com/a.png"));
+    }
+
+    #[tokio::test]
+    async fn test_download_without_token() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut headers = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).await.unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                headers.push(line.to_lowercase());
+            }
+            reader.get_mut().write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok").await.unwrap();
+            headers
+        });
+
+        let yuque = crate::yuque::Yuque::new("s3cret").unwrap();
+        assert_eq!(yuque.download(&format!("http://{}/a.png", addr)).await.unwrap(), b"ok");
+        let headers = server.await.unwrap();
+        assert!(headers.iter().any(|line| line.starts_with("user-agent:")));
+        assert!(!headers.iter().any(|line| line.starts_with("x-auth-token:")));
+    }
+
+    #[test]
+    fn test_extract() {
+        let markdown = r#"![a](https://cdn.nlark.com/yuque/0/a.png) <img alt="b" src="https://cdn.nlark.com/yuque/0/b.jpg"> ![c](./local.png) [f.pdf](https://www.yuque.com/attachments/yuque/0/f.pdf) [site](https://example.com)"#;
+        let refs = extract(markdown, BodyFormat::Markdown);
+        let urls: Vec<_> = refs.iter().map(|r| (r.url.as_str(), r.kind)).collect();
+        assert_eq!(urls, vec![
+            ("https://cdn.nlark.com/yuque/0/a.png", AssetKind::Image),
+            ("https://www.yuque.com/attachments/yuque/0/f.pdf", AssetKind::Attachment),
+            ("https://cdn.nlark.com/yuque/0/b.jpg", AssetKind::Image),
+        ]);
+
+        let value = encode_card_value(&serde_json::json!({"src": "https://cdn.nlark.com/yuque/0/c.png", "name": "c"}));
+        let lake = format!(r#"<p><card type="inline" name="image" value="{}"></card></p>"#, value);
+        assert_eq!(extract(&lake, BodyFormat::Lake)[0].url, "https://cdn.nlark.com/yuque/0/c.png");
+
+        let urls = HashMap::from([("https://cdn.nlark.com/yuque/0/c.png".to_string(), "../assets/c.png".to_string())]);
+        let Block::Paragraph(content) = &parse(&rewrite(&lake, &urls)).blocks[0] else { panic!() };
+        assert!(matches!(&content[0], crate::lake::Inline::Image(image) if image.src == "../assets/c.png"));
+    }
+
+    #[test]
+    fn test_relative_link() {
+        assert_eq!(relative_link("repo/doc.md", "assets/a.png"), "../assets/a.png");
+        assert_eq!(relative_link("doc.md", "assets/a.png"), "assets/a.png");
+        assert_eq!(relative_link("a/b/doc.md", "a/assets/x.png"), "../assets/x.png");
+    }
+
+    #[test]
+    fn test_store_dedupes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = AssetStore::open(dir.path()).unwrap();
+        let a = store.insert("https://cdn.nlark.com/a.png", b"same").unwrap();
+        let b = store.insert("https://cdn.nlark.com/b.png", b"same").unwrap();
+        assert_eq!(a, b);
+        store.save().unwrap();
+        let reopened = AssetStore::open(dir.path()).unwrap();
+        assert_eq!(reopened.get("https://cdn.nlark.com/b.png"), Some(a.as_str()));
+    }
+}
@@ -0,0 +1,433 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Display;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use anyhow::Result;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+
+use crate::asset::{self, DownloadOptions};
+use crate::{repo::ObjType, serializer::{BookSerializer, DocSerializer}, yuque::Yuque};
+
+pub static MANIFEST_FILE: &str = "manifest.json";
+pub static BACKUP_FORMAT_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BodyFormat {
+    Markdown,
+    Lake,
+    Html,
+}
+
+impl BodyFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            BodyFormat::Markdown => "md",
+            BodyFormat::Lake => "lake",
+            BodyFormat::Html => "html",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub version: u32,
+    pub created_at: String,
+    /// `users` or `groups`, as used in the API path.
+    pub owner_type: String,
+    pub owner: String,
+    /// `created_at` of the backup this one is incremental to.
+    #[serde(default)]
+    pub base: Option<String>,
+    pub repos: Vec<RepoBackup>,
+    /// Image URL to archive path.
+    #[serde(default)]
+    pub assets: BTreeMap<String, String>,
+    #[serde(default)]
+    pub failed_assets: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoBackup {
+    /// Repo metadata including `toc_yml`.
+    pub book: BookSerializer,
+    pub docs: Vec<DocBackup>,
+    /// Ids of docs in the base backup that no longer exist.
+    #[serde(default)]
+    pub deleted_docs: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocBackup {
+    /// Doc metadata, the bodies are stored as separate archive files.
+    pub doc: DocSerializer,
+    pub bodies: BTreeMap<BodyFormat, String>,
+    /// Set in incremental backups when the bodies are in the base backup.
+    #[serde(default)]
+    pub unchanged: bool,
+}
+
+/// A backup held in memory: the manifest plus every other archive file keyed
+/// by its path inside the archive.
+#[derive(Debug, Clone)]
+pub struct BackupArchive {
+    pub manifest: BackupManifest,
+    pub files: BTreeMap<String, Vec<u8>>,
+}
+
+#[derive(Debug, Default)]
+pub struct BackupOptions {
+    /// Only back up repos with these slugs.
+    pub repos: Option<Vec<String>>,
+    pub skip_assets: bool,
+}
+
+impl BackupArchive {
+    pub fn new(owner_type: ObjType, owner: &str) -> BackupArchive {
+        BackupArchive {
+            manifest: BackupManifest {
+                version: BACKUP_FORMAT_VERSION,
+                created_at: chrono::Utc::now().to_rfc3339(),
+                owner_type: owner_type.to_string(),
+                owner: owner.to_string(),
+                base: None,
+                repos: Vec::new(),
+                assets: BTreeMap::new(),
+                failed_assets: Vec::new(),
+            },
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Writes a gzipped tar with the manifest as first entry.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let file = File::create(path)?;
+        let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        let manifest = serde_json::to_vec_pretty(&self.manifest)?;
+        append_file(&mut builder, MANIFEST_FILE, &manifest)?;
+        for (name, data) in &self.files {
+            append_file(&mut builder, name, data)?;
+        }
+        builder.into_inner()?.finish()?;
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> Result<BackupArchive> {
+        let file = File::open(path)?;
+        let mut archive = tar::Archive::new(GzDecoder::new(file));
+        let mut manifest = None;
+        let mut files = BTreeMap::new();
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let name = entry.path()?.to_string_lossy().into_owned();
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+            if name == MANIFEST_FILE {
+                manifest = Some(serde_json::from_slice::<BackupManifest>(&data)?);
+            } else {
+                files.insert(name, data);
+            }
+        }
+        let manifest = manifest.ok_or_else(|| anyhow::anyhow!("{} not found in backup", MANIFEST_FILE))?;
+        if manifest.version > BACKUP_FORMAT_VERSION {
+            return Err(anyhow::anyhow!("unsupported backup version {}", manifest.version));
+        }
+        Ok(BackupArchive { manifest, files })
+    }
+
+    pub fn body(&self, doc: &DocBackup, format: BodyFormat) -> Option<String> {
+        let path = doc.bodies.get(&format)?;
+        let data = self.files.get(path)?;
+        Some(String::from_utf8_lossy(data).into_owned())
+    }
+
+    /// Moves the bodies out of `doc` into archive files.
+    pub fn add_doc(&mut self, repo_slug: &str, mut doc: DocSerializer) -> DocBackup {
+        let mut bodies = BTreeMap::new();
+        for (format, body) in [
+            (BodyFormat::Markdown, doc.body.take()),
+            (BodyFormat::Lake, doc.body_lake.take()),
+            (BodyFormat::Html, doc.body_html.take()),
+        ] {
+            if let Some(body) = body.filter(|b| !b.is_empty()) {
+                let path = format!("repos/{}/{}.{}", repo_slug, doc.slug, format.extension());
+                self.files.insert(path.clone(), body.into_bytes());
+                bodies.insert(format, path);
+            }
+        }
+        doc.body_draft = None;
+        doc.body_draft_lake = None;
+        DocBackup { doc, bodies, unchanged: false }
+    }
+
+    pub fn add_asset(&mut self, url: &str, data: Vec<u8>) -> String {
+        let path = asset::content_path(url, &data);
+        self.manifest.assets.insert(url.to_string(), path.clone());
+        self.files.insert(path.clone(), data);
+        path
+    }
+
+    /// Applies an incremental backup on top of this one. Merging into a full
+    /// backup gives a full snapshot as of the incremental backup.
+    pub fn merge(&self, incremental: &BackupArchive) -> Result<BackupArchive> {
+        if incremental.manifest.base.as_deref() != Some(self.manifest.created_at.as_str()) {
+            return Err(anyhow::anyhow!("backup {} is not based on {}", incremental.manifest.created_at, self.manifest.created_at));
+        }
+        let mut merged = incremental.clone();
+        merged.manifest.base = self.manifest.base.clone();
+        for repo in merged.manifest.repos.iter_mut() {
+            let base_repo = self.manifest.repos.iter().find(|r| r.book.object.id == repo.book.object.id);
+            for doc in repo.docs.iter_mut().filter(|d| d.unchanged) {
+                let base_doc = base_repo
+                    .and_then(|r| r.docs.iter().find(|d| d.doc.object.id == doc.doc.object.id))
+                    .ok_or_else(|| anyhow::anyhow!("doc {} not found in base backup", doc.doc.object.id))?;
+                for path in base_doc.bodies.values() {
+                    if let Some(data) = self.files.get(path) {
+                        merged.files.insert(path.clone(), data.clone());
+                    }
+                }
+                doc.bodies = base_doc.bodies.clone();
+                doc.unchanged = base_doc.unchanged;
+            }
+            if merged.manifest.base.is_none() {
+                repo.deleted_docs.clear();
+            }
+        }
+        for (url, path) in &self.manifest.assets {
+            // Incremental backups list the base's assets without carrying
+            // their files.
+            let path = merged.manifest.assets.entry(url.clone()).or_insert_with(|| path.clone()).clone();
+            if let (Some(data), std::collections::btree_map::Entry::Vacant(entry)) = (self.files.get(&path), merged.files.entry(path.clone())) {
+                entry.insert(data.clone());
+            }
+        }
+        Ok(merged)
+    }
+
+    /// Merges a full backup followed by its incremental backups, in order.
+    pub fn merge_chain(chain: &[BackupArchive]) -> Result<BackupArchive> {
+        let (first, rest) = chain.split_first().ok_or_else(|| anyhow::anyhow!("empty backup chain"))?;
+        rest.iter().try_fold(first.clone(), |merged, incremental| merged.merge(incremental))
+    }
+}
+
+fn append_file<W: std::io::Write>(builder: &mut tar::Builder<W>, name: &str, data: &[u8]) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, name, data)?;
+    Ok(())
+}
+
+fn repo_changed(base: &BookSerializer, current: &BookSerializer) -> bool {
+    base.object.updated_at != current.object.updated_at || base.content_updated_at != current.content_updated_at
+}
+
+fn doc_changed(base: &DocSerializer, current: &DocSerializer) -> bool {
+    base.object.updated_at != current.object.updated_at || base.content_updated_at != current.content_updated_at
+}
+
+fn unchanged_doc(base: &DocBackup) -> DocBackup {
+    DocBackup {
+        doc: base.doc.clone(),
+        bodies: BTreeMap::new(),
+        unchanged: true,
+    }
+}
+
+fn same_docs(base: &RepoBackup, summaries: &[DocSerializer]) -> bool {
+    let base_ids: HashSet<u32> = base.docs.iter().map(|d| d.doc.object.id).collect();
+    let ids: HashSet<u32> = summaries.iter().map(|d| d.object.id).collect();
+    base_ids == ids
+}
+
+fn unchanged_repo(base: &RepoBackup) -> RepoBackup {
+    RepoBackup {
+        book: base.book.clone(),
+        docs: base.docs.iter().map(unchanged_doc).collect(),
+        deleted_docs: Vec::new(),
+    }
+}
+
+impl Yuque {
+    /// Backs up every repo of a user or group with docs, TOC and images.
+    pub async fn backup<T: Display>(&self, obj_type: ObjType, obj: T, options: &BackupOptions) -> Result<BackupArchive> {
+        self.backup_since(obj_type, obj, None, options).await
+    }
+
+    /// Backs up only what changed since `base`, which can be a full or an
+    /// incremental backup of the same owner. Repos and docs whose
+    /// `updated_at` and `content_updated_at` did not move are recorded as
+    /// unchanged without fetching their bodies. The docs of every repo are
+    /// still listed so that deletions are noticed, and images already in
+    /// `base` are not downloaded again.
+    pub async fn backup_incremental<T: Display>(&self, obj_type: ObjType, obj: T, base: &BackupManifest, options: &BackupOptions) -> Result<BackupArchive> {
+        self.backup_since(obj_type, obj, Some(base), options).await
+    }
+
+    /// Backs up a single repo, e.g. as the source of an export.
+    pub async fn backup_one_repo<T: Display>(&self, namespace: T, options: &BackupOptions) -> Result<BackupArchive> {
+        let namespace = namespace.to_string();
+        let book = self.get_repo(&namespace).await?.data;
+        let owner = namespace.split('/').next().unwrap_or_default().to_string();
+        let obj_type = match book.user.as_ref().and_then(|user| user.object.typ.as_deref()) {
+            Some("Group") => ObjType::Group,
+            _ => ObjType::User,
+        };
+        let mut archive = BackupArchive::new(obj_type, &owner);
+        let repo = self.backup_repo(&mut archive, &owner, &book, None, options).await?;
+        archive.manifest.repos.push(repo);
+        Ok(archive)
+    }
+
+    async fn backup_since<T: Display>(&self, obj_type: ObjType, obj: T, base: Option<&BackupManifest>, options: &BackupOptions) -> Result<BackupArchive> {
+        let owner = obj.to_string();
+        let mut archive = BackupArchive::new(obj_type, &owner);
+        if let Some(base) = base {
+            archive.manifest.base = Some(base.created_at.clone());
+            archive.manifest.assets = base.assets.clone();
+        }
+        let repos = self.list_all_repos(obj_type, &owner, None).await?;
+        for book in repos {
+            if let Some(slugs) = &options.repos {
+                if !slugs.contains(&book.slug) {
+                    continue;
+                }
+            }
+            let base_repo = base.and_then(|base| base.repos.iter().find(|r| r.book.object.id == book.object.id));
+            let namespace = book.namespace.clone().unwrap_or_else(|| format!("{}/{}", owner, book.slug));
+            let summaries = self.list_all_docs(&namespace, None).await?;
+            let repo = match base_repo {
+                Some(base_repo) if !repo_changed(&base_repo.book, &book) && same_docs(base_repo, &summaries) => unchanged_repo(base_repo),
+                _ => self.backup_listed_repo(&mut archive, &namespace, summaries, base_repo, options).await?,
+            };
+            archive.manifest.repos.push(repo);
+        }
+        Ok(archive)
+    }
+
+    pub(crate) async fn backup_repo(&self, archive: &mut BackupArchive, owner: &str, book: &BookSerializer, base: Option<&RepoBackup>, options: &BackupOptions) -> Result<RepoBackup> {
+        let namespace = book.namespace.clone().unwrap_or_else(|| format!("{}/{}", owner, book.slug));
+        let summaries = self.list_all_docs(&namespace, None).await?;
+        self.backup_listed_repo(archive, &namespace, summaries, base, options).await
+    }
+
+    async fn backup_listed_repo(&self, archive: &mut BackupArchive, namespace: &str, summaries: Vec<DocSerializer>, base: Option<&RepoBackup>, options: &BackupOptions) -> Result<RepoBackup> {
+        let book = self.get_repo(namespace).await?.data;
+        let mut docs = Vec::new();
+        for summary in &summaries {
+            let base_doc = base.and_then(|base| base.docs.iter().find(|d| d.doc.object.id == summary.object.id));
+            if let Some(base_doc) = base_doc.filter(|d| !doc_changed(&d.doc, summary)) {
+                docs.push(unchanged_doc(base_doc));
+                continue;
+            }
+            let doc = self.get_doc(namespace, summary.slug.as_str(), Some(true)).await?;
+            let doc = archive.add_doc(&book.slug, doc);
+            if !options.skip_assets {
+                self.backup_assets(archive, &doc).await;
+            }
+            docs.push(doc);
+        }
+        let deleted_docs = match base {
+            Some(base) => base
+                .docs
+                .iter()
+                .map(|d| d.doc.object.id)
+                .filter(|id| !summaries.iter().any(|s| s.object.id == *id))
+                .collect(),
+            None => Vec::new(),
+        };
+        Ok(RepoBackup { book, docs, deleted_docs })
+    }
+
+    async fn backup_assets(&self, archive: &mut BackupArchive, doc: &DocBackup) {
+        let mut urls = HashSet::new();
+        for format in doc.bodies.keys() {
+            if let Some(body) = archive.body(doc, *format) {
+                urls.extend(asset::extract(&body, *format).into_iter().map(|asset| asset.url));
+            }
+        }
+        for url in urls {
+            if archive.manifest.assets.contains_key(&url) || archive.manifest.failed_assets.contains(&url) {
+                continue;
+            }
+            match self.download_with_retry(&url, &DownloadOptions::default()).await {
+                Ok(data) => {
+                    archive.add_asset(&url, data);
+                }
+                Err(_) => archive.manifest.failed_assets.push(url),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backup::{same_docs, unchanged_repo, BackupArchive, BodyFormat, RepoBackup};
+    use crate::repo::ObjType;
+    use crate::serializer::{BookSerializer, DocSerializer};
+
+    #[test]
+    fn test_merge_incremental() {
+        let mut full = BackupArchive::new(ObjType::User, "me");
+        full.manifest.created_at = "t1".to_string();
+        let kept = full.add_doc("kb", DocSerializer { slug: "kept".to_string(), body: Some("kept".to_string()), ..DocSerializer::default() });
+        let mut gone = full.add_doc("kb", DocSerializer { slug: "gone".to_string(), body: Some("gone".to_string()), ..DocSerializer::default() });
+        gone.doc.object.id = 2;
+        full.manifest.repos.push(RepoBackup { book: BookSerializer::default(), docs: vec![kept.clone(), gone], deleted_docs: Vec::new() });
+        let image = full.add_asset("https://cdn.nlark.com/a.png", b"png".to_vec());
+
+        let mut incremental = BackupArchive::new(ObjType::User, "me");
+        incremental.manifest.base = Some("t1".to_string());
+        incremental.manifest.assets = full.manifest.assets.clone();
+        assert!(!same_docs(&full.manifest.repos[0], std::slice::from_ref(&kept.doc)));
+        assert!(same_docs(&full.manifest.repos[0], &full.manifest.repos[0].docs.iter().map(|d| d.doc.clone()).collect::<Vec<_>>()));
+        let mut repo = unchanged_repo(&full.manifest.repos[0]);
+        repo.docs.truncate(1);
+        repo.deleted_docs.push(2);
+        let mut added = incremental.add_doc("kb", DocSerializer { slug: "new".to_string(), body: Some("new".to_string()), ..DocSerializer::default() });
+        added.doc.object.id = 3;
+        repo.docs.push(added.clone());
+        incremental.manifest.repos.push(repo);
+
+        let merged = BackupArchive::merge_chain(&[full.clone(), incremental.clone()]).unwrap();
+        assert_eq!(merged.manifest.base, None);
+        let docs = &merged.manifest.repos[0].docs;
+        assert_eq!(docs.len(), 2);
+        assert!(docs.iter().all(|d| !d.unchanged));
+        assert_eq!(merged.body(&docs[0], BodyFormat::Markdown).unwrap(), "kept");
+        assert_eq!(merged.body(&docs[1], BodyFormat::Markdown).unwrap(), "new");
+        assert!(merged.manifest.repos[0].deleted_docs.is_empty());
+        assert_eq!(merged.files.get(&image).unwrap(), b"png");
+
+        assert!(incremental.merge(&full).is_err());
+    }
+
+    #[test]
+    fn test_archive_round_trip() {
+        let mut archive = BackupArchive::new(ObjType::Group, "team");
+        let doc = DocSerializer {
+            slug: "intro".to_string(),
+            body: Some("# Intro".to_string()),
+            body_html: Some("<h1>Intro</h1>".to_string()),
+            ..DocSerializer::default()
+        };
+        let doc = archive.add_doc("handbook", doc);
+        assert_eq!(doc.doc.body, None);
+        let path = archive.add_asset("https://cdn.nlark.com/a.PNG?x=1", b"png".to_vec());
+        assert!(path.starts_with("assets/") && path.ends_with(".png"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("backup.tar.gz");
+        archive.write(&file).unwrap();
+        let restored = BackupArchive::read(&file).unwrap();
+        assert_eq!(restored.manifest.owner, "team");
+        assert_eq!(restored.manifest.owner_type, "groups");
+        assert_eq!(restored.body(&doc, BodyFormat::Markdown).unwrap(), "# Intro");
+        assert_eq!(restored.body(&doc, BodyFormat::Lake), None);
+        assert_eq!(restored.files.get(&path).unwrap(), b"png");
+    }
+}
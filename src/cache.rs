@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::Result;
+use lru::LruCache;
+use reqwest::header::{HeaderMap, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::utils::ApiError;
+use crate::yuque::Yuque;
+
+static API_PREFIX: &str = "/api/v2";
+static DEFAULT_CAPACITY: usize = 256;
+static DEFAULT_TTL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct CacheOptions {
+    /// Responses kept in memory.
+    pub capacity: usize,
+    /// Also keeps responses on disk, so they survive restarts.
+    pub dir: Option<PathBuf>,
+    /// How long a response is used without asking the server again.
+    pub default_ttl: Duration,
+    /// TTLs by API path, where `*` stands for one segment, e.g.
+    /// `/repos/*/*/docs`. A pattern also covers the paths below it and the
+    /// longest matching pattern wins. A zero TTL revalidates every time.
+    pub ttls: Vec<(String, Duration)>,
+}
+
+impl Default for CacheOptions {
+    fn default() -> Self {
+        CacheOptions { capacity: DEFAULT_CAPACITY, dir: None, default_ttl: DEFAULT_TTL, ttls: Vec::new() }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedResponse {
+    /// Hash of the token, method and URL, with the query.
+    pub key: String,
+    /// API path, e.g. `/repos/team/kb/docs`.
+    pub path: String,
+    pub body: String,
+    #[serde(default)]
+    pub etag: Option<String>,
+    #[serde(default)]
+    pub last_modified: Option<String>,
+    /// Milliseconds since the epoch.
+    pub stored_at: u64,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or_default()
+}
+
+/// Whether `pattern` matches `path` or one of its ancestors.
+fn path_matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<&str> = pattern.trim_end_matches('/').split('/').collect();
+    let path: Vec<&str> = path.split('/').collect();
+    pattern.len() <= path.len() && pattern.iter().zip(&path).all(|(p, s)| *p == "*" || p == s)
+}
+
+/// Whether `path` is named like a response file, `<sha256>.json`.
+fn is_entry_file(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else { return false };
+    name.strip_suffix(".json").is_some_and(|hash| hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+/// Responses of GET requests, in an LRU in memory and optionally on disk.
+pub struct ResponseCache {
+    options: CacheOptions,
+    entries: Mutex<LruCache<String, CachedResponse>>,
+    /// The API paths of the responses on disk, by key.
+    index: Mutex<HashMap<String, String>>,
+}
+
+impl fmt::Debug for ResponseCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ResponseCache").field("options", &self.options).finish()
+    }
+}
+
+impl ResponseCache {
+    pub fn new(options: CacheOptions) -> Result<ResponseCache> {
+        if let Some(dir) = &options.dir {
+            std::fs::create_dir_all(dir)?;
+        }
+        let capacity = NonZeroUsize::new(options.capacity).unwrap_or(NonZeroUsize::MIN);
+        let index = Mutex::new(ResponseCache::load_index(options.dir.as_deref())?);
+        Ok(ResponseCache { options, entries: Mutex::new(LruCache::new(capacity)), index })
+    }
+
+    /// Reads the responses on disk once. Files that are not named like a
+    /// response or do not parse are left alone.
+    fn load_index(dir: Option<&Path>) -> Result<HashMap<String, String>> {
+        let mut index = HashMap::new();
+        let Some(dir) = dir else { return Ok(index) };
+        for file in std::fs::read_dir(dir)?.flatten() {
+            let path = file.path();
+            if !is_entry_file(&path) {
+                continue;
+            }
+            let entry = std::fs::read(&path).ok().and_then(|data| serde_json::from_slice::<CachedResponse>(&data).ok());
+            if let Some(entry) = entry {
+                index.insert(entry.key, entry.path);
+            }
+        }
+        Ok(index)
+    }
+
+    pub fn ttl(&self, path: &str) -> Duration {
+        self.options
+            .ttls
+            .iter()
+            .filter(|(pattern, _)| path_matches(pattern, path))
+            .max_by_key(|(pattern, _)| pattern.split('/').count())
+            .map(|(_, ttl)| *ttl)
+            .unwrap_or(self.options.default_ttl)
+    }
+
+    fn disk_path(&self, key: &str) -> Option<PathBuf> {
+        let dir = self.options.dir.as_ref()?;
+        Some(dir.join(format!("{}.json", hex::encode(Sha256::digest(key.as_bytes())))))
+    }
+
+    pub fn get(&self, key: &str) -> Option<CachedResponse> {
+        if let Some(entry) = self.entries.lock().unwrap().get(key) {
+            return Some(entry.clone());
+        }
+        if !self.index.lock().unwrap().contains_key(key) {
+            return None;
+        }
+        let data = std::fs::read(self.disk_path(key)?).ok()?;
+        let entry: CachedResponse = serde_json::from_slice(&data).ok()?;
+        self.entries.lock().unwrap().put(key.to_string(), entry.clone());
+        Some(entry)
+    }
+
+    /// Disk writes are best effort, a failure only loses the entry.
+    pub fn put(&self, entry: CachedResponse) {
+        if let Some(path) = self.disk_path(&entry.key) {
+            if let Ok(data) = serde_json::to_vec(&entry) {
+                if std::fs::write(path, data).is_ok() {
+                    self.index.lock().unwrap().insert(entry.key.clone(), entry.path.clone());
+                }
+            }
+        }
+        self.entries.lock().unwrap().put(entry.key.clone(), entry);
+    }
+
+    /// Drops the responses whose path matches one of `patterns`, see
+    /// `CacheOptions::ttls`.
+    pub fn invalidate<S: AsRef<str>>(&self, patterns: &[S]) {
+        let matches = |path: &str| patterns.iter().any(|pattern| path_matches(pattern.as_ref(), path));
+        {
+            let mut entries = self.entries.lock().unwrap();
+            let keys: Vec<String> = entries.iter().filter(|(_, entry)| matches(&entry.path)).map(|(key, _)| key.clone()).collect();
+            for key in keys {
+                entries.pop(&key);
+            }
+        }
+        let mut index = self.index.lock().unwrap();
+        let keys: Vec<String> = index.iter().filter(|(_, path)| matches(path)).map(|(key, _)| key.clone()).collect();
+        for key in keys {
+            if let Some(path) = self.disk_path(&key) {
+                let _ = std::fs::remove_file(path);
+            }
+            index.remove(&key);
+        }
+    }
+
+    pub fn clear(&self) {
+        self.invalidate(&[""]);
+    }
+}
+
+fn header(headers: &HeaderMap, name: reqwest::header::HeaderName) -> Option<String> {
+    headers.get(name).and_then(|value| value.to_str().ok()).map(str::to_string)
+}
+
+fn api_path(url: &reqwest::Url) -> String {
+    let path = url.path();
+    path.strip_prefix(API_PREFIX).unwrap_or(path).to_string()
+}
+
+impl Yuque {
+    /// Caches the responses of GET requests made through the client.
+    pub fn with_cache(mut self, options: CacheOptions) -> Result<Yuque> {
+        self.cache = Some(ResponseCache::new(options)?);
+        Ok(self)
+    }
+
+    /// Drops cached responses after a change, see `ResponseCache::invalidate`.
+    pub fn invalidate_cache<S: AsRef<str>>(&self, patterns: &[S]) {
+        if let Some(cache) = &self.cache {
+            cache.invalidate(patterns);
+        }
+    }
+
+    /// Sends a GET request and parses its JSON response, through the cache
+    /// when there is one. Stale responses are revalidated with their ETag or
+    /// Last-Modified.
+    pub async fn get_json<T: DeserializeOwned>(&self, req: RequestBuilder) -> Result<T> {
+        let mut req = req.build()?;
+        let Some(cache) = &self.cache else {
+            let resp = self.client.execute(req).await?;
+            return if resp.status().is_success() {
+                Ok(resp.json::<T>().await?)
+            } else {
+                Err(ApiError::from_response(resp).await.into())
+            };
+        };
+
+        let key = format!("{} {} {}", self.token_hash, req.method(), req.url());
+        let path = api_path(req.url());
+        let cached = cache.get(&key);
+        if let Some(entry) = &cached {
+            if now_millis().saturating_sub(entry.stored_at) < cache.ttl(&path).as_millis() as u64 {
+                return Ok(serde_json::from_str(&entry.body)?);
+            }
+            if let Some(etag) = &entry.etag {
+                req.headers_mut().insert(IF_NONE_MATCH, etag.parse()?);
+            }
+            if let Some(last_modified) = &entry.last_modified {
+                req.headers_mut().insert(IF_MODIFIED_SINCE, last_modified.parse()?);
+            }
+        }
+
+        let resp = self.client.execute(req).await?;
+        if let (StatusCode::NOT_MODIFIED, Some(mut entry)) = (resp.status(), cached) {
+            entry.stored_at = now_millis();
+            let value = serde_json::from_str(&entry.body)?;
+            cache.put(entry);
+            return Ok(value);
+        }
+        if !resp.status().is_success() {
+            return Err(ApiError::from_response(resp).await.into());
+        }
+        let etag = header(resp.headers(), ETAG);
+        let last_modified = header(resp.headers(), LAST_MODIFIED);
+        let body = resp.text().await?;
+        let value = serde_json::from_str(&body)?;
+        cache.put(CachedResponse { key, path, body, etag, last_modified, stored_at: now_millis() });
+        Ok(value)
+    }
+}
+
+/// Group lists and members, dropped after any group change.
+pub(crate) static GROUP_PATTERNS: [&str; 2] = ["/groups", "/users/*/groups"];
+
+/// The patterns to invalidate after docs of `repo` change.
+pub(crate) fn doc_patterns<T: Display>(repo: T) -> [String; 2] {
+    [format!("/repos/{}", repo), "/doc_versions".to_string()]
+}
+
+/// The patterns to invalidate after `repo` changes. Repo lists are dropped
+/// too since a repo can be listed under its owner by another name.
+pub(crate) fn repo_patterns<T: Display>(repo: T) -> [String; 2] {
+    [format!("/repos/{}", repo), "/*/*/repos".to_string()]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use serde_json::Value;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use crate::cache::{path_matches, CacheOptions};
+    use crate::yuque::Yuque;
+
+    /// Serves `{"data": <path>}` with an ETag, answering 304 to a matching
+    /// `If-None-Match`. Returns the base URL and the request lines seen.
+    async fn stand_in() -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}/api/v2", listener.local_addr().unwrap());
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut reader = BufReader::new(stream);
+                let (mut request, mut not_modified) = (String::new(), false);
+                reader.read_line(&mut request).await.unwrap();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).await.unwrap();
+                    not_modified |= line.eq_ignore_ascii_case("if-none-match: \"v1\"\r\n");
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let path = request.split_whitespace().nth(1).unwrap().to_string();
+                log.lock().unwrap().push(format!("{}{}", path, if not_modified { " revalidated" } else { "" }));
+                let reply = if not_modified {
+                    "HTTP/1.1 304 Not Modified\r\netag: \"v1\"\r\nconnection: close\r\n\r\n".to_string()
+                } else {
+                    let body = format!(r#"{{"data": "{}"}}"#, path);
+                    format!("HTTP/1.1 200 OK\r\netag: \"v1\"\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}", body.len(), body)
+                };
+                reader.get_mut().write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+        (base, seen)
+    }
+
+    #[test]
+    fn test_path_matches() {
+        assert!(path_matches("/repos/team/kb", "/repos/team/kb/docs/intro"));
+        assert!(path_matches("/*/*/repos", "/users/alice/repos"));
+        assert!(!path_matches("/repos/team/kb", "/repos/team/kb2"));
+        assert!(!path_matches("/repos/*/*/docs", "/repos/team/kb"));
+    }
+
+    #[tokio::test]
+    async fn test_response_cache() {
+        let (base, seen) = stand_in().await;
+        let dir = tempfile::tempdir().unwrap();
+        let options = CacheOptions {
+            capacity: 1,
+            dir: None,
+            ttls: vec![("/repos/*/*/docs".to_string(), Duration::ZERO)],
+            ..CacheOptions::default()
+        };
+        let yuque = Yuque::new("token").unwrap().with_cache(options.clone()).unwrap();
+        let get = |path: &str| yuque.get_json::<Value>(yuque.client.get(format!("{}{}", base, path)));
+
+        assert_eq!(get("/repos/team/kb").await.unwrap()["data"], "/api/v2/repos/team/kb");
+        get("/repos/team/kb").await.unwrap();
+        // A zero TTL revalidates, and the 304 is answered from the cache.
+        assert_eq!(get("/repos/team/kb/docs").await.unwrap()["data"], "/api/v2/repos/team/kb/docs");
+        assert_eq!(get("/repos/team/kb/docs").await.unwrap()["data"], "/api/v2/repos/team/kb/docs");
+        // The capacity of one evicted the repo.
+        get("/repos/team/kb").await.unwrap();
+        yuque.invalidate_cache(&["/repos/team/kb"]);
+        get("/repos/team/kb").await.unwrap();
+        assert_eq!(*seen.lock().unwrap(), vec![
+            "/api/v2/repos/team/kb",
+            "/api/v2/repos/team/kb/docs",
+            "/api/v2/repos/team/kb/docs revalidated",
+            "/api/v2/repos/team/kb",
+            "/api/v2/repos/team/kb",
+        ]);
+
+        // Responses on disk outlive the client.
+        let on_disk = CacheOptions { dir: Some(dir.path().to_path_buf()), ..options };
+        let yuque = Yuque::new("token").unwrap().with_cache(on_disk.clone()).unwrap();
+        yuque.get_json::<Value>(yuque.client.get(format!("{}/users/alice", base))).await.unwrap();
+        let yuque = Yuque::new("token").unwrap().with_cache(on_disk.clone()).unwrap();
+        yuque.get_json::<Value>(yuque.client.get(format!("{}/users/alice", base))).await.unwrap();
+        assert_eq!(seen.lock().unwrap().len(), 6);
+        // Another token does not see the response of the first.
+        let other = Yuque::new("other").unwrap().with_cache(on_disk.clone()).unwrap();
+        other.get_json::<Value>(other.client.get(format!("{}/users/alice", base))).await.unwrap();
+        assert_eq!(seen.lock().unwrap().len(), 7);
+
+        // Files that are not responses are kept.
+        std::fs::write(dir.path().join("notes.txt"), "keep").unwrap();
+        std::fs::write(dir.path().join(format!("{}.json", "0".repeat(64))), "not json").unwrap();
+        let yuque = Yuque::new("token").unwrap().with_cache(on_disk).unwrap();
+        yuque.invalidate_cache(&["/users"]);
+        let mut left: Vec<String> = std::fs::read_dir(dir.path()).unwrap().map(|file| file.unwrap().file_name().into_string().unwrap()).collect();
+        left.sort();
+        assert_eq!(left, vec![format!("{}.json", "0".repeat(64)), "notes.txt".to_string()]);
+    }
+}
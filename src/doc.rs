@@ -2,9 +2,11 @@ use std::{fmt::Display};
 use anyhow::Result;
 use serde::Serialize;
 
-use crate::{serializer::{DocSerializer, APIResponse}, yuque::Yuque};
+use crate::{cache::doc_patterns, serializer::{DocSerializer, DocVersionSerializer, APIResponse}, yuque::Yuque};
 
-#[derive(Debug)]
+static LIST_DOCS_PAGE_LIMIT: u8 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DocFormat {
     Markdown,
     Lake,
@@ -31,6 +33,8 @@ pub struct CreateDocRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub format: Option<DocFormat>,
     pub body: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public: Option<u8>,
 }
 
 #[derive(Debug, Serialize, Default)]
@@ -62,12 +66,19 @@ impl Yuque {
             query.push(("optional_properties", props));
         }
 
-        let resp = self.client.get(api).query(&query).send().await?;
-        if resp.status().is_success() {
-            let resp = resp.json::<APIResponse<Vec<DocSerializer>>>().await?;
-            Ok(resp.data)
-        } else {
-            Err(anyhow::anyhow!("{} {}", resp.status(), resp.text().await?))
+        let resp = self.get_json::<APIResponse<Vec<DocSerializer>>>(self.client.get(api).query(&query)).await?;
+        Ok(resp.data)
+    }
+
+    pub async fn list_all_docs<T: Display>(&self, repo: T, optional_properties: Option<Vec<String>>) -> Result<Vec<DocSerializer>> {
+        let mut docs = Vec::new();
+        loop {
+            let page = self.list_docs(&repo, Some(docs.len() as u32), Some(LIST_DOCS_PAGE_LIMIT), optional_properties.clone()).await?;
+            let count = page.len();
+            docs.extend(page);
+            if count < LIST_DOCS_PAGE_LIMIT as usize {
+                return Ok(docs);
+            }
         }
     }
 
@@ -82,19 +93,15 @@ impl Yuque {
             }
         }
 
-        let resp = self.client.get(api).query(&query).send().await?;
-        if resp.status().is_success() {
-            let resp = resp.json::<APIResponse<DocSerializer>>().await?;
-            Ok(resp.data)
-        } else {
-            Err(anyhow::anyhow!("{} {}", resp.status(), resp.text().await?))
-        }
+        let resp = self.get_json::<APIResponse<DocSerializer>>(self.client.get(api).query(&query)).await?;
+        Ok(resp.data)
     }
 
     pub async fn create_doc<T: Display>(&self, repo: T, req: &CreateDocRequest) -> Result<DocSerializer> {
         let endpoint = format!("/repos/{}/docs", repo);
         let api = Yuque::build_api(&endpoint, None);
         let resp = self.client.post(api).json(req).send().await?;
+        self.invalidate_cache(&doc_patterns(&repo));
         if resp.status().is_success() {
             let resp = resp.json::<APIResponse<DocSerializer>>().await?;
             Ok(resp.data)
@@ -107,6 +114,7 @@ impl Yuque {
         let endpoint = format!("/repos/{}/docs/{}", repo, doc_id);
         let api = Yuque::build_api(&endpoint, None);
         let resp = self.client.delete(api).send().await?;
+        self.invalidate_cache(&doc_patterns(&repo));
         if resp.status().is_success() {
             Ok(())
         } else {
@@ -125,6 +133,7 @@ impl Yuque {
             }
         }
         let resp = self.client.put(api).json(req).send().await?;
+        self.invalidate_cache(&doc_patterns(&repo));
         if resp.status().is_success() {
             let resp = resp.json::<APIResponse<DocSerializer>>().await?;
             Ok(resp.data)
@@ -132,6 +141,21 @@ impl Yuque {
             Err(anyhow::anyhow!("{} {}", resp.status(), resp.text().await?))
         }
     }
+
+    /// Saved versions of a doc, newest first.
+    pub async fn list_doc_versions(&self, doc_id: u32) -> Result<Vec<DocVersionSerializer>> {
+        let api = Yuque::build_api("/doc_versions", None);
+        let resp = self.get_json::<APIResponse<Vec<DocVersionSerializer>>>(self.client.get(api).query(&[("doc_id", doc_id)])).await?;
+        Ok(resp.data)
+    }
+
+    /// A version with its bodies.
+    pub async fn get_doc_version(&self, version_id: u32) -> Result<DocVersionSerializer> {
+        let endpoint = format!("/doc_versions/{}", version_id);
+        let api = Yuque::build_api(&endpoint, None);
+        let resp = self.get_json::<APIResponse<DocVersionSerializer>>(self.client.get(api)).await?;
+        Ok(resp.data)
+    }
 }
 
 #[cfg(test)]
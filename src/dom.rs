@@ -0,0 +1,314 @@
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Element {
+    /// Lowercased tag name.
+    pub tag: String,
+    pub attrs: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+static VOID_TAGS: &[&str] = &["area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr"];
+static RAW_TEXT_TAGS: &[&str] = &["script", "style", "textarea"];
+
+impl Element {
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+
+    /// Concatenated text of all descendants.
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+        collect_text(&self.children, &mut out);
+        out
+    }
+
+    pub fn child_elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(el) => Some(el),
+            Node::Text(_) => None,
+        })
+    }
+}
+
+fn collect_text(nodes: &[Node], out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Element(el) if el.tag == "br" => out.push('\n'),
+            Node::Element(el) => collect_text(&el.children, out),
+        }
+    }
+}
+
+/// Leniently parses the HTML-like markup of Lake and HTML bodies into a tree.
+/// Unclosed elements are closed at the end of their parent.
+pub fn parse(input: &str) -> Vec<Node> {
+    let mut stack: Vec<Element> = vec![Element::default()];
+    let mut rest = input;
+    while !rest.is_empty() {
+        let Some(lt) = rest.find('<') else {
+            push_text(&mut stack, rest);
+            break;
+        };
+        push_text(&mut stack, &rest[..lt]);
+        rest = &rest[lt..];
+
+        if let Some(after) = rest.strip_prefix("<!--") {
+            rest = after.find("-->").map(|end| &after[end + 3..]).unwrap_or("");
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest = rest.find('>').map(|end| &rest[end + 1..]).unwrap_or("");
+        } else if let Some(after) = rest.strip_prefix("</") {
+            let end = after.find('>').unwrap_or(after.len());
+            let tag = after[..end].trim().to_ascii_lowercase();
+            rest = after.get(end + 1..).unwrap_or("");
+            close(&mut stack, &tag);
+        } else if let Some((el, self_closing, after)) = parse_open_tag(rest) {
+            rest = after;
+            if RAW_TEXT_TAGS.contains(&el.tag.as_str()) && !self_closing {
+                let close_tag = format!("</{}", el.tag);
+                let end = find_ignore_case(rest, &close_tag).unwrap_or(rest.len());
+                let mut el = el;
+                if end > 0 {
+                    el.children.push(Node::Text(decode_entities(&rest[..end])));
+                }
+                rest = &rest[end..];
+                rest = rest.find('>').map(|i| &rest[i + 1..]).unwrap_or("");
+                stack.last_mut().unwrap().children.push(Node::Element(el));
+            } else if self_closing || VOID_TAGS.contains(&el.tag.as_str()) {
+                stack.last_mut().unwrap().children.push(Node::Element(el));
+            } else {
+                if el.tag == "li" {
+                    close_open_sibling(&mut stack, "li", &["ul", "ol"]);
+                }
+                stack.push(el);
+            }
+        } else {
+            push_text(&mut stack, "<");
+            rest = &rest[1..];
+        }
+    }
+    while stack.len() > 1 {
+        let el = stack.pop().unwrap();
+        stack.last_mut().unwrap().children.push(Node::Element(el));
+    }
+    stack.pop().unwrap().children
+}
+
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack.to_ascii_lowercase().find(&needle.to_ascii_lowercase())
+}
+
+fn push_text(stack: &mut [Element], text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    let children = &mut stack.last_mut().unwrap().children;
+    let text = decode_entities(text);
+    if let Some(Node::Text(prev)) = children.last_mut() {
+        prev.push_str(&text);
+    } else {
+        children.push(Node::Text(text));
+    }
+}
+
+fn close(stack: &mut Vec<Element>, tag: &str) {
+    let Some(pos) = stack.iter().rposition(|el| el.tag == tag) else {
+        return;
+    };
+    if pos == 0 {
+        return;
+    }
+    while stack.len() > pos {
+        let el = stack.pop().unwrap();
+        stack.last_mut().unwrap().children.push(Node::Element(el));
+    }
+}
+
+/// Closes an unterminated `tag` when a new one starts inside the same parent.
+fn close_open_sibling(stack: &mut Vec<Element>, tag: &str, parents: &[&str]) {
+    let Some(pos) = stack.iter().rposition(|el| el.tag == tag || parents.contains(&el.tag.as_str())) else {
+        return;
+    };
+    if stack[pos].tag == tag {
+        close(stack, tag);
+    }
+}
+
+fn parse_open_tag(input: &str) -> Option<(Element, bool, &str)> {
+    let body = &input[1..];
+    let name_len = body.find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == ':' || c == '_')).unwrap_or(body.len());
+    if name_len == 0 {
+        return None;
+    }
+    let mut el = Element {
+        tag: body[..name_len].to_ascii_lowercase(),
+        ..Element::default()
+    };
+    let mut rest = &body[name_len..];
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix("/>") {
+            return Some((el, true, after));
+        }
+        if let Some(after) = rest.strip_prefix('>') {
+            return Some((el, false, after));
+        }
+        if rest.is_empty() {
+            return Some((el, false, rest));
+        }
+        if let Some(after) = rest.strip_prefix('/') {
+            rest = after;
+            continue;
+        }
+        let name_len = match rest.find(|c: char| c.is_whitespace() || c == '=' || c == '>' || c == '/') {
+            Some(0) => rest.chars().next().map(char::len_utf8).unwrap_or(1),
+            Some(len) => len,
+            None => rest.len(),
+        };
+        let name = rest[..name_len].to_ascii_lowercase();
+        rest = rest[name_len..].trim_start();
+        let mut value = String::new();
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (raw, remaining) = match after.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let inner = &after[1..];
+                    let end = inner.find(quote).unwrap_or(inner.len());
+                    (&inner[..end], inner.get(end + 1..).unwrap_or(""))
+                }
+                _ => {
+                    let end = after.find(|c: char| c.is_whitespace() || c == '>').unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            value = decode_entities(raw);
+            rest = remaining;
+        }
+        el.attrs.push((name, value));
+    }
+}
+
+pub fn decode_entities(input: &str) -> String {
+    if !input.contains('&') {
+        return input.to_string();
+    }
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let end = rest.find(';').filter(|end| *end <= 10);
+        let decoded = end.and_then(|end| decode_entity(&rest[1..end]));
+        match (end, decoded) {
+            (Some(end), Some(c)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    if let Some(num) = name.strip_prefix('#') {
+        let code = match num.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => num.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        _ => return None,
+    })
+}
+
+/// Escapes text for use in element content or double-quoted attributes.
+pub fn escape(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Serializes nodes as well-formed XHTML, as EPUB content documents require.
+pub fn to_xhtml(nodes: &[Node]) -> String {
+    let mut out = String::new();
+    write_xhtml(nodes, &mut out);
+    out
+}
+
+fn write_xhtml(nodes: &[Node], out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(&escape(text)),
+            Node::Element(el) => {
+                out.push('<');
+                out.push_str(&el.tag);
+                for (name, value) in &el.attrs {
+                    if name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == ':') {
+                        out.push_str(&format!(r#" {}="{}""#, name, escape(value)));
+                    }
+                }
+                if VOID_TAGS.contains(&el.tag.as_str()) {
+                    out.push_str(" />");
+                    continue;
+                }
+                out.push('>');
+                write_xhtml(&el.children, out);
+                out.push_str(&format!("</{}>", el.tag));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dom::{decode_entities, escape, parse, to_xhtml, Node};
+
+    #[test]
+    fn test_parse() {
+        let nodes = parse("<!doctype lake><p id=a class='x y'>a &amp; <b>b</b><br>c</p><ul><li>1<li>2</ul><script>if (a < b) {}</script>");
+        assert_eq!(nodes.len(), 3);
+        let Node::Element(p) = &nodes[0] else { panic!() };
+        assert_eq!(p.attr("id"), Some("a"));
+        assert_eq!(p.text(), "a & b\nc");
+        let Node::Element(ul) = &nodes[1] else { panic!() };
+        assert_eq!(ul.child_elements().count(), 2);
+        let Node::Element(script) = &nodes[2] else { panic!() };
+        assert_eq!(script.text(), "if (a < b) {}");
+    }
+
+    #[test]
+    fn test_entities() {
+        assert_eq!(decode_entities("&lt;a&gt; &#39;&#x41;&nbsp;&unknown; &"), "<a> 'A\u{a0}&unknown; &");
+        assert_eq!(escape("<a href=\"x\">&</a>"), "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;");
+    }
+
+    #[test]
+    fn test_to_xhtml() {
+        assert_eq!(to_xhtml(&parse("<p>a<br>b &amp; <img src=x alt='\"'></p>")), "<p>a<br />b &amp; <img src=\"x\" alt=\"&quot;\" /></p>");
+    }
+}
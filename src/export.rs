@@ -0,0 +1,299 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::path::Path;
+use anyhow::Result;
+use pulldown_cmark::{Options, Parser};
+
+use crate::asset::{self, relative_link};
+use crate::backup::{BackupArchive, BackupOptions, BodyFormat};
+use crate::html;
+use crate::lake::{self, MarkdownOptions};
+use crate::links::PathMap;
+use crate::serializer::{BookSerializer, DocSerializer};
+use crate::toc::{parse_toc, TocItem, TocItemType};
+use crate::yuque::Yuque;
+
+mod docusaurus;
+mod epub;
+mod hugo;
+mod mdbook;
+mod single_html;
+
+pub use epub::write_epub;
+pub use single_html::single_html;
+
+/// Layouts of docs tools a repo can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    /// `book.toml` and `src/` with `SUMMARY.md`.
+    MdBook,
+    /// `docs/` with frontmatter and `sidebars.js`.
+    Docusaurus,
+    /// `content/` nested like the TOC, with weights, and `static/assets/`.
+    Hugo,
+}
+
+/// A doc with the bodies exporters work from.
+#[derive(Debug, Clone)]
+pub struct ExportDoc {
+    /// Doc metadata without bodies.
+    pub doc: DocSerializer,
+    pub markdown: String,
+    pub html: String,
+}
+
+/// One repo of a backup, ready to be written out in some layout.
+#[derive(Debug, Clone)]
+pub struct ExportRepo {
+    pub book: BookSerializer,
+    pub namespace: String,
+    /// The repo TOC, with docs missing from it appended at the top level.
+    pub toc: Vec<TocItem>,
+    /// Docs in TOC order.
+    pub docs: Vec<ExportDoc>,
+    /// Asset file name to content.
+    pub assets: BTreeMap<String, Vec<u8>>,
+    /// Remote asset URL to asset file name.
+    asset_names: HashMap<String, String>,
+}
+
+pub(crate) fn markdown_to_html(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS | Options::ENABLE_GFM;
+    let mut out = String::new();
+    pulldown_cmark::html::push_html(&mut out, Parser::new_ext(markdown, options));
+    out
+}
+
+/// Orders docs by the TOC, adding the ones it does not list.
+fn complete_toc(toc: Vec<TocItem>, docs: &[DocSerializer]) -> Vec<TocItem> {
+    let mut toc: Vec<TocItem> = toc.into_iter().filter(|item| item.typ != TocItemType::Doc || docs.iter().any(|doc| doc.slug == item.url)).collect();
+    for doc in docs {
+        if !toc.iter().any(|item| item.typ == TocItemType::Doc && item.url == doc.slug) {
+            toc.push(TocItem::doc(&doc.title, &doc.slug, 0));
+        }
+    }
+    toc
+}
+
+/// Writes `data` to `path` under `out`, creating directories as needed.
+pub(crate) fn write_file(out: &Path, path: &str, data: &[u8]) -> Result<()> {
+    let full = out.join(path);
+    if let Some(parent) = full.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(full, data)?;
+    Ok(())
+}
+
+/// YAML frontmatter block, fields in the given order.
+pub(crate) fn front_matter(fields: &[(&str, serde_yaml::Value)]) -> Result<String> {
+    let mut mapping = serde_yaml::Mapping::new();
+    for (key, value) in fields {
+        mapping.insert((*key).into(), value.clone());
+    }
+    Ok(format!("---\n{}---\n\n", serde_yaml::to_string(&mapping)?))
+}
+
+pub(crate) fn toml_string(value: &str) -> String {
+    serde_json::to_string(value).unwrap()
+}
+
+impl ExportRepo {
+    /// Picks the repo with `slug` out of a full backup.
+    pub fn from_backup(archive: &BackupArchive, slug: &str) -> Result<ExportRepo> {
+        if archive.manifest.base.is_some() {
+            return Err(anyhow::anyhow!("incremental backup, merge it into its base first"));
+        }
+        let repo = archive
+            .manifest
+            .repos
+            .iter()
+            .find(|repo| repo.book.slug == slug)
+            .ok_or_else(|| anyhow::anyhow!("repo {} not found in backup", slug))?;
+        let namespace = repo.book.namespace.clone().unwrap_or_else(|| format!("{}/{}", archive.manifest.owner, slug));
+
+        let mut docs = Vec::new();
+        let mut assets = BTreeMap::new();
+        let mut asset_names = HashMap::new();
+        for backup in &repo.docs {
+            let body = |format| archive.body(backup, format).filter(|body| !body.trim().is_empty());
+            let markdown = match (body(BodyFormat::Markdown), body(BodyFormat::Lake), body(BodyFormat::Html)) {
+                (Some(markdown), _, _) => markdown,
+                (None, Some(lake_body), _) => lake::to_markdown(&lake::parse(&lake_body), &MarkdownOptions::default()),
+                (None, None, Some(html_body)) => html::to_markdown(&html_body, &MarkdownOptions::default()),
+                (None, None, None) => String::new(),
+            };
+            let html = body(BodyFormat::Html).unwrap_or_else(|| markdown_to_html(&markdown));
+            for format in backup.bodies.keys() {
+                for asset in asset::extract(&archive.body(backup, *format).unwrap_or_default(), *format) {
+                    let Some(path) = archive.manifest.assets.get(&asset.url) else { continue };
+                    let Some(data) = archive.files.get(path) else { continue };
+                    let name = path.rsplit('/').next().unwrap_or(path).to_string();
+                    assets.insert(name.clone(), data.clone());
+                    asset_names.insert(asset.url, name);
+                }
+            }
+            docs.push(ExportDoc { doc: backup.doc.clone(), markdown, html });
+        }
+
+        let metas: Vec<DocSerializer> = docs.iter().map(|d| d.doc.clone()).collect();
+        let toc = complete_toc(parse_toc(repo.book.toc_yml.as_deref().unwrap_or_default())?, &metas);
+        let position = |slug: &str| toc.iter().position(|item| item.typ == TocItemType::Doc && item.url == slug);
+        docs.sort_by_key(|d| position(&d.doc.slug));
+        Ok(ExportRepo { book: repo.book.clone(), namespace, toc, docs, assets, asset_names })
+    }
+
+    pub fn doc(&self, slug: &str) -> Option<&ExportDoc> {
+        self.docs.iter().find(|d| d.doc.slug == slug)
+    }
+
+    /// Rewrites links to other exported docs and to assets for a body written
+    /// to `from`. Assets are expected under `assets_dir`; when it starts with
+    /// `/` links to them are absolute.
+    pub fn localize(&self, body: &str, from: &str, paths: &PathMap, assets_dir: &str) -> String {
+        self.localize_with(body, from, paths, |name, _| {
+            let path = format!("{}/{}", assets_dir.trim_end_matches('/'), name);
+            if assets_dir.starts_with('/') { path } else { relative_link(from, &path) }
+        })
+    }
+
+    /// Like `localize`, with `asset_link` making the link to an asset from
+    /// its file name and content.
+    pub fn localize_with<F: Fn(&str, &[u8]) -> String>(&self, body: &str, from: &str, paths: &PathMap, asset_link: F) -> String {
+        let body = paths.to_local(body, from);
+        let links: HashMap<String, String> = self
+            .asset_names
+            .iter()
+            .map(|(url, name)| (url.clone(), asset_link(name, &self.assets[name])))
+            .collect();
+        asset::rewrite(&body, &links)
+    }
+
+    pub fn write_assets(&self, dir: &Path) -> Result<()> {
+        for (name, data) in &self.assets {
+            write_file(dir, name, data)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a repo in the layout of a docs tool, ready to build.
+pub fn write_layout(repo: &ExportRepo, layout: Layout, out: &Path) -> Result<()> {
+    match layout {
+        Layout::MdBook => mdbook::write(repo, out),
+        Layout::Docusaurus => docusaurus::write(repo, out),
+        Layout::Hugo => hugo::write(repo, out),
+    }
+}
+
+impl Yuque {
+    /// Exports a live repo; use `ExportRepo::from_backup` to export offline.
+    pub async fn export_layout<T: Display>(&self, namespace: T, layout: Layout, out: &Path) -> Result<()> {
+        let namespace = namespace.to_string();
+        let archive = self.backup_one_repo(&namespace, &BackupOptions::default()).await?;
+        let slug = namespace.rsplit('/').next().unwrap_or_default();
+        write_layout(&ExportRepo::from_backup(&archive, slug)?, layout, out)
+    }
+
+    pub async fn export_epub<T: Display>(&self, namespace: T, path: &Path) -> Result<()> {
+        let namespace = namespace.to_string();
+        let archive = self.backup_one_repo(&namespace, &BackupOptions::default()).await?;
+        let slug = namespace.rsplit('/').next().unwrap_or_default();
+        write_epub(&ExportRepo::from_backup(&archive, slug)?, path)
+    }
+
+    pub async fn export_single_html<T: Display>(&self, namespace: T, path: &Path) -> Result<()> {
+        let namespace = namespace.to_string();
+        let archive = self.backup_one_repo(&namespace, &BackupOptions::default()).await?;
+        let slug = namespace.rsplit('/').next().unwrap_or_default();
+        std::fs::write(path, single_html(&ExportRepo::from_backup(&archive, slug)?))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::backup::{BackupArchive, RepoBackup};
+    use crate::export::{mdbook, write_layout, ExportRepo, Layout};
+    use crate::links::PathMap;
+    use crate::repo::ObjType;
+    use crate::serializer::{BookSerializer, DocSerializer};
+
+    /// A backup with one repo, `kb`, holding an intro and a guide doc.
+    pub(crate) fn sample_archive() -> BackupArchive {
+        let mut archive = BackupArchive::new(ObjType::User, "me");
+        archive.add_asset("https://cdn.nlark.com/yuque/0/arch.png", b"png".to_vec());
+        let intro = archive.add_doc("kb", DocSerializer {
+            slug: "intro".to_string(),
+            title: "Intro".to_string(),
+            content_updated_at: "2024-01-01T00:00:00.000Z".to_string(),
+            body: Some("# Intro\n\nSee the [guide](https://www.yuque.com/me/kb/guide#setup).\n\n![arch](https://cdn.nlark.com/yuque/0/arch.png)\n".to_string()),
+            ..DocSerializer::default()
+        });
+        let guide = archive.add_doc("kb", DocSerializer {
+            slug: "guide".to_string(),
+            title: "Guide".to_string(),
+            content_updated_at: "2024-01-02T00:00:00.000Z".to_string(),
+            body_lake: Some("<h2>Setup</h2><p>Run <code>make</code>.</p>".to_string()),
+            ..DocSerializer::default()
+        });
+        let book = BookSerializer {
+            slug: "kb".to_string(),
+            name: "Knowledge Base".to_string(),
+            namespace: Some("me/kb".to_string()),
+            toc_yml: Some("- type: META\n  count: 2\n- type: TITLE\n  title: Start\n  level: 0\n- type: DOC\n  title: Guide\n  url: guide\n  level: 1\n".to_string()),
+            ..BookSerializer::default()
+        };
+        archive.manifest.repos.push(RepoBackup { book, docs: vec![intro, guide], deleted_docs: Vec::new() });
+        archive
+    }
+
+    #[test]
+    fn test_from_backup() {
+        let repo = ExportRepo::from_backup(&sample_archive(), "kb").unwrap();
+        let slugs: Vec<_> = repo.docs.iter().map(|d| d.doc.slug.as_str()).collect();
+        assert_eq!(slugs, vec!["guide", "intro"]);
+        assert_eq!(repo.toc.len(), 3);
+        assert!(repo.doc("guide").unwrap().markdown.starts_with("## Setup"));
+        assert!(repo.doc("intro").unwrap().html.contains("<h1>Intro</h1>"));
+
+        let mut paths = PathMap::default();
+        paths.insert("me/kb", "guide", "docs/guide.md");
+        let body = repo.localize(&repo.doc("intro").unwrap().markdown, "docs/intro.md", &paths, "assets");
+        assert!(body.contains("[guide](guide.md#setup)"));
+        assert!(body.contains("![arch](../assets/"));
+        assert!(ExportRepo::from_backup(&sample_archive(), "missing").is_err());
+    }
+
+    #[test]
+    fn test_layouts() {
+        let repo = ExportRepo::from_backup(&sample_archive(), "kb").unwrap();
+        let read = |dir: &tempfile::TempDir, path: &str| std::fs::read_to_string(dir.path().join(path)).unwrap();
+
+        let mdbook = tempfile::tempdir().unwrap();
+        write_layout(&repo, Layout::MdBook, mdbook.path()).unwrap();
+        assert_eq!(read(&mdbook, "src/SUMMARY.md"), "# Summary\n\n\n# Start\n\n- [Guide](guide.md)\n- [Intro](intro.md)\n");
+        assert!(read(&mdbook, "src/intro.md").contains("[guide](guide.md#setup)"));
+        let mut bracketed = repo.clone();
+        bracketed.toc[1].title = "[WIP] Guide".to_string();
+        assert!(mdbook::summary(&bracketed).contains("\n- [\\[WIP\\] Guide](guide.md)\n"));
+        assert!(read(&mdbook, "book.toml").contains("title = \"Knowledge Base\""));
+
+        let docusaurus = tempfile::tempdir().unwrap();
+        write_layout(&repo, Layout::Docusaurus, docusaurus.path()).unwrap();
+        assert!(read(&docusaurus, "docs/intro.md").starts_with("---\nid: intro\ntitle: Intro\nsidebar_position: 2\n---\n\n# Intro"));
+        let sidebars = read(&docusaurus, "sidebars.js");
+        let items = &sidebars[sidebars.find("docs: ").unwrap() + 6..sidebars.rfind(",\n};").unwrap()];
+        let items: serde_json::Value = serde_json::from_str(items).unwrap();
+        assert_eq!(items, serde_json::json!([{"type": "category", "label": "Start", "items": ["guide"]}, "intro"]));
+
+        let hugo = tempfile::tempdir().unwrap();
+        write_layout(&repo, Layout::Hugo, hugo.path()).unwrap();
+        assert!(read(&hugo, "content/start/_index.md").contains("weight: 1"));
+        assert!(read(&hugo, "content/start/guide.md").contains("weight: 2"));
+        let intro = read(&hugo, "content/intro.md");
+        assert!(intro.contains(r#"[guide]({{< relref "start/guide.md#setup" >}})"#));
+        assert!(intro.contains("![arch](/assets/"));
+        assert_eq!(std::fs::read_dir(hugo.path().join("static/assets")).unwrap().count(), 1);
+    }
+}
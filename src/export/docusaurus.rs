@@ -0,0 +1,55 @@
+use std::path::Path;
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use crate::export::{front_matter, write_file, ExportRepo};
+use crate::links::PathMap;
+use crate::toc::{toc_tree, TocItemType, TocNode};
+
+fn sidebar_items(nodes: &[TocNode]) -> Vec<Value> {
+    nodes
+        .iter()
+        .filter_map(|node| {
+            let item = &node.item;
+            let children = sidebar_items(&node.children);
+            Some(match item.typ {
+                TocItemType::Doc if children.is_empty() => json!(item.url),
+                TocItemType::Doc => json!({ "type": "category", "label": item.title, "link": { "type": "doc", "id": item.url }, "items": children }),
+                TocItemType::Title => json!({ "type": "category", "label": item.title, "items": children }),
+                TocItemType::Link => json!({ "type": "link", "label": item.title, "href": item.url }),
+                _ => return None,
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn sidebars(repo: &ExportRepo) -> Result<String> {
+    let items = serde_json::to_string_pretty(&sidebar_items(&toc_tree(&repo.toc)))?;
+    Ok(format!(
+        "// @ts-check\n\n/** @type {{import('@docusaurus/plugin-content-docs').SidebarsConfig}} */\nconst sidebars = {{\n  docs: {},\n}};\n\nmodule.exports = sidebars;\n",
+        items.replace('\n', "\n  "),
+    ))
+}
+
+pub(crate) fn write(repo: &ExportRepo, out: &Path) -> Result<()> {
+    let docs_dir = out.join("docs");
+    let mut paths = PathMap::default();
+    for doc in &repo.docs {
+        paths.insert(&repo.namespace, &doc.doc.slug, &format!("{}.md", doc.doc.slug));
+    }
+    for (position, doc) in repo.docs.iter().enumerate() {
+        let path = format!("{}.md", doc.doc.slug);
+        let mut fields = vec![
+            ("id", doc.doc.slug.clone().into()),
+            ("title", doc.doc.title.clone().into()),
+            ("sidebar_position", (position as u64 + 1).into()),
+        ];
+        if let Some(description) = doc.doc.description.as_deref().filter(|d| !d.is_empty()) {
+            fields.push(("description", description.into()));
+        }
+        let body = format!("{}{}", front_matter(&fields)?, repo.localize(&doc.markdown, &path, &paths, "assets"));
+        write_file(&docs_dir, &path, body.as_bytes())?;
+    }
+    write_file(out, "sidebars.js", sidebars(repo)?.as_bytes())?;
+    repo.write_assets(&docs_dir.join("assets"))
+}
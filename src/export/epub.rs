@@ -0,0 +1,166 @@
+use std::io::{Cursor, Write};
+use std::path::Path;
+use anyhow::Result;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::asset::media_type;
+use crate::dom::{self, escape};
+use crate::export::ExportRepo;
+use crate::html::sanitize;
+use crate::links::PathMap;
+use crate::toc::{toc_tree, TocItemType, TocNode};
+
+static CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+static EPUB_STYLE: &str = "body{font-family:sans-serif;line-height:1.6}img{max-width:100%}pre{white-space:pre-wrap;background:#f6f8fa;padding:.5em}\
+table{border-collapse:collapse}td,th{border:1px solid #999;padding:2px 6px}";
+
+fn chapter_path(slug: &str) -> String {
+    format!("{}.xhtml", slug)
+}
+
+fn xhtml_page(title: &str, body: &str, nav: bool) -> String {
+    let epub_ns = if nav { r#" xmlns:epub="http://www.idpf.org/2007/ops""# } else { "" };
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n<html xmlns=\"http://www.w3.org/1999/xhtml\"{}>\n<head>\n<title>{}</title>\n<link rel=\"stylesheet\" type=\"text/css\" href=\"style.css\" />\n</head>\n<body>\n{}\n</body>\n</html>\n",
+        epub_ns,
+        escape(title),
+        body,
+    )
+}
+
+/// The EPUB 3 navigation list. Titles without docs under them are left out,
+/// since a `span` entry needs a nested list.
+fn nav_list(nodes: &[TocNode], out: &mut String) {
+    out.push_str("<ol>");
+    for node in nodes {
+        let item = &node.item;
+        let entry = match item.typ {
+            TocItemType::Doc => format!(r#"<a href="{}">{}</a>"#, escape(&chapter_path(&item.url)), escape(&item.title)),
+            TocItemType::Title if !node.children.is_empty() => format!("<span>{}</span>", escape(&item.title)),
+            _ => continue,
+        };
+        out.push_str("<li>");
+        out.push_str(&entry);
+        if !node.children.is_empty() {
+            nav_list(&node.children, out);
+        }
+        out.push_str("</li>");
+    }
+    out.push_str("</ol>");
+}
+
+/// `dcterms:modified` wants whole seconds in UTC.
+fn modified(repo: &ExportRepo) -> String {
+    let latest = repo.docs.iter().map(|d| d.doc.content_updated_at.as_str()).max().unwrap_or_default();
+    chrono::DateTime::parse_from_rfc3339(latest)
+        .map(|t| t.with_timezone(&chrono::Utc).format("%Y-%m-%dT%H:%M:%SZ").to_string())
+        .unwrap_or_else(|_| "1970-01-01T00:00:00Z".to_string())
+}
+
+fn package(repo: &ExportRepo) -> String {
+    let mut manifest = String::from(
+        "    <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n    <item id=\"style\" href=\"style.css\" media-type=\"text/css\"/>\n",
+    );
+    let mut spine = String::new();
+    for (i, doc) in repo.docs.iter().enumerate() {
+        manifest.push_str(&format!("    <item id=\"doc{}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n", i, escape(&chapter_path(&doc.doc.slug))));
+        spine.push_str(&format!("    <itemref idref=\"doc{}\"/>\n", i));
+    }
+    for (i, name) in repo.assets.keys().enumerate() {
+        manifest.push_str(&format!("    <item id=\"asset{}\" href=\"images/{}\" media-type=\"{}\"/>\n", i, escape(name), media_type(name)));
+    }
+    let language = if repo.docs.iter().any(|d| d.doc.title.chars().any(|c| ('\u{4e00}'..='\u{9fff}').contains(&c))) { "zh" } else { "en" };
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\">\n  <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n    <dc:identifier id=\"book-id\">urn:yuque:{}</dc:identifier>\n    <dc:title>{}</dc:title>\n    <dc:language>{}</dc:language>\n    <meta property=\"dcterms:modified\">{}</meta>\n  </metadata>\n  <manifest>\n{}  </manifest>\n  <spine>\n{}  </spine>\n</package>\n",
+        escape(&repo.namespace),
+        escape(&repo.book.name),
+        language,
+        modified(repo),
+        manifest,
+        spine,
+    )
+}
+
+/// Builds an EPUB 3 with one chapter per doc in TOC order and the images
+/// of the backup embedded.
+pub fn write_epub(repo: &ExportRepo, path: &Path) -> Result<()> {
+    let mut paths = PathMap::default();
+    for doc in &repo.docs {
+        paths.insert(&repo.namespace, &doc.doc.slug, &chapter_path(&doc.doc.slug));
+    }
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    // The mimetype has to come first and stay uncompressed.
+    zip.start_file("mimetype", SimpleFileOptions::default().compression_method(CompressionMethod::Stored))?;
+    zip.write_all(b"application/epub+zip")?;
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut add = |name: &str, data: &[u8]| -> Result<()> {
+        zip.start_file(name, deflated)?;
+        zip.write_all(data)?;
+        Ok(())
+    };
+    add("META-INF/container.xml", CONTAINER_XML.as_bytes())?;
+    add("OEBPS/content.opf", package(repo).as_bytes())?;
+    add("OEBPS/style.css", EPUB_STYLE.as_bytes())?;
+    let mut nav = String::from(r#"<nav epub:type="toc" id="toc"><h1>"#);
+    nav.push_str(&escape(&repo.book.name));
+    nav.push_str("</h1>");
+    nav_list(&toc_tree(&repo.toc), &mut nav);
+    nav.push_str("</nav>");
+    add("OEBPS/nav.xhtml", xhtml_page(&repo.book.name, &nav, true).as_bytes())?;
+    for doc in &repo.docs {
+        let chapter = chapter_path(&doc.doc.slug);
+        let html = sanitize(&repo.localize(&doc.html, &chapter, &paths, "images"));
+        let body = format!("<h1>{}</h1>\n{}", escape(&doc.doc.title), dom::to_xhtml(&dom::parse(&html)));
+        add(&format!("OEBPS/{}", chapter), xhtml_page(&doc.doc.title, &body, false).as_bytes())?;
+    }
+    for (name, data) in &repo.assets {
+        add(&format!("OEBPS/images/{}", name), data)?;
+    }
+    std::fs::write(path, zip.finish()?.into_inner())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use crate::export::tests::sample_archive;
+    use crate::export::{write_epub, ExportRepo};
+
+    #[test]
+    fn test_write_epub() {
+        let repo = ExportRepo::from_backup(&sample_archive(), "kb").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("kb.epub");
+        write_epub(&repo, &path).unwrap();
+
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(zip.by_index(0).unwrap().name(), "mimetype");
+        let read = |zip: &mut zip::ZipArchive<std::fs::File>, name: &str| {
+            let mut text = String::new();
+            zip.by_name(name).unwrap().read_to_string(&mut text).unwrap();
+            text
+        };
+        let opf = read(&mut zip, "OEBPS/content.opf");
+        assert!(opf.contains("<meta property=\"dcterms:modified\">2024-01-02T00:00:00Z</meta>"));
+        assert!(opf.find("idref=\"doc0\"").unwrap() < opf.find("idref=\"doc1\"").unwrap());
+        assert!(opf.contains("media-type=\"image/png\""));
+        let nav = read(&mut zip, "OEBPS/nav.xhtml");
+        assert!(nav.contains("<li><span>Start</span><ol><li><a href=\"guide.xhtml\">Guide</a></li></ol></li><li><a href=\"intro.xhtml\">Intro</a></li>"));
+        let intro = read(&mut zip, "OEBPS/intro.xhtml");
+        assert!(intro.contains("href=\"guide.xhtml#setup\""));
+        assert!(intro.contains("src=\"images/"));
+        assert_eq!(zip.file_names().filter(|name| name.starts_with("OEBPS/images/")).count(), 1);
+
+        let again = dir.path().join("again.epub");
+        write_epub(&repo, &again).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), std::fs::read(&again).unwrap());
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::LazyLock;
+use anyhow::Result;
+use regex::{Captures, Regex};
+
+use crate::export::{front_matter, write_file, ExportRepo};
+use crate::links::PathMap;
+use crate::toc::{toc_tree, TocItemType, TocNode};
+use crate::utils::{slugify, unique_slug};
+
+static MD_LINK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"\]\(([^)\s:]+\.md(?:#[^)\s]*)?)\)"#).unwrap());
+
+/// A content file, with the TOC position as weight.
+struct Page {
+    path: String,
+    title: String,
+    weight: u64,
+    /// Doc slug, or none for a section made from a TOC title.
+    slug: Option<String>,
+}
+
+/// Nests pages like the TOC: items with children become sections.
+fn pages(nodes: &[TocNode], dir: &str, taken: &mut HashSet<String>, weight: &mut u64, out: &mut Vec<Page>) {
+    for node in nodes {
+        let item = &node.item;
+        let name = match item.typ {
+            TocItemType::Doc => item.url.clone(),
+            TocItemType::Title => {
+                let name = slugify(&item.title);
+                if name.is_empty() { format!("section-{}", *weight + 1) } else { name }
+            }
+            _ => continue,
+        };
+        let name = unique_slug(&format!("{}{}", dir, name), taken);
+        taken.insert(name.clone());
+        *weight += 1;
+        let slug = (item.typ == TocItemType::Doc).then(|| item.url.clone());
+        let path = if node.children.is_empty() && slug.is_some() { format!("{}.md", name) } else { format!("{}/_index.md", name) };
+        out.push(Page { path, title: item.title.clone(), weight: *weight, slug });
+        pages(&node.children, &format!("{}/", name), taken, weight, out);
+    }
+}
+
+/// Relative links between content files only resolve through `relref`.
+fn relref(markdown: &str) -> String {
+    MD_LINK
+        .replace_all(markdown, |cap: &Captures| format!("]({{{{< relref \"{}\" >}}}})", &cap[1]))
+        .into_owned()
+}
+
+pub(crate) fn write(repo: &ExportRepo, out: &Path) -> Result<()> {
+    let content = out.join("content");
+    let mut list = Vec::new();
+    pages(&toc_tree(&repo.toc), "", &mut HashSet::new(), &mut 0, &mut list);
+    let mut paths = PathMap::default();
+    for page in &list {
+        if let Some(slug) = &page.slug {
+            paths.insert(&repo.namespace, slug, &page.path);
+        }
+    }
+    for page in &list {
+        let mut fields = vec![("title", page.title.clone().into()), ("weight", page.weight.into())];
+        let doc = page.slug.as_deref().and_then(|slug| repo.doc(slug));
+        let body = match doc {
+            Some(doc) => {
+                fields.push(("date", doc.doc.content_updated_at.clone().into()));
+                relref(&repo.localize(&doc.markdown, &page.path, &paths, "/assets"))
+            }
+            None => String::new(),
+        };
+        write_file(&content, &page.path, format!("{}{}", front_matter(&fields)?, body).as_bytes())?;
+    }
+    let index = vec![("title", repo.book.name.clone().into())];
+    write_file(&content, "_index.md", front_matter(&index)?.as_bytes())?;
+    repo.write_assets(&out.join("static").join("assets"))
+}
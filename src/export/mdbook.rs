@@ -0,0 +1,47 @@
+use std::path::Path;
+use anyhow::Result;
+
+use crate::export::{toml_string, write_file, ExportRepo};
+use crate::links::PathMap;
+use crate::toc::{escape_title, TocItemType};
+
+/// `SUMMARY.md` for the TOC. Top-level titles become part titles, nested
+/// ones draft chapters. mdBook has no place for external links.
+pub(crate) fn summary(repo: &ExportRepo) -> String {
+    let mut out = String::from("# Summary\n\n");
+    // The children of a part title are its top-level chapters.
+    let mut part = false;
+    for item in &repo.toc {
+        if item.level == 0 {
+            part = item.typ == TocItemType::Title;
+        }
+        let indent = "    ".repeat(item.level.saturating_sub(part as u32) as usize);
+        let title = escape_title(&item.title);
+        match item.typ {
+            TocItemType::Doc => out.push_str(&format!("{}- [{}]({}.md)\n", indent, title, item.url)),
+            TocItemType::Title if item.level == 0 => out.push_str(&format!("\n# {}\n\n", title)),
+            TocItemType::Title => out.push_str(&format!("{}- [{}]()\n", indent, title)),
+            _ => {}
+        }
+    }
+    out
+}
+
+pub(crate) fn write(repo: &ExportRepo, out: &Path) -> Result<()> {
+    let src = out.join("src");
+    let mut paths = PathMap::default();
+    for doc in &repo.docs {
+        paths.insert(&repo.namespace, &doc.doc.slug, &format!("{}.md", doc.doc.slug));
+    }
+    for doc in &repo.docs {
+        let path = format!("{}.md", doc.doc.slug);
+        write_file(&src, &path, repo.localize(&doc.markdown, &path, &paths, "assets").as_bytes())?;
+    }
+    write_file(&src, "SUMMARY.md", summary(repo).as_bytes())?;
+    let mut book = format!("[book]\ntitle = {}\nsrc = \"src\"\n", toml_string(&repo.book.name));
+    if let Some(description) = repo.book.description.as_deref().filter(|d| !d.is_empty()) {
+        book.push_str(&format!("description = {}\n", toml_string(description)));
+    }
+    write_file(out, "book.toml", book.as_bytes())?;
+    repo.write_assets(&src.join("assets"))
+}
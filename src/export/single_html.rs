@@ -0,0 +1,79 @@
+use std::sync::LazyLock;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use regex::{Captures, Regex};
+
+use crate::asset::media_type;
+use crate::dom::escape;
+use crate::export::ExportRepo;
+use crate::html::sanitize;
+use crate::links::PathMap;
+use crate::site::{render_nav, STYLE};
+use crate::toc::toc_tree;
+
+static INLINE_DIR: &str = "yuque-inline";
+
+static SECTION_LINK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"href="([^"/#:]+)\.html(#[^"]*)?""#).unwrap());
+
+/// Images get a placeholder path that survives sanitizing and are inlined
+/// afterwards, since the sanitizer drops `data:` URLs.
+static INLINE_IMAGE: LazyLock<Regex> = LazyLock::new(|| Regex::new(&format!(r#"src="{}/([^"]+)""#, INLINE_DIR)).unwrap());
+
+fn section_id(slug: &str) -> String {
+    format!("doc-{}", slug)
+}
+
+/// Renders the whole repo as one self-contained HTML file: CSS inlined,
+/// images as `data:` URIs and links between docs pointing at sections.
+pub fn single_html(repo: &ExportRepo) -> String {
+    let mut paths = PathMap::default();
+    for doc in &repo.docs {
+        paths.insert(&repo.namespace, &doc.doc.slug, &format!("{}.html", doc.doc.slug));
+    }
+    let mut sections = String::new();
+    for doc in &repo.docs {
+        let html = repo.localize_with(&doc.html, "index.html", &paths, |name, _| format!("{}/{}", INLINE_DIR, name));
+        let html = SECTION_LINK.replace_all(&html, |cap: &Captures| match (repo.doc(&cap[1]), cap.get(2)) {
+            (Some(_), Some(anchor)) => format!(r#"href="{}""#, anchor.as_str()),
+            (Some(_), None) => format!(r##"href="#{}""##, section_id(&cap[1])),
+            (None, _) => cap[0].to_string(),
+        });
+        let html = sanitize(&html);
+        let html = INLINE_IMAGE.replace_all(&html, |cap: &Captures| match repo.assets.get(&cap[1]) {
+            Some(data) => format!(r#"src="data:{};base64,{}""#, media_type(&cap[1]), STANDARD.encode(data)),
+            None => cap[0].to_string(),
+        });
+        sections.push_str(&format!(
+            "<section id=\"{}\">\n<h1>{}</h1>\n{}\n</section>\n",
+            escape(&section_id(&doc.doc.slug)),
+            escape(&doc.doc.title),
+            html,
+        ));
+    }
+    let mut nav = String::new();
+    render_nav(&toc_tree(&repo.toc), None, &|slug| format!("#{}", section_id(slug)), &mut nav);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>{}</title>\n<style>{}section{{border-bottom:1px solid #e8e8e8;padding-bottom:24px}}</style>\n</head>\n<body>\n<nav><strong>{}</strong>\n{}\n</nav>\n<main>\n{}</main>\n</body>\n</html>\n",
+        escape(&repo.book.name),
+        STYLE,
+        escape(&repo.book.name),
+        nav,
+        sections,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::tests::sample_archive;
+    use crate::export::{single_html, ExportRepo};
+
+    #[test]
+    fn test_single_html() {
+        let repo = ExportRepo::from_backup(&sample_archive(), "kb").unwrap();
+        let html = single_html(&repo);
+        assert!(html.contains("<section id=\"doc-intro\">"));
+        assert!(html.contains(r##"<a href="#doc-guide">Guide</a>"##));
+        assert!(html.contains(r##"href="#setup""##));
+        assert!(html.contains("src=\"data:image/png;base64,cG5n\""));
+        assert!(!html.contains("yuque-inline"));
+    }
+}
@@ -0,0 +1,332 @@
+use std::fmt::Display;
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset};
+
+use crate::dom::escape;
+use crate::links::DEFAULT_WEB_BASE;
+use crate::repo::ObjType;
+use crate::serializer::DocSerializer;
+use crate::yuque::Yuque;
+
+static DEFAULT_LIMIT: usize = 20;
+static GENERATOR: &str = "yuque-rs";
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FeedFormat {
+    #[default]
+    Atom,
+    Rss,
+}
+
+impl FeedFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FeedOptions {
+    pub format: FeedFormat,
+    /// Number of entries, defaults to 20.
+    pub limit: Option<usize>,
+}
+
+/// A doc of the feed and the repo it belongs to.
+#[derive(Debug, Clone)]
+pub struct FeedDoc {
+    pub namespace: String,
+    pub doc: DocSerializer,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FeedMeta {
+    pub title: String,
+    pub link: String,
+    pub description: Option<String>,
+}
+
+struct Entry<'a> {
+    title: &'a str,
+    link: String,
+    updated: DateTime<FixedOffset>,
+    published: Option<DateTime<FixedOffset>>,
+    author: Option<&'a str>,
+    summary: Option<&'a str>,
+}
+
+fn parse_time(time: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(time).ok()
+}
+
+fn entry(feed_doc: &FeedDoc) -> Option<Entry<'_>> {
+    let doc = &feed_doc.doc;
+    let summary = [&doc.custom_description, &doc.description].into_iter().flatten().map(|s| s.trim()).find(|s| !s.is_empty());
+    Some(Entry {
+        title: &doc.title,
+        link: format!("{}/{}/{}", DEFAULT_WEB_BASE, feed_doc.namespace, doc.slug),
+        updated: parse_time(&doc.content_updated_at)?,
+        published: doc.first_published_at.as_deref().or(doc.published_at.as_deref()).and_then(parse_time),
+        author: doc.last_editor.as_ref().or(doc.creator.as_ref()).map(|user| user.name.as_str()),
+        summary,
+    })
+}
+
+fn atom(meta: &FeedMeta, entries: &[Entry]) -> String {
+    let updated = entries.first().map(|entry| entry.updated.to_rfc3339()).unwrap_or_else(|| "1970-01-01T00:00:00+00:00".to_string());
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    out.push_str(&format!("  <title>{}</title>\n", escape(&meta.title)));
+    if let Some(description) = &meta.description {
+        out.push_str(&format!("  <subtitle>{}</subtitle>\n", escape(description)));
+    }
+    out.push_str(&format!("  <link href=\"{}\"/>\n  <id>{}</id>\n  <updated>{}</updated>\n", escape(&meta.link), escape(&meta.link), updated));
+    out.push_str(&format!("  <generator>{}</generator>\n", GENERATOR));
+    for entry in entries {
+        out.push_str(&format!("  <entry>\n    <title>{}</title>\n", escape(entry.title)));
+        out.push_str(&format!("    <link href=\"{}\"/>\n    <id>{}</id>\n", escape(&entry.link), escape(&entry.link)));
+        out.push_str(&format!("    <updated>{}</updated>\n", entry.updated.to_rfc3339()));
+        if let Some(published) = entry.published {
+            out.push_str(&format!("    <published>{}</published>\n", published.to_rfc3339()));
+        }
+        if let Some(author) = entry.author {
+            out.push_str(&format!("    <author><name>{}</name></author>\n", escape(author)));
+        }
+        if let Some(summary) = entry.summary {
+            out.push_str(&format!("    <summary>{}</summary>\n", escape(summary)));
+        }
+        out.push_str("  </entry>\n");
+    }
+    out.push_str("</feed>\n");
+    out
+}
+
+fn rss(meta: &FeedMeta, entries: &[Entry]) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<rss version=\"2.0\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n<channel>\n");
+    out.push_str(&format!("  <title>{}</title>\n  <link>{}</link>\n", escape(&meta.title), escape(&meta.link)));
+    out.push_str(&format!("  <description>{}</description>\n", escape(meta.description.as_deref().unwrap_or(&meta.title))));
+    if let Some(entry) = entries.first() {
+        out.push_str(&format!("  <lastBuildDate>{}</lastBuildDate>\n", entry.updated.to_rfc2822()));
+    }
+    out.push_str(&format!("  <generator>{}</generator>\n", GENERATOR));
+    for entry in entries {
+        out.push_str(&format!("  <item>\n    <title>{}</title>\n", escape(entry.title)));
+        out.push_str(&format!("    <link>{}</link>\n    <guid isPermaLink=\"true\">{}</guid>\n", escape(&entry.link), escape(&entry.link)));
+        out.push_str(&format!("    <pubDate>{}</pubDate>\n", entry.updated.to_rfc2822()));
+        if let Some(author) = entry.author {
+            out.push_str(&format!("    <dc:creator>{}</dc:creator>\n", escape(author)));
+        }
+        if let Some(summary) = entry.summary {
+            out.push_str(&format!("    <description>{}</description>\n", escape(summary)));
+        }
+        out.push_str("  </item>\n");
+    }
+    out.push_str("</channel>\n</rss>\n");
+    out
+}
+
+fn published(feed_doc: &FeedDoc) -> bool {
+    feed_doc.doc.status == 1 && feed_doc.doc.published_at.is_some()
+}
+
+/// Renders the most recently updated docs, by `content_updated_at`. Drafts
+/// and docs without a valid update time are left out.
+pub fn build_feed(meta: &FeedMeta, docs: &[FeedDoc], options: &FeedOptions) -> String {
+    let mut entries: Vec<Entry> = docs.iter().filter(|feed_doc| published(feed_doc)).filter_map(entry).collect();
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.updated));
+    entries.truncate(options.limit.unwrap_or(DEFAULT_LIMIT));
+    match options.format {
+        FeedFormat::Atom => atom(meta, &entries),
+        FeedFormat::Rss => rss(meta, &entries),
+    }
+}
+
+impl Yuque {
+    /// A feed of the recently updated docs of a repo.
+    pub async fn repo_feed<T: Display>(&self, repo: T, options: &FeedOptions) -> Result<String> {
+        let namespace = repo.to_string();
+        let book = self.get_repo(&namespace).await?.data;
+        let docs = self.list_all_docs(&namespace, None).await?;
+        let meta = FeedMeta {
+            title: book.name,
+            link: format!("{}/{}", DEFAULT_WEB_BASE, namespace),
+            description: book.description.filter(|description| !description.is_empty()),
+        };
+        let docs: Vec<FeedDoc> = docs.into_iter().map(|doc| FeedDoc { namespace: namespace.clone(), doc }).collect();
+        Ok(build_feed(&meta, &docs, options))
+    }
+
+    /// A feed of the recently updated docs across the repos of a user or
+    /// group.
+    pub async fn owner_feed<T: Display>(&self, obj_type: ObjType, obj: T, options: &FeedOptions) -> Result<String> {
+        let login = obj.to_string();
+        let mut docs = Vec::new();
+        for book in self.list_all_repos(obj_type, &login, None).await? {
+            let namespace = book.namespace.unwrap_or_else(|| format!("{}/{}", login, book.slug));
+            for doc in self.list_all_docs(&namespace, None).await? {
+                docs.push(FeedDoc { namespace: namespace.clone(), doc });
+            }
+        }
+        let meta = FeedMeta { title: login.clone(), link: format!("{}/{}", DEFAULT_WEB_BASE, login), description: None };
+        Ok(build_feed(&meta, &docs, options))
+    }
+}
+
+#[cfg(feature = "feed-server")]
+mod server {
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+    use std::num::NonZeroUsize;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use anyhow::Result;
+    use lru::LruCache;
+    use axum::{extract::{Path, Query, State}, http::{header, StatusCode}, response::{IntoResponse, Response}, routing::get, Router};
+
+    use super::{FeedFormat, FeedOptions};
+    use crate::repo::ObjType;
+    use crate::yuque::Yuque;
+
+    struct FeedServer {
+        yuque: Yuque,
+        ttl: Duration,
+        limit: Option<usize>,
+        feeds: Vec<String>,
+        cache: Mutex<LruCache<String, (Instant, String)>>,
+    }
+
+    impl FeedServer {
+        async fn feed(&self, path: &str, query: &HashMap<String, String>) -> Response {
+            if !self.feeds.iter().any(|feed| feed == path) {
+                return StatusCode::NOT_FOUND.into_response();
+            }
+            let format = match query.get("format").map(String::as_str) {
+                Some("rss") => FeedFormat::Rss,
+                _ => FeedFormat::Atom,
+            };
+            let key = format!("{:?} {}", format, path);
+            let cached = self.cache.lock().unwrap().get(&key).filter(|(at, _)| at.elapsed() < self.ttl).map(|(_, feed)| feed.clone());
+            let feed = match cached {
+                Some(feed) => feed,
+                None => {
+                    let options = FeedOptions { format, limit: self.limit };
+                    let feed = match path.split('/').collect::<Vec<_>>()[..] {
+                        ["users", login] => self.yuque.owner_feed(ObjType::User, login, &options).await,
+                        ["groups", login] => self.yuque.owner_feed(ObjType::Group, login, &options).await,
+                        [owner, repo] => self.yuque.repo_feed(format!("{}/{}", owner, repo), &options).await,
+                        _ => return StatusCode::NOT_FOUND.into_response(),
+                    };
+                    match feed {
+                        Ok(feed) => {
+                            self.cache.lock().unwrap().put(key, (Instant::now(), feed.clone()));
+                            feed
+                        }
+                        Err(e) => return (StatusCode::BAD_GATEWAY, e.to_string()).into_response(),
+                    }
+                }
+            };
+            ([(header::CONTENT_TYPE, format.content_type())], feed).into_response()
+        }
+    }
+
+    async fn feed(State(server): State<Arc<FeedServer>>, Path((owner, name)): Path<(String, String)>, Query(query): Query<HashMap<String, String>>) -> Response {
+        server.feed(&format!("{}/{}", owner, name), &query).await
+    }
+
+    /// Serves `feeds`, each one of `{owner}/{repo}`, `users/{login}` or
+    /// `groups/{login}`, and 404 for any other path. Atom by default or RSS
+    /// with `?format=rss`. Feeds are kept for `ttl`.
+    pub fn feed_router(yuque: Yuque, feeds: Vec<String>, ttl: Duration, limit: Option<usize>) -> Router {
+        let capacity = NonZeroUsize::new(feeds.len() * 2).unwrap_or(NonZeroUsize::MIN);
+        let server = FeedServer { yuque, ttl, limit, feeds, cache: Mutex::new(LruCache::new(capacity)) };
+        Router::new().route("/:owner/:name", get(feed)).with_state(Arc::new(server))
+    }
+
+    pub async fn serve_feeds(addr: SocketAddr, yuque: Yuque, feeds: Vec<String>, ttl: Duration) -> Result<()> {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        axum::serve(listener, feed_router(yuque, feeds, ttl, None)).await?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "feed-server")]
+pub use server::{feed_router, serve_feeds};
+
+#[cfg(test)]
+mod tests {
+    use crate::feed::{build_feed, FeedDoc, FeedFormat, FeedMeta, FeedOptions};
+    use crate::serializer::{DocSerializer, UserSerializer};
+
+    fn doc(slug: &str, title: &str, updated_at: &str) -> FeedDoc {
+        let doc = DocSerializer {
+            slug: slug.to_string(),
+            title: title.to_string(),
+            content_updated_at: updated_at.to_string(),
+            status: 1,
+            published_at: Some(updated_at.to_string()),
+            ..DocSerializer::default()
+        };
+        FeedDoc { namespace: "team/kb".to_string(), doc }
+    }
+
+    fn docs() -> Vec<FeedDoc> {
+        let mut newest = doc("faq", "Q & A", "2024-03-02T10:00:00.000Z");
+        newest.doc.custom_description = Some("Common <questions>".to_string());
+        newest.doc.description = Some("ignored".to_string());
+        newest.doc.last_editor = Some(UserSerializer { name: "Alice".to_string(), ..UserSerializer::default() });
+        newest.doc.first_published_at = Some("2024-01-01T00:00:00.000Z".to_string());
+        let mut draft = doc("draft", "Draft", "2024-04-01T00:00:00.000Z");
+        draft.doc.status = 0;
+        let mut unpublished = doc("unpublished", "Unpublished", "2024-04-01T00:00:00.000Z");
+        unpublished.doc.published_at = None;
+        vec![doc("old", "Old", "2023-01-01T00:00:00.000Z"), newest, doc("mid", "Mid", "2024-02-01T00:00:00+08:00"), doc("bad", "Bad", ""), draft, unpublished]
+    }
+
+    #[test]
+    fn test_atom_feed() {
+        let meta = FeedMeta { title: "KB".to_string(), link: "https://www.yuque.com/team/kb".to_string(), description: Some("Team docs".to_string()) };
+        let feed = build_feed(&meta, &docs(), &FeedOptions { limit: Some(2), ..FeedOptions::default() });
+        assert!(feed.contains("<title>KB</title>\n  <subtitle>Team docs</subtitle>\n  <link href=\"https://www.yuque.com/team/kb\"/>"));
+        assert!(feed.contains("<updated>2024-03-02T10:00:00+00:00</updated>\n  <generator>"));
+        let entries: Vec<&str> = feed.split("<entry>").skip(1).collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0], "\n    <title>Q &amp; A</title>
+    <link href=\"https://www.yuque.com/team/kb/faq\"/>
+    <id>https://www.yuque.com/team/kb/faq</id>
+    <updated>2024-03-02T10:00:00+00:00</updated>
+    <published>2024-01-01T00:00:00+00:00</published>
+    <author><name>Alice</name></author>
+    <summary>Common &lt;questions&gt;</summary>
+  </entry>
+  ");
+        assert!(entries[1].contains("<title>Mid</title>"));
+    }
+
+    #[test]
+    fn test_rss_feed() {
+        let meta = FeedMeta { title: "KB".to_string(), link: "https://www.yuque.com/team/kb".to_string(), description: None };
+        let feed = build_feed(&meta, &docs(), &FeedOptions { format: FeedFormat::Rss, limit: None });
+        assert!(feed.contains("<channel>\n  <title>KB</title>\n  <link>https://www.yuque.com/team/kb</link>\n  <description>KB</description>"));
+        assert!(feed.contains("<lastBuildDate>Sat, 2 Mar 2024 10:00:00 +0000</lastBuildDate>"));
+        assert!(feed.contains("<guid isPermaLink=\"true\">https://www.yuque.com/team/kb/faq</guid>\n    <pubDate>Sat, 2 Mar 2024 10:00:00 +0000</pubDate>\n    <dc:creator>Alice</dc:creator>"));
+        assert_eq!(feed.matches("<item>").count(), 3);
+        assert!(feed.find("/kb/mid").unwrap() < feed.find("/kb/old").unwrap());
+        assert!(!feed.contains("Draft") && !feed.contains("Unpublished"));
+    }
+
+    #[cfg(feature = "feed-server")]
+    #[tokio::test]
+    async fn test_feed_router() {
+        let yuque = crate::yuque::Yuque::new("token").unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = crate::feed::feed_router(yuque, vec!["team/kb".to_string()], std::time::Duration::from_secs(60), None);
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let resp = reqwest::get(format!("http://{}/team/other", addr)).await.unwrap();
+        assert_eq!(resp.status().as_u16(), 404);
+        let resp = reqwest::get(format!("http://{}/users/alice", addr)).await.unwrap();
+        assert_eq!(resp.status().as_u16(), 404);
+    }
+}
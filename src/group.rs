@@ -1,3 +1,4 @@
+use crate::cache::GROUP_PATTERNS;
 use crate::serializer::{APIResponse, GroupSerializer, GroupUserSerializer};
 use crate::yuque::Yuque;
 use anyhow::Result;
@@ -9,15 +10,11 @@ impl Yuque {
     pub async fn list_user_groups<T: Display>(&self, user: T) -> Result<Vec<GroupSerializer>> {
         let endpoint = format!("/users/{}/groups", user);
         let api = Yuque::build_api(&endpoint, None);
-        let resp = self.client.get(api).send().await?;
-        if resp.status().is_success() {
-            let resp = resp.json::<APIResponse<Vec<GroupSerializer>>>().await?;
-            Ok(resp.data)
-        } else {
-            Err(anyhow::anyhow!("{} {}", resp.status(), resp.text().await?))
-        }
+        let resp = self.get_json::<APIResponse<Vec<GroupSerializer>>>(self.client.get(api)).await?;
+        Ok(resp.data)
     }
 
+    #[allow(clippy::useless_format)]
     pub async fn list_public_groups(&self, offset: Option<u32>) -> Result<Vec<GroupSerializer>> {
         let endpoint = format!("/groups");
         let api = Yuque::build_api(&endpoint, None);
@@ -25,13 +22,8 @@ impl Yuque {
         if let Some(offset) = offset {
             query.push(("offset", offset));
         }
-        let resp = self.client.get(api).query(&query).send().await?;
-        if resp.status().is_success() {
-            let resp = resp.json::<APIResponse<Vec<GroupSerializer>>>().await?;
-            Ok(resp.data)
-        } else {
-            Err(anyhow::anyhow!("{} {}", resp.status(), resp.text().await?))
-        }
+        let resp = self.get_json::<APIResponse<Vec<GroupSerializer>>>(self.client.get(api).query(&query)).await?;
+        Ok(resp.data)
     }
 
     pub async fn list_group_users<T: Display>(
@@ -55,27 +47,18 @@ impl Yuque {
             query.push(("limit", limit.into()));
         }
 
-        let resp = self.client.get(api).query(&query).send().await?;
-        if resp.status().is_success() {
-            let resp = resp.json::<APIResponse<Vec<GroupUserSerializer>>>().await?;
-            Ok(resp.data)
-        } else {
-            Err(anyhow::anyhow!("{} {}", resp.status(), resp.text().await?))
-        }
+        let resp = self.get_json::<APIResponse<Vec<GroupUserSerializer>>>(self.client.get(api).query(&query)).await?;
+        Ok(resp.data)
     }
 
     pub async fn get_group<T: Display>(&self, group: T) -> Result<APIResponse<GroupSerializer>> {
         let endpoint = format!("/groups/{}", group);
         let api = Yuque::build_api(&endpoint, None);
-        let resp = self.client.get(api).send().await?;
-        if resp.status().is_success() {
-            let resp = resp.json::<APIResponse<GroupSerializer>>().await?;
-            Ok(resp)
-        } else {
-            Err(anyhow::anyhow!("{} {}", resp.status(), resp.text().await?))
-        }
+        let resp = self.get_json::<APIResponse<GroupSerializer>>(self.client.get(api)).await?;
+        Ok(resp)
     }
 
+    #[allow(clippy::useless_format)]
     pub async fn create_group(
         &self,
         name: &str,
@@ -94,6 +77,7 @@ impl Yuque {
             }))
             .send()
             .await?;
+        self.invalidate_cache(&GROUP_PATTERNS);
         if resp.status().is_success() {
             let resp = resp.json::<APIResponse<GroupSerializer>>().await?;
             Ok(resp.data)
@@ -124,6 +108,7 @@ impl Yuque {
         }
 
         let resp = self.client.put(api).json(&payload).send().await?;
+        self.invalidate_cache(&GROUP_PATTERNS);
         if resp.status().is_success() {
             let resp = resp.json::<APIResponse<GroupSerializer>>().await?;
             Ok(resp.data)
@@ -136,6 +121,7 @@ impl Yuque {
         let endpoint = format!("/groups/{}", group);
         let api = Yuque::build_api(&endpoint, None);
         let resp = self.client.delete(api).send().await?;
+        self.invalidate_cache(&GROUP_PATTERNS);
         if resp.status().is_success() {
             Ok(())
         } else {
@@ -154,6 +140,7 @@ impl Yuque {
             }))
             .send()
             .await?;
+        self.invalidate_cache(&GROUP_PATTERNS);
         if resp.status().is_success() {
             let resp = resp.json::<APIResponse<GroupUserSerializer>>().await?;
             Ok(resp.data)
@@ -166,6 +153,7 @@ impl Yuque {
         let endpoint = format!("/groups/{}/users/{}", group, user);
         let api = Yuque::build_api(&endpoint, None);
         let resp = self.client.delete(api).send().await?;
+        self.invalidate_cache(&GROUP_PATTERNS);
         if resp.status().is_success() {
             Ok(())
         } else {
@@ -0,0 +1,116 @@
+use std::borrow::Cow;
+
+use crate::dom::{self, Node};
+use crate::lake::{self, Document, MarkdownOptions};
+use crate::serializer::DocSerializer;
+
+static YUQUE_CDN_HOSTS: &[&str] = &["cdn.nlark.com", "cdn.yuque.com", "gw.alipayobjects.com"];
+
+/// Whether `url` points at one of the CDNs Yuque serves doc images from.
+pub fn is_yuque_cdn(url: &str) -> bool {
+    let Some(rest) = url.strip_prefix("https://").or_else(|| url.strip_prefix("http://")).or_else(|| url.strip_prefix("//")) else {
+        return false;
+    };
+    let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    YUQUE_CDN_HOSTS.contains(&host)
+}
+
+/// Sanitizes a `body_html` with the default allowlist.
+pub fn sanitize(html: &str) -> String {
+    sanitize_with(html, |_| None)
+}
+
+/// Sanitizes a `body_html`, passing the `src` of every Yuque CDN image to
+/// `rewrite_image`. Returning `None` keeps the original URL.
+///
+/// Images are served with `referrerpolicy="no-referrer"`, without which the
+/// CDN refuses requests from other sites.
+pub fn sanitize_with<F>(html: &str, rewrite_image: F) -> String
+where
+    F: Fn(&str) -> Option<String> + Send + Sync + 'static,
+{
+    ammonia::Builder::default()
+        .add_tag_attributes("pre", &["data-language"])
+        .add_tag_attributes("code", &["class"])
+        .add_tag_attributes("td", &["colspan", "rowspan"])
+        .add_tag_attributes("th", &["colspan", "rowspan"])
+        .set_tag_attribute_value("img", "referrerpolicy", "no-referrer")
+        .attribute_filter(move |element, attribute, value| {
+            if element == "img" && attribute == "src" && is_yuque_cdn(value) {
+                if let Some(url) = rewrite_image(value) {
+                    return Some(Cow::Owned(url));
+                }
+            }
+            Some(Cow::Borrowed(value))
+        })
+        .clean(html)
+        .to_string()
+}
+
+/// Parses an HTML body into the Lake document model.
+pub fn to_document(html: &str) -> Document {
+    let mut nodes = dom::parse(html);
+    collapse_whitespace(&mut nodes);
+    Document { blocks: lake::blocks(&nodes) }
+}
+
+/// Converts an HTML body to Markdown.
+pub fn to_markdown(html: &str, options: &MarkdownOptions) -> String {
+    lake::to_markdown(&to_document(html), options)
+}
+
+/// The Markdown of a doc, converted from whichever body is populated.
+pub fn doc_markdown(doc: &DocSerializer, options: &MarkdownOptions) -> Option<String> {
+    let populated = |body: &Option<String>| body.clone().filter(|body| !body.trim().is_empty());
+    if let Some(body) = populated(&doc.body) {
+        return Some(body);
+    }
+    if let Some(lake_body) = populated(&doc.body_lake) {
+        return Some(lake::to_markdown(&lake::parse(&lake_body), options));
+    }
+    populated(&doc.body_html).map(|html| to_markdown(&html, options))
+}
+
+/// Collapses runs of whitespace like a browser does, leaving `pre` alone.
+fn collapse_whitespace(nodes: &mut [Node]) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => {
+                let collapsed = text.split_ascii_whitespace().collect::<Vec<_>>().join(" ");
+                let start = if text.starts_with(|c: char| c.is_ascii_whitespace()) { " " } else { "" };
+                let end = if text.ends_with(|c: char| c.is_ascii_whitespace()) && !collapsed.is_empty() { " " } else { "" };
+                *text = format!("{}{}{}", start, collapsed, end);
+            }
+            Node::Element(el) if el.tag != "pre" => collapse_whitespace(&mut el.children),
+            Node::Element(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html::{is_yuque_cdn, sanitize, sanitize_with, to_markdown};
+    use crate::lake::MarkdownOptions;
+
+    #[test]
+    fn test_sanitize() {
+        let html = r#"<p onclick="x()">Hi<script>alert(1)</script><img src="https://cdn.nlark.com/yuque/0/a.png"><img src="https://example.com/b.png"></p>"#;
+        let clean = sanitize(html);
+        assert!(!clean.contains("script") && !clean.contains("onclick"));
+        assert!(clean.contains(r#"referrerpolicy="no-referrer""#));
+
+        let proxied = sanitize_with(html, |url| Some(format!("/proxy?url={}", url)));
+        assert!(proxied.contains(r#"src="/proxy?url=https://cdn.nlark.com/yuque/0/a.png""#));
+        assert!(proxied.contains(r#"src="https://example.com/b.png""#));
+
+        assert!(is_yuque_cdn("//cdn.yuque.com/x.png"));
+        assert!(!is_yuque_cdn("https://cdn.nlark.com.evil.com/x.png"));
+    }
+
+    #[test]
+    fn test_to_markdown() {
+        let html = "<!doctype html><div class=\"lake-content\">\n  <h2 id=\"a\">Title</h2>\n  <p>Some\n    <b>bold</b> text</p>\n  <pre><code class=\"language-js\">let a;\n  a = 1;</code></pre>\n  <ol><li>one<ul><li>two</li></ul></li></ol>\n</div>";
+        let expected = "## Title\n\nSome **bold** text\n\n```js\nlet a;\n  a = 1;\n```\n\n1. one\n    - two\n";
+        assert_eq!(to_markdown(html, &MarkdownOptions::default()), expected);
+    }
+}
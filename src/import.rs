@@ -0,0 +1,398 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use serde_yaml::Value;
+use sha2::{Digest, Sha256};
+
+use crate::doc::{CreateDocRequest, DocFormat, UpdateDocRequest};
+use crate::export::markdown_to_html;
+use crate::lake::markdown_to_lake;
+use crate::links::PathMap;
+use crate::repo::{CreateRepoRequest, ObjType, RepoPublic, RepoType, UpdateRepoRequest};
+use crate::serializer::{BookSerializer, DocSerializer};
+use crate::sync::title_from_markdown;
+use crate::toc::{merge_toc, parse_toc, render_toc_markdown, TocItem, TocItemType};
+use crate::upload::{upload_local_files, upload_local_images, YuqueUploader};
+use crate::utils::{is_not_found, slugify, unique_slug};
+use crate::vault::{collect_notes, split_properties};
+use crate::yuque::Yuque;
+
+mod confluence;
+mod mediawiki;
+
+pub use confluence::read_confluence_export;
+pub use mediawiki::{read_mediawiki_dump, wikitext_to_markdown};
+
+pub static IMPORT_MAPPING_FILE: &str = ".yuque-import.json";
+
+/// File names that stand for the folder they are in.
+static INDEX_FILES: [&str; 3] = ["README.md", "readme.md", "index.md"];
+
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    /// Name of the repo if it has to be created, defaults to the directory name.
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Visibility of a created repo, defaults to private.
+    pub public: Option<RepoPublic>,
+    /// Where the path to doc id mapping is kept, defaults to
+    /// `.yuque-import.json` in the imported directory.
+    pub mapping_file: Option<PathBuf>,
+    /// Format of the created docs, defaults to Markdown. Other formats are
+    /// converted from the Markdown.
+    pub format: Option<DocFormat>,
+}
+
+/// A Markdown file of the imported tree, or a page of a wiki export
+/// converted to Markdown.
+#[derive(Debug, Clone)]
+pub struct MarkdownFile {
+    /// `/` separated path relative to the imported directory. Links and
+    /// images in the body are relative to it.
+    pub path: String,
+    pub title: String,
+    pub slug: String,
+    pub body: String,
+}
+
+impl MarkdownFile {
+    fn is_index(&self) -> bool {
+        INDEX_FILES.contains(&self.path.rsplit('/').next().unwrap_or_default())
+    }
+
+    /// Folders the file is in, an index file standing for its own folder.
+    fn folders(&self) -> Vec<&str> {
+        let mut parts: Vec<&str> = self.path.split('/').collect();
+        parts.pop();
+        parts
+    }
+
+    /// Orders index files before the rest of their folder.
+    fn sort_key(&self) -> Vec<&str> {
+        let mut key = self.folders();
+        key.push(if self.is_index() { "" } else { self.path.rsplit('/').next().unwrap_or_default() });
+        key
+    }
+}
+
+/// The slug of a file: its path with `/` as `-`, or for index files the
+/// path of their folder. Paths without ASCII letters or digits get a hash.
+pub fn slug_from_path(path: &str) -> String {
+    let stem = path.strip_suffix(".md").unwrap_or(path);
+    let stem = match stem.rsplit_once('/') {
+        Some((dir, name)) if INDEX_FILES.contains(&format!("{}.md", name).as_str()) => dir,
+        _ => stem,
+    };
+    match slugify(stem) {
+        slug if slug.is_empty() => format!("doc-{}", &hex::encode(Sha256::digest(path.as_bytes()))[..8]),
+        slug => slug,
+    }
+}
+
+/// Drops the first line when it is the `# title` heading the title came from.
+fn strip_title(body: &str, title: &str) -> String {
+    let trimmed = body.trim_start();
+    match trimmed.split_once('\n') {
+        Some((first, rest)) if first.strip_prefix("# ").map(str::trim) == Some(title) => rest.trim_start_matches(['\r', '\n']).to_string(),
+        None if trimmed.strip_prefix("# ").map(str::trim) == Some(title) => String::new(),
+        _ => body.to_string(),
+    }
+}
+
+/// Reads the Markdown files under `dir`, skipping hidden files and folders.
+/// The title comes from the `title` front matter, the first `# ` heading or
+/// the file name; the slug from the `slug` front matter or the path.
+pub fn scan_markdown_dir(dir: &Path) -> Result<Vec<MarkdownFile>> {
+    let mut paths = Vec::new();
+    collect_notes(dir, dir, Path::new(""), &mut paths)?;
+    let mut files = Vec::new();
+    for path in paths {
+        let rel = path.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+        let (properties, body) = split_properties(&std::fs::read_to_string(dir.join(&path))?)?;
+        let property = |key: &str| properties.get(key).and_then(Value::as_str).map(str::to_string);
+        let (title, body) = match (property("title"), title_from_markdown(&body)) {
+            (Some(title), _) => (title, body),
+            (None, Some(title)) => {
+                let body = strip_title(&body, &title);
+                (title, body)
+            }
+            (None, None) => (path.file_stem().unwrap_or_default().to_string_lossy().into_owned(), body),
+        };
+        let slug = property("slug").unwrap_or_else(|| slug_from_path(&rel));
+        files.push(MarkdownFile { path: rel, title, slug, body });
+    }
+    files.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+    Ok(files)
+}
+
+/// TOC following the folders. A folder with an index file is the doc of
+/// that file, other folders become `TITLE` items.
+pub fn markdown_toc(files: &[MarkdownFile]) -> Vec<TocItem> {
+    let mut folders = HashSet::new();
+    let mut items = Vec::new();
+    for file in files {
+        let parts = file.folders();
+        let mut level = parts.len() as u32;
+        for depth in 0..parts.len() {
+            if !folders.insert(parts[..=depth].join("/")) {
+                continue;
+            }
+            if file.is_index() && depth == parts.len() - 1 {
+                level = depth as u32;
+                break;
+            }
+            items.push(TocItem {
+                typ: TocItemType::Title,
+                title: parts[depth].to_string(),
+                url: String::new(),
+                uuid: String::new(),
+                doc_id: None,
+                level: depth as u32,
+            });
+        }
+        items.push(TocItem::doc(&file.title, &file.slug, level));
+    }
+    items
+}
+
+/// A page of a wiki export, with the page it is nested under.
+#[derive(Debug, Clone)]
+pub struct ImportPage {
+    pub file: MarkdownFile,
+    /// Path of the parent page.
+    pub parent: Option<String>,
+}
+
+/// TOC nesting pages under their parents, siblings in the given order.
+/// Pages whose parent is missing go to the top level.
+pub fn page_toc(files: &[MarkdownFile], parents: &HashMap<String, String>) -> Vec<TocItem> {
+    fn add(files: &[MarkdownFile], children: &HashMap<&str, Vec<usize>>, index: usize, level: u32, items: &mut Vec<TocItem>) {
+        let file = &files[index];
+        items.push(TocItem::doc(&file.title, &file.slug, level));
+        for child in children.get(file.path.as_str()).into_iter().flatten() {
+            add(files, children, *child, level + 1, items);
+        }
+    }
+    let paths: HashSet<&str> = files.iter().map(|file| file.path.as_str()).collect();
+    let mut roots = Vec::new();
+    let mut children: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, file) in files.iter().enumerate() {
+        match parents.get(&file.path).filter(|parent| paths.contains(parent.as_str()) && **parent != file.path) {
+            Some(parent) => children.entry(parent.as_str()).or_default().push(i),
+            None => roots.push(i),
+        }
+    }
+    let mut items = Vec::new();
+    for root in roots {
+        add(files, &children, root, 0, &mut items);
+    }
+    items
+}
+
+fn load_mapping(path: &Path) -> Result<BTreeMap<String, u32>> {
+    match std::fs::read(path) {
+        Ok(data) => Ok(serde_json::from_slice(&data)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub namespace: String,
+    pub created_repo: bool,
+    pub docs: Vec<ImportedFile>,
+}
+
+#[derive(Debug)]
+pub struct ImportedFile {
+    pub path: String,
+    pub slug: String,
+    pub doc_id: u32,
+    /// `false` when the doc of an earlier import was updated.
+    pub created: bool,
+}
+
+impl Yuque {
+    async fn import_target(&self, obj_type: ObjType, owner: &str, slug: &str, default_name: &str, options: &ImportOptions) -> Result<(BookSerializer, bool)> {
+        match self.get_repo(format!("{}/{}", owner, slug)).await {
+            Ok(resp) => Ok((resp.data, false)),
+            Err(e) if is_not_found(&e) => {
+                let req = CreateRepoRequest {
+                    name: options.name.clone().unwrap_or_else(|| default_name.to_string()),
+                    slug: slug.to_string(),
+                    description: options.description.clone().unwrap_or_default(),
+                    public: options.public.unwrap_or(RepoPublic::Private),
+                    typ: RepoType::Book,
+                };
+                Ok((self.create_repo(obj_type, owner, &req).await?, true))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Creates or updates a doc for each file and sets the TOC made by `toc`
+    /// once slugs are final. Files are read relative to `dir`.
+    async fn import_files<F>(&self, target: (ObjType, &str, &str), dir: &Path, default_name: &str, mut files: Vec<MarkdownFile>, toc: F, options: &ImportOptions) -> Result<ImportReport>
+    where
+        F: FnOnce(&[MarkdownFile]) -> Vec<TocItem>,
+    {
+        let (obj_type, owner, slug) = target;
+        let namespace = format!("{}/{}", owner, slug);
+        let mapping_file = options.mapping_file.clone().unwrap_or_else(|| dir.join(IMPORT_MAPPING_FILE));
+        let mut mapping = load_mapping(&mapping_file)?;
+        let (book, created_repo) = self.import_target(obj_type, owner, slug, default_name, options).await?;
+
+        let existing = if created_repo { Vec::new() } else { self.list_all_docs(&namespace, None).await? };
+        let existing: HashMap<u32, &DocSerializer> = existing.iter().map(|doc| (doc.object.id, doc)).collect();
+        let mut taken: HashSet<String> = existing.values().map(|doc| doc.slug.clone()).collect();
+        let mut targets = Vec::new();
+        for file in files.iter_mut() {
+            match mapping.get(&file.path).and_then(|id| existing.get(id)) {
+                Some(doc) => {
+                    file.slug = doc.slug.clone();
+                    targets.push(Some(doc.object.id));
+                }
+                None => {
+                    file.slug = unique_slug(&file.slug, &taken);
+                    taken.insert(file.slug.clone());
+                    targets.push(None);
+                }
+            }
+        }
+
+        let mut paths = PathMap::default();
+        for file in &files {
+            paths.insert(&namespace, &file.slug, &file.path);
+        }
+        let format = options.format.unwrap_or(DocFormat::Markdown);
+        let uploader = YuqueUploader::new(self, book.object.id);
+        let mut report = ImportReport { namespace: namespace.clone(), created_repo, docs: Vec::new() };
+        for (file, target) in files.iter().zip(targets) {
+            let base_dir = dir.join(&file.path);
+            let base_dir = base_dir.parent().unwrap_or(dir);
+            let body = paths.to_yuque(&file.body, &file.path);
+            let body = upload_local_images(&uploader, &body, base_dir, dir).await?;
+            let body = upload_local_files(&uploader, &body, base_dir, dir).await?;
+            let body = match format {
+                DocFormat::Markdown => body,
+                DocFormat::Lake => markdown_to_lake(&body),
+                DocFormat::Html => markdown_to_html(&body),
+            };
+            let doc = match target {
+                Some(doc_id) => {
+                    let mut req = UpdateDocRequest { title: Some(file.title.clone()), body: Some(body), _force_asl: Some(1), ..UpdateDocRequest::default() };
+                    self.update_doc(&namespace, doc_id, &mut req).await?
+                }
+                None => {
+                    let req = CreateDocRequest { title: file.title.clone(), slug: file.slug.clone(), format: Some(format), body, public: None };
+                    self.create_doc(&namespace, &req).await?
+                }
+            };
+            // Saved as we go, so a failed import can be resumed.
+            mapping.insert(file.path.clone(), doc.object.id);
+            std::fs::write(&mapping_file, serde_json::to_vec_pretty(&mapping)?)?;
+            report.docs.push(ImportedFile { path: file.path.clone(), slug: file.slug.clone(), doc_id: doc.object.id, created: target.is_none() });
+        }
+
+        // Files of an earlier import keep their place in the TOC.
+        let toc = merge_toc(parse_toc(book.toc_yml.as_deref().unwrap_or_default())?, &toc(&files));
+        let req = UpdateRepoRequest { toc: Some(render_toc_markdown(&toc)), ..UpdateRepoRequest::default() };
+        self.update_repo(&namespace, &req).await?;
+        Ok(report)
+    }
+
+    /// Imports a directory tree of Markdown files into the repo `slug` of a
+    /// user or group, creating the repo if needed. Links between the files
+    /// point at the new docs, local images and attachments are uploaded and
+    /// new files are added to the TOC following the folders. The doc id of
+    /// each path is kept in the mapping file, so running the import again
+    /// updates those docs instead of adding new ones.
+    pub async fn import_markdown_dir<T: Display>(&self, obj_type: ObjType, owner: T, slug: &str, dir: &Path, options: &ImportOptions) -> Result<ImportReport> {
+        let owner = owner.to_string();
+        let name = dir.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_else(|| slug.to_string());
+        let files = scan_markdown_dir(dir)?;
+        self.import_files((obj_type, &owner, slug), dir, &name, files, markdown_toc, options).await
+    }
+
+    /// Imports a Confluence space exported as HTML, keeping the page tree
+    /// and uploading attachments. Works like `import_markdown_dir`.
+    pub async fn import_confluence<T: Display>(&self, obj_type: ObjType, owner: T, slug: &str, dir: &Path, options: &ImportOptions) -> Result<ImportReport> {
+        let owner = owner.to_string();
+        let (space, pages) = read_confluence_export(dir)?;
+        let parents: HashMap<String, String> = pages.iter().filter_map(|page| Some((page.file.path.clone(), page.parent.clone()?))).collect();
+        let files = pages.into_iter().map(|page| page.file).collect();
+        let name = space.unwrap_or_else(|| slug.to_string());
+        self.import_files((obj_type, &owner, slug), dir, &name, files, |files| page_toc(files, &parents), options).await
+    }
+
+    /// Imports the main namespace of a MediaWiki XML dump, subpages nested
+    /// under their parents. Files are taken from `files_dir` when given; the
+    /// mapping file defaults to the directory of the dump.
+    pub async fn import_mediawiki<T: Display>(&self, obj_type: ObjType, owner: T, slug: &str, dump: &Path, files_dir: Option<&Path>, options: &ImportOptions) -> Result<ImportReport> {
+        let owner = owner.to_string();
+        let (site, pages) = read_mediawiki_dump(&std::fs::read_to_string(dump)?, files_dir)?;
+        let parents: HashMap<String, String> = pages.iter().filter_map(|page| Some((page.file.path.clone(), page.parent.clone()?))).collect();
+        let files = pages.into_iter().map(|page| page.file).collect();
+        let dump_dir = dump.parent().unwrap_or(Path::new("."));
+        let options = ImportOptions { mapping_file: Some(options.mapping_file.clone().unwrap_or_else(|| dump_dir.join(IMPORT_MAPPING_FILE))), ..options.clone() };
+        // Images are linked relative to `files_dir`, so uploads resolve there.
+        let dir = files_dir.unwrap_or(dump_dir);
+        let name = site.unwrap_or_else(|| slug.to_string());
+        self.import_files((obj_type, &owner, slug), dir, &name, files, |files| page_toc(files, &parents), &options).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::import::{markdown_toc, page_toc, scan_markdown_dir, slug_from_path, MarkdownFile};
+    use crate::toc::render_toc_markdown;
+
+    #[test]
+    fn test_slug_from_path() {
+        assert_eq!(slug_from_path("Guide/Getting Started.md"), "guide-getting-started");
+        assert_eq!(slug_from_path("guide/README.md"), "guide");
+        assert_eq!(slug_from_path("README.md"), "readme");
+        assert!(slug_from_path("指南.md").starts_with("doc-"));
+        assert!(slug_from_path("教程.md").starts_with("doc-"));
+        assert_ne!(slug_from_path("指南.md"), slug_from_path("教程.md"));
+    }
+
+    #[test]
+    fn test_scan_markdown_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, content: &str| {
+            let full = dir.path().join(path);
+            std::fs::create_dir_all(full.parent().unwrap()).unwrap();
+            std::fs::write(full, content).unwrap();
+        };
+        write("README.md", "# Home\n\nSee [install](guide/install.md).\n");
+        write("guide/install.md", "---\nslug: setup\n---\n\nRun it.\n");
+        write("guide/index.md", "# The Guide\n");
+        write("api/users.md", "# Users\n");
+        write(".git/HEAD.md", "");
+
+        let files = scan_markdown_dir(dir.path()).unwrap();
+        let paths: Vec<_> = files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, vec!["README.md", "api/users.md", "guide/index.md", "guide/install.md"]);
+        assert_eq!(files[0].title, "Home");
+        assert_eq!(files[0].body, "See [install](guide/install.md).\n");
+        assert_eq!((files[3].title.as_str(), files[3].slug.as_str()), ("install", "setup"));
+        assert_eq!(files[2].slug, "guide");
+
+        assert_eq!(
+            render_toc_markdown(&markdown_toc(&files)),
+            "- [Home](readme)\n- api\n  - [Users](api-users)\n- [The Guide](guide)\n  - [install](setup)\n",
+        );
+    }
+
+    #[test]
+    fn test_page_toc() {
+        let file = |path: &str, title: &str| MarkdownFile { path: path.to_string(), title: title.to_string(), slug: title.to_lowercase(), body: String::new() };
+        let files = vec![file("1.html", "Home"), file("3.html", "Child"), file("2.html", "Other"), file("4.html", "Orphan")];
+        let parents = HashMap::from([("3.html".to_string(), "1.html".to_string()), ("4.html".to_string(), "9.html".to_string())]);
+        assert_eq!(render_toc_markdown(&page_toc(&files, &parents)), "- [Home](home)\n  - [Child](child)\n- [Other](other)\n- [Orphan](orphan)\n");
+    }
+}
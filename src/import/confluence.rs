@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::LazyLock;
+use anyhow::Result;
+use regex::Regex;
+
+use crate::dom::{self, Element, Node};
+use crate::html;
+use crate::import::{slug_from_path, ImportPage, MarkdownFile};
+use crate::lake::MarkdownOptions;
+use crate::utils::slugify;
+
+static INDEX_PAGE: &str = "index.html";
+static PAGE_ID: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"pageId=(\d+)").unwrap());
+
+fn find<'a>(nodes: &'a [Node], pred: &dyn Fn(&Element) -> bool) -> Option<&'a Element> {
+    nodes.iter().find_map(|node| match node {
+        Node::Element(el) if pred(el) => Some(el),
+        Node::Element(el) => find(&el.children, pred),
+        Node::Text(_) => None,
+    })
+}
+
+fn find_all<'a>(nodes: &'a [Node], pred: &dyn Fn(&Element) -> bool, out: &mut Vec<&'a Element>) {
+    for node in nodes {
+        if let Node::Element(el) = node {
+            if pred(el) {
+                out.push(el);
+            }
+            find_all(&el.children, pred, out);
+        }
+    }
+}
+
+fn has_class(el: &Element, class: &str) -> bool {
+    el.attr("class").is_some_and(|classes| classes.split_whitespace().any(|c| c == class))
+}
+
+fn by_id<'a>(nodes: &'a [Node], id: &str) -> Option<&'a Element> {
+    find(nodes, &|el| el.attr("id") == Some(id))
+}
+
+fn links(nodes: &[Node]) -> Vec<&Element> {
+    let mut out = Vec::new();
+    find_all(nodes, &|el| el.tag == "a" && el.attr("href").is_some(), &mut out);
+    out
+}
+
+/// Page titles read `Space : Title`.
+fn page_title(nodes: &[Node]) -> Option<(Option<String>, String)> {
+    let text = by_id(nodes, "title-text").or_else(|| find(nodes, &|el| el.tag == "title"))?.text();
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    Some(match text.split_once(" : ") {
+        Some((space, title)) => (Some(space.to_string()), title.to_string()),
+        None => (None, text),
+    })
+}
+
+/// The closest ancestor in the breadcrumbs, which start at the space index.
+fn page_parent(nodes: &[Node]) -> Option<String> {
+    let breadcrumbs = by_id(nodes, "breadcrumbs")?;
+    links(&breadcrumbs.children).into_iter().filter_map(|a| a.attr("href")).rfind(|href| *href != INDEX_PAGE).map(str::to_string)
+}
+
+fn callout_kind(el: &Element) -> Option<&'static str> {
+    if el.tag != "div" || !has_class(el, "confluence-information-macro") {
+        return None;
+    }
+    Some(match () {
+        _ if has_class(el, "confluence-information-macro-tip") => "tips",
+        _ if has_class(el, "confluence-information-macro-note") => "warning",
+        _ if has_class(el, "confluence-information-macro-warning") => "danger",
+        _ => "info",
+    })
+}
+
+/// Rewrites Confluence markup into what the HTML converter understands:
+/// code macros get their language, panels become callouts and links by
+/// page id point at the exported file.
+fn clean(nodes: &[Node], files_by_id: &HashMap<String, String>) -> Vec<Node> {
+    nodes
+        .iter()
+        .filter_map(|node| {
+            let Node::Element(el) = node else { return Some(node.clone()) };
+            if el.tag == "script" || el.tag == "style" || has_class(el, "confluence-information-macro-icon") {
+                return None;
+            }
+            let mut el = Element { children: clean(&el.children, files_by_id), ..el.clone() };
+            if let Some(kind) = callout_kind(&el) {
+                el.attrs = vec![("class".to_string(), "alert".to_string()), ("data-type".to_string(), kind.to_string())];
+            }
+            if el.tag == "pre" {
+                let brush = el.attr("data-syntaxhighlighter-params").and_then(|params| params.split(';').find_map(|p| p.trim().strip_prefix("brush:")));
+                if let Some(brush) = brush.map(|b| b.trim().to_string()) {
+                    el.attrs.push(("data-language".to_string(), brush));
+                }
+            }
+            if el.tag == "a" {
+                let file = el.attr("href").and_then(|href| PAGE_ID.captures(href)).and_then(|cap| files_by_id.get(&cap[1]));
+                if let Some(file) = file.cloned() {
+                    el.attrs.retain(|(name, _)| name != "href");
+                    el.attrs.push(("href".to_string(), file));
+                }
+            }
+            Some(Node::Element(el))
+        })
+        .collect()
+}
+
+/// Links of the attachments section that the page content does not use.
+fn extra_attachments(nodes: &[Node], body: &str) -> Vec<(String, String)> {
+    let is_section = |el: &Element| has_class(el, "pageSection") && by_id(&el.children, "attachments").is_some();
+    let Some(section) = find(nodes, &is_section) else { return Vec::new() };
+    let mut out: Vec<(String, String)> = Vec::new();
+    for a in links(&section.children) {
+        let href = a.attr("href").unwrap_or_default();
+        if href.starts_with("attachments/") && !body.contains(href) && !out.iter().any(|(_, h)| h == href) {
+            out.push((a.text().trim().to_string(), href.to_string()));
+        }
+    }
+    out
+}
+
+/// The page id in an exported file name, `Title_123.html` or `123.html`.
+fn file_page_id(name: &str) -> Option<&str> {
+    let stem = name.strip_suffix(".html")?;
+    let id = stem.rsplit('_').next()?;
+    id.chars().all(|c| c.is_ascii_digit()).then_some(id)
+}
+
+/// Reads a Confluence space exported as HTML: one file per page, the page
+/// tree in the breadcrumbs and `index.html`, files under `attachments/`.
+/// Returns the space name and the pages converted to Markdown, in the order
+/// of the space index.
+pub fn read_confluence_export(dir: &Path) -> Result<(Option<String>, Vec<ImportPage>)> {
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if name.ends_with(".html") && name != INDEX_PAGE {
+            names.push(name);
+        }
+    }
+    names.sort();
+    let files_by_id: HashMap<String, String> = names.iter().filter_map(|name| Some((file_page_id(name)?.to_string(), name.clone()))).collect();
+
+    let mut space = None;
+    let mut order = HashMap::new();
+    if let Ok(index) = std::fs::read_to_string(dir.join(INDEX_PAGE)) {
+        let nodes = dom::parse(&index);
+        space = page_title(&nodes).map(|(space, title)| space.unwrap_or(title));
+        for href in links(&nodes).into_iter().filter_map(|a| a.attr("href")) {
+            let len = order.len();
+            order.entry(href.to_string()).or_insert(len);
+        }
+    }
+
+    let mut pages = Vec::new();
+    for name in names {
+        let nodes = dom::parse(&std::fs::read_to_string(dir.join(&name))?);
+        let Some(content) = by_id(&nodes, "main-content") else { continue };
+        let Some((page_space, title)) = page_title(&nodes) else { continue };
+        space = space.or(page_space);
+        let html = dom::to_xhtml(&clean(&content.children, &files_by_id));
+        let mut body = html::to_markdown(&html, &MarkdownOptions::default());
+        let attachments = extra_attachments(&nodes, &body);
+        if !attachments.is_empty() {
+            body.push_str("\n## Attachments\n\n");
+            for (text, href) in attachments {
+                body.push_str(&format!("- [{}]({})\n", text, href.replace(' ', "%20")));
+            }
+        }
+        let slug = match slugify(&title) {
+            slug if slug.is_empty() => slug_from_path(&format!("page-{}", name.trim_end_matches(".html"))),
+            slug => slug,
+        };
+        let parent = page_parent(&nodes);
+        pages.push(ImportPage { file: MarkdownFile { path: name, title, slug, body }, parent });
+    }
+    pages.sort_by_key(|page| order.get(&page.file.path).copied().unwrap_or(usize::MAX));
+    Ok((space, pages))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::import::read_confluence_export;
+
+    fn page(title: &str, breadcrumbs: &str, content: &str, attachments: &str) -> String {
+        format!(
+            r#"<html><head><title>Eng : {title}</title></head><body><div id="page"><div id="main-header"><div id="breadcrumb-section"><ol id="breadcrumbs"><li class="first"><span><a href="index.html">Eng</a></span></li>{breadcrumbs}</ol></div>
+<h1 id="title-heading" class="pagetitle"><span id="title-text"> Eng : {title} </span></h1></div>
+<div id="content" class="view"><div id="main-content" class="wiki-content group">{content}</div>{attachments}</div></div></body></html>"#
+        )
+    }
+
+    #[test]
+    fn test_read_confluence_export() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, content: String| std::fs::write(dir.path().join(name), content).unwrap();
+        write("index.html", r#"<html><body><h1 id="title-heading"><span id="title-text">Eng</span></h1><ul><li><a href="Home_1.html">Home</a><ul><li><a href="Setup_2.html">Setup</a></li></ul></li></ul></body></html>"#.to_string());
+        write("Setup_2.html", page(
+            "Setup",
+            r#"<li><span><a href="Home_1.html">Home</a></span></li>"#,
+            r#"<div class="confluence-information-macro confluence-information-macro-warning"><span class="aui-icon confluence-information-macro-icon"></span><div class="confluence-information-macro-body"><p>Careful.</p></div></div>
+<div class="code panel pdl"><div class="codeContent panelContent pdl"><pre class="syntaxhighlighter-pre" data-syntaxhighlighter-params="brush: bash; gutter: false">make install</pre></div></div>
+<p><img class="confluence-embedded-image" src="attachments/2/10.png" alt="shot"></p>"#,
+            r#"<div class="pageSection group"><div class="pageSectionHeader"><h2 id="attachments" class="pageSectionTitle">Attachments:</h2></div><div class="greybox"><img src="images/icons/bullet_blue.gif"> <a href="attachments/2/10.png">shot.png</a><br><img src="images/icons/bullet_blue.gif"> <a href="attachments/2/11.pdf">spec.pdf</a></div></div>"#,
+        ));
+        write("Home_1.html", page("Home", "", r#"<p>Start with <a href="/pages/viewpage.action?pageId=2">setup</a>.</p>"#, ""));
+
+        let (space, pages) = read_confluence_export(dir.path()).unwrap();
+        assert_eq!(space.as_deref(), Some("Eng"));
+        let paths: Vec<_> = pages.iter().map(|p| p.file.path.as_str()).collect();
+        assert_eq!(paths, vec!["Home_1.html", "Setup_2.html"]);
+        assert_eq!(pages[0].parent, None);
+        assert_eq!(pages[0].file.body, "Start with [setup](Setup_2.html).\n");
+
+        let setup = &pages[1];
+        assert_eq!((setup.file.title.as_str(), setup.file.slug.as_str()), ("Setup", "setup"));
+        assert_eq!(setup.parent.as_deref(), Some("Home_1.html"));
+        assert!(setup.file.body.contains("> [!CAUTION]\n> Careful."), "{}", setup.file.body);
+        assert!(setup.file.body.contains("```bash\nmake install\n```"));
+        assert!(setup.file.body.contains("![shot](attachments/2/10.png)"));
+        assert!(setup.file.body.ends_with("## Attachments\n\n- [spec.pdf](attachments/2/11.pdf)\n"));
+    }
+}
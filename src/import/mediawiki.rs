@@ -0,0 +1,377 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::LazyLock;
+use anyhow::Result;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use regex::{Captures, Regex};
+
+use crate::import::{slug_from_path, ImportPage, MarkdownFile};
+use crate::vault::link_path;
+
+/// Marks text set aside while the rest is converted.
+const STASH: char = '\u{1}';
+
+static IMAGE_OPTIONS: &[&str] = &["thumb", "thumbnail", "frame", "frameless", "border", "left", "right", "center", "centre", "none", "upright", "baseline", "middle", "top", "bottom"];
+
+static COMMENT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)<!--.*?-->").unwrap());
+static CODE_BLOCK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?si)<(?:syntaxhighlight|source|pre)\b([^>]*)>\n?(.*?)</(?:syntaxhighlight|source|pre)>").unwrap());
+static LANG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"lang(?:uage)?\s*=\s*"?([\w+-]+)"#).unwrap());
+static NOWIKI: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)<nowiki>(.*?)</nowiki>").unwrap());
+static CODE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)<code>(.*?)</code>").unwrap());
+static TEMPLATE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{\{[^{}]*\}\}").unwrap());
+static MAGIC: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"__[A-Z]+__|<references\s*/>|<ref\b[^>]*/>").unwrap());
+static REFERENCE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)<ref\b[^>]*>(.*?)</ref>").unwrap());
+static RESTORE: LazyLock<Regex> = LazyLock::new(|| Regex::new(&format!("{}(\\d+){}", STASH, STASH)).unwrap());
+static BLANK_LINES: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\n{3,}").unwrap());
+static EXTERNAL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[((?:https?|ftp)://[^\s\]]+)(?:\s+([^\]]*))?\]").unwrap());
+static FILE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[\[\s*(?i:file|image)\s*:([^|\]]+)((?:\|[^\]]*)?)\]\]").unwrap());
+static CATEGORY: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[\[\s*(?i:category)\s*:[^\]]*\]\]").unwrap());
+static INTERNAL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[\[([^|\]#]*)(#[^|\]]*)?(?:\|([^\]]*))?\]\]([a-z]*)").unwrap());
+static BOLD_ITALIC: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"'''''(.+?)'''''").unwrap());
+static BOLD: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"'''(.+?)'''").unwrap());
+static ITALIC: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"''(.+?)''").unwrap());
+
+#[derive(Debug, Default)]
+struct RawPage {
+    title: String,
+    ns: String,
+    redirect: bool,
+    timestamp: String,
+    text: String,
+}
+
+/// The file a page title maps to, as in MediaWiki URLs: first letter upper
+/// case and `_` for spaces.
+fn page_file(title: &str) -> String {
+    let title = title.trim().replace(' ', "_");
+    let mut chars = title.chars();
+    match chars.next() {
+        Some(first) => format!("{}{}.md", first.to_uppercase(), chars.as_str()),
+        None => String::new(),
+    }
+}
+
+fn stash(stashed: &mut Vec<String>, text: String) -> String {
+    stashed.push(text);
+    format!("{}{}{}", STASH, stashed.len() - 1, STASH)
+}
+
+fn image(target: &str, params: &str, page_path: &str, files_dir: Option<&Path>) -> String {
+    let mut alt = None;
+    let mut caption = None;
+    for param in params.split('|').map(str::trim).filter(|p| !p.is_empty()) {
+        if let Some(text) = param.strip_prefix("alt=") {
+            alt = Some(text.to_string());
+        } else if !IMAGE_OPTIONS.contains(&param) && !param.ends_with("px") && !param.contains('=') {
+            caption = Some(param.to_string());
+        }
+    }
+    let text = alt.or(caption).unwrap_or_default();
+    let name = page_file(target);
+    let name = name.trim_end_matches(".md");
+    let found = files_dir.and_then(|dir| [name, target.trim()].into_iter().find(|n| dir.join(n).is_file()));
+    match found {
+        Some(file) => format!("![{}]({})", text, link_path(page_path, file)),
+        None if text.is_empty() => target.trim().to_string(),
+        None => text,
+    }
+}
+
+/// Links, images and emphasis of a line.
+fn inline(line: &str, page_path: &str, files_dir: Option<&Path>) -> String {
+    let line = EXTERNAL.replace_all(line, |cap: &Captures| match cap.get(2) {
+        Some(text) => format!("[{}]({})", text.as_str(), &cap[1]),
+        None => format!("<{}>", &cap[1]),
+    });
+    let line = FILE.replace_all(&line, |cap: &Captures| image(&cap[1], &cap[2], page_path, files_dir));
+    let line = CATEGORY.replace_all(&line, "");
+    let line = INTERNAL.replace_all(&line, |cap: &Captures| {
+        let target = cap[1].trim().trim_start_matches(':');
+        let anchor = cap.get(2).map_or(String::new(), |m| m.as_str().replace(' ', "_"));
+        let text = match cap.get(3) {
+            Some(text) if !text.as_str().is_empty() => text.as_str().to_string(),
+            _ => format!("{}{}", target, cap.get(2).map_or("", |m| m.as_str())).trim_start_matches('#').to_string(),
+        };
+        let text = format!("{}{}", text, &cap[4]);
+        if target.contains(':') {
+            // Other namespaces and interwiki links are not imported.
+            return text;
+        }
+        match target {
+            "" => format!("[{}]({})", text, anchor),
+            target => format!("[{}]({}{})", text, link_path(page_path, &page_file(target)), anchor),
+        }
+    });
+    let line = BOLD_ITALIC.replace_all(&line, "***$1***");
+    let line = BOLD.replace_all(&line, "**$1**");
+    ITALIC.replace_all(&line, "*$1*").into_owned()
+}
+
+/// Splits table cells off a row line, dropping cell attributes.
+fn table_cells(line: &str, separator: &str) -> Vec<String> {
+    line.split(separator)
+        .map(|cell| match cell.split_once('|') {
+            Some((attrs, content)) if attrs.contains('=') && !attrs.contains("[[") => content.trim().to_string(),
+            _ => cell.trim().to_string(),
+        })
+        .collect()
+}
+
+fn table(lines: &[&str], page_path: &str, files_dir: Option<&Path>) -> String {
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    let mut caption = None;
+    let mut row: Vec<String> = Vec::new();
+    for line in lines.iter().map(|line| line.trim()) {
+        if let Some(text) = line.strip_prefix("|+") {
+            caption = Some(text.trim().to_string());
+        } else if line.starts_with("|-") {
+            if !row.is_empty() {
+                rows.push(std::mem::take(&mut row));
+            }
+        } else if let Some(cells) = line.strip_prefix('!') {
+            row.extend(table_cells(cells, "!!").into_iter().flat_map(|cell| table_cells(&cell, "||")));
+        } else if let Some(cells) = line.strip_prefix('|') {
+            row.extend(table_cells(cells, "||"));
+        } else if let Some(last) = row.last_mut() {
+            last.push_str("<br>");
+            last.push_str(line);
+        }
+    }
+    if !row.is_empty() {
+        rows.push(row);
+    }
+    let columns = rows.iter().map(Vec::len).max().unwrap_or_default();
+    if columns == 0 {
+        return String::new();
+    }
+    let mut out = String::new();
+    if let Some(caption) = caption {
+        out.push_str(&format!("*{}*\n\n", inline(&caption, page_path, files_dir)));
+    }
+    for (i, row) in rows.iter().enumerate() {
+        let cells: Vec<String> = (0..columns)
+            .map(|c| row.get(c).map(|cell| inline(cell, page_path, files_dir).replace('|', "\\|")).unwrap_or_default())
+            .collect();
+        out.push_str(&format!("| {} |\n", cells.join(" | ")));
+        if i == 0 {
+            out.push_str(&format!("|{}\n", " --- |".repeat(columns)));
+        }
+    }
+    out
+}
+
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let line = line.trim_end();
+    let open = line.chars().take_while(|c| *c == '=').count();
+    let close = line.chars().rev().take_while(|c| *c == '=').count();
+    let level = open.min(close).min(6);
+    if level == 0 || line.len() <= open + close {
+        return None;
+    }
+    Some((level, line[level..line.len() - level].trim()))
+}
+
+/// Converts the wikitext of the page at `page_path` to Markdown. Templates
+/// and categories are dropped, references become footnotes and images are
+/// linked when found in `files_dir`, relative to the page as if the pages
+/// were written into it.
+pub fn wikitext_to_markdown(text: &str, page_path: &str, files_dir: Option<&Path>) -> String {
+    let mut stashed = Vec::new();
+    let text = COMMENT.replace_all(text, "");
+    let text = CODE_BLOCK
+        .replace_all(&text, |cap: &Captures| {
+            let language = LANG.captures(&cap[1]).map(|l| l[1].to_string()).unwrap_or_default();
+            format!("\n{}\n", stash(&mut stashed, format!("```{}\n{}\n```", language, cap[2].trim_end())))
+        })
+        .into_owned();
+    let text = NOWIKI.replace_all(&text, |cap: &Captures| stash(&mut stashed, cap[1].to_string())).into_owned();
+    let mut text = CODE.replace_all(&text, |cap: &Captures| stash(&mut stashed, format!("`{}`", &cap[1]))).into_owned();
+    while TEMPLATE.is_match(&text) {
+        text = TEMPLATE.replace_all(&text, "").into_owned();
+    }
+    let text = MAGIC.replace_all(&text, "");
+    let mut notes = Vec::new();
+    let text = REFERENCE.replace_all(&text, |cap: &Captures| {
+        notes.push(cap[1].trim().to_string());
+        format!("[^{}]", notes.len())
+    });
+
+    let mut out: Vec<String> = Vec::new();
+    let mut pre: Vec<&str> = Vec::new();
+    let mut rows: Option<Vec<&str>> = None;
+    for line in text.lines() {
+        if let Some(table_lines) = rows.as_mut() {
+            if line.trim_start().starts_with("|}") {
+                out.push(table(table_lines, page_path, files_dir));
+                rows = None;
+            } else {
+                table_lines.push(line);
+            }
+            continue;
+        }
+        if line.starts_with(' ') && !line.trim().is_empty() {
+            pre.push(&line[1..]);
+            continue;
+        }
+        if !pre.is_empty() {
+            out.push(format!("```\n{}\n```", pre.join("\n")));
+            pre.clear();
+        }
+        if line.trim_start().starts_with("{|") {
+            rows = Some(Vec::new());
+        } else if let Some((level, title)) = heading(line) {
+            out.push(format!("{} {}", "#".repeat(level), inline(title, page_path, files_dir)));
+        } else if line.starts_with("----") {
+            out.push("---".to_string());
+        } else if let Some(prefix) = line.find(|c: char| !"*#:;".contains(c)).map(|end| &line[..end]).filter(|p| !p.is_empty()) {
+            let content = inline(line[prefix.len()..].trim(), page_path, files_dir);
+            let indent = "    ".repeat(prefix.len() - 1);
+            out.push(match prefix.chars().last().unwrap() {
+                '*' => format!("{}- {}", indent, content),
+                '#' => format!("{}1. {}", indent, content),
+                ';' => match content.split_once(" : ") {
+                    Some((term, definition)) => format!("{}**{}**\n{}{}", indent, term, indent, definition),
+                    None => format!("{}**{}**", indent, content),
+                },
+                _ if prefix.len() == 1 => format!("> {}", content),
+                _ => format!("{}{}", indent, content),
+            });
+        } else {
+            out.push(inline(line, page_path, files_dir));
+        }
+    }
+    if !pre.is_empty() {
+        out.push(format!("```\n{}\n```", pre.join("\n")));
+    }
+    if let Some(table_lines) = rows {
+        out.push(table(&table_lines, page_path, files_dir));
+    }
+    for (i, note) in notes.iter().enumerate() {
+        out.push(format!("\n[^{}]: {}", i + 1, inline(note, page_path, files_dir)));
+    }
+
+    let mut markdown = out.join("\n");
+    // Stashed text may hold further placeholders, like code in a nowiki.
+    while RESTORE.is_match(&markdown) {
+        markdown = RESTORE.replace_all(&markdown, |cap: &Captures| stashed[cap[1].parse::<usize>().unwrap()].clone()).into_owned();
+    }
+    let markdown = BLANK_LINES.replace_all(markdown.trim(), "\n\n").into_owned();
+    format!("{}\n", markdown)
+}
+
+fn read_pages(xml: &str) -> Result<(Option<String>, Vec<RawPage>)> {
+    let mut reader = Reader::from_str(xml);
+    let mut path: Vec<String> = Vec::new();
+    let mut site = None;
+    let mut pages = Vec::new();
+    let mut page = RawPage::default();
+    let mut revision = (String::new(), String::new());
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                let name = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+                match name.as_str() {
+                    "page" => page = RawPage::default(),
+                    "revision" => revision = (String::new(), String::new()),
+                    _ => {}
+                }
+                path.push(name);
+            }
+            Event::Empty(e) if e.local_name().as_ref() == b"redirect" => page.redirect = true,
+            Event::Text(e) => {
+                let text = e.unescape()?;
+                match path.iter().rev().take(2).map(String::as_str).collect::<Vec<_>>()[..] {
+                    ["sitename", "siteinfo"] => site = Some(text.into_owned()),
+                    ["title", "page"] => page.title.push_str(&text),
+                    ["ns", "page"] => page.ns.push_str(&text),
+                    ["timestamp", "revision"] => revision.0.push_str(&text),
+                    ["text", "revision"] => revision.1.push_str(&text),
+                    _ => {}
+                }
+            }
+            Event::End(e) => {
+                path.pop();
+                match e.local_name().as_ref() {
+                    // Dumps with history hold every revision, the latest wins.
+                    b"revision" if revision.0 >= page.timestamp => (page.timestamp, page.text) = std::mem::take(&mut revision),
+                    b"page" => pages.push(std::mem::take(&mut page)),
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok((site, pages))
+}
+
+/// Reads the main namespace of a MediaWiki XML dump, skipping redirects.
+/// Subpages like `Guide/Install` are nested under the closest existing
+/// parent page. Returns the site name and the pages converted to Markdown,
+/// ordered by title.
+pub fn read_mediawiki_dump(xml: &str, files_dir: Option<&Path>) -> Result<(Option<String>, Vec<ImportPage>)> {
+    let (site, mut raw) = read_pages(xml)?;
+    raw.retain(|page| (page.ns.is_empty() || page.ns == "0") && !page.redirect && !page.title.trim().is_empty());
+    raw.sort_by(|a, b| a.title.split('/').cmp(b.title.split('/')));
+    let titles: HashSet<&str> = raw.iter().map(|page| page.title.as_str()).collect();
+    let pages = raw
+        .iter()
+        .map(|page| {
+            let parts: Vec<&str> = page.title.split('/').collect();
+            let parent = (1..parts.len()).rev().map(|end| parts[..end].join("/")).find(|title| titles.contains(title.as_str()));
+            let title = match &parent {
+                Some(parent) => page.title[parent.len() + 1..].to_string(),
+                None => page.title.clone(),
+            };
+            let path = page_file(&page.title);
+            let body = wikitext_to_markdown(&page.text, &path, files_dir);
+            let slug = slug_from_path(&path);
+            ImportPage { file: MarkdownFile { path, title, slug, body }, parent: parent.map(|parent| page_file(&parent)) }
+        })
+        .collect();
+    Ok((site, pages))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::import::{read_mediawiki_dump, wikitext_to_markdown};
+
+    #[test]
+    fn test_wikitext_to_markdown() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("Arch.png"), b"png").unwrap();
+        let text = "{{Infobox|name={{PAGENAME}}}}\n__TOC__\n== Setup ==\nRun '''make''' in ''src''<ref>Needs [[Tools#Make|GNU make]].</ref>.\n\
+* See [[Guide/Install]]s\n** and [https://example.com the site]\n# first\n[[File:arch.png|thumb|200px|The architecture]]\n[[File:missing.png|thumb|Missing]]\n\
+<syntaxhighlight lang=\"bash\">\nmake install\n</syntaxhighlight>\n{| class=\"wikitable\"\n! Name !! Value\n|-\n| a || style=\"color:red\" | [[Page|b]]\n|}\n[[Category:Docs]]";
+        let markdown = wikitext_to_markdown(text, "Guide.md", Some(dir.path()));
+        assert_eq!(
+            markdown,
+            "## Setup\nRun **make** in *src*[^1].\n- See [Guide/Installs](Guide/Install.md)\n    - and [the site](https://example.com)\n1. first\n![The architecture](Arch.png)\nMissing\n\n```bash\nmake install\n```\n\n| Name | Value |\n| --- | --- |\n| a | [b](Page.md) |\n\n[^1]: Needs [GNU make](Tools.md#Make).\n"
+        );
+        let markdown = wikitext_to_markdown("[[File:Arch.png]]", "Guide/Install.md", Some(dir.path()));
+        assert_eq!(markdown, "![](../Arch.png)\n");
+    }
+
+    #[test]
+    fn test_read_mediawiki_dump() {
+        let xml = r#"<mediawiki xmlns="http://www.mediawiki.org/xml/export-0.10/">
+  <siteinfo><sitename>Team Wiki</sitename></siteinfo>
+  <page><title>Guide/Install</title><ns>0</ns><id>2</id>
+    <revision><id>20</id><timestamp>2024-01-01T00:00:00Z</timestamp><text xml:space="preserve">Old</text></revision>
+    <revision><id>21</id><timestamp>2024-02-01T00:00:00Z</timestamp><text xml:space="preserve">Back to [[Guide]] &amp; more</text></revision>
+  </page>
+  <page><title>Guide</title><ns>0</ns><id>1</id><revision><timestamp>2024-01-01T00:00:00Z</timestamp><text xml:space="preserve">Hi</text></revision></page>
+  <page><title>Old guide</title><ns>0</ns><id>3</id><redirect title="Guide" /><revision><timestamp>2024-01-01T00:00:00Z</timestamp><text>#REDIRECT [[Guide]]</text></revision></page>
+  <page><title>Talk:Guide</title><ns>1</ns><id>4</id><revision><timestamp>2024-01-01T00:00:00Z</timestamp><text>Talk</text></revision></page>
+</mediawiki>"#;
+        let (site, pages) = read_mediawiki_dump(xml, None).unwrap();
+        assert_eq!(site.as_deref(), Some("Team Wiki"));
+        let paths: Vec<_> = pages.iter().map(|p| p.file.path.as_str()).collect();
+        assert_eq!(paths, vec!["Guide.md", "Guide/Install.md"]);
+        let install = &pages[1];
+        assert_eq!((install.file.title.as_str(), install.file.slug.as_str()), ("Install", "guide-install"));
+        assert_eq!(install.parent.as_deref(), Some("Guide.md"));
+        assert_eq!(install.file.body, "Back to [Guide](../Guide.md) & more\n");
+    }
+}
@@ -0,0 +1,538 @@
+use std::sync::LazyLock;
+use percent_encoding::percent_decode_str;
+use regex::{Captures, Regex};
+use serde_json::Value;
+
+use crate::dom::{self, Element, Node};
+
+mod from_markdown;
+mod markdown;
+mod writer;
+
+pub use from_markdown::{from_markdown, markdown_to_lake};
+pub use markdown::{to_markdown, MarkdownOptions, UnknownCardFallback};
+pub use writer::{encode_card_value, to_lake};
+
+/// A Lake document, as found in `DocSerializer.body_lake`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Document {
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Paragraph(Vec<Inline>),
+    Heading { level: u8, content: Vec<Inline> },
+    /// Lake keeps lists flat and marks nesting with an indent level.
+    List(List),
+    Quote(Vec<Block>),
+    Table(Table),
+    CodeBlock { language: String, code: String },
+    Image(Image),
+    Attachment(Attachment),
+    Diagram(Diagram),
+    Callout { kind: String, blocks: Vec<Block> },
+    Math(String),
+    ThematicBreak,
+    /// Any card without a dedicated variant.
+    Card(Card),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct List {
+    pub ordered: bool,
+    pub start: u32,
+    pub indent: u32,
+    pub items: Vec<ListItem>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListItem {
+    /// `Some` for task list items.
+    pub checked: Option<bool>,
+    pub content: Vec<Inline>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Table {
+    pub rows: Vec<Vec<TableCell>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TableCell {
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Strong(Vec<Inline>),
+    Emphasis(Vec<Inline>),
+    Strike(Vec<Inline>),
+    Underline(Vec<Inline>),
+    Code(String),
+    Link { href: String, content: Vec<Inline> },
+    Image(Image),
+    Attachment(Attachment),
+    Mention(Mention),
+    Math(String),
+    Break,
+    Card(Card),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Image {
+    pub src: String,
+    pub alt: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Attachment {
+    pub name: String,
+    pub src: String,
+    pub size: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiagramKind {
+    PlantUml,
+    Mermaid,
+    Graphviz,
+    Board,
+    Other(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagram {
+    pub kind: DiagramKind,
+    /// Diagram source when the card carries it.
+    pub code: Option<String>,
+    /// Rendered image.
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Mention {
+    pub name: String,
+    pub login: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Card {
+    pub name: String,
+    pub block: bool,
+    pub value: Value,
+}
+
+/// Decodes a card `value` attribute, `data:` followed by URL encoded JSON.
+pub fn decode_card_value(value: &str) -> Value {
+    let data = value.strip_prefix("data:").unwrap_or(value);
+    let decoded = percent_decode_str(data).decode_utf8_lossy();
+    serde_json::from_str(&decoded).unwrap_or_else(|_| Value::String(decoded.into_owned()))
+}
+
+static CARD: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"<card\b[^>]*>"#).unwrap());
+
+static CARD_VALUE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"value="(data:[^"]*)""#).unwrap());
+
+/// Rewrites the string fields of every card value in a Lake body. `f`
+/// returns the replacement, or `None` to keep a string.
+pub(crate) fn map_card_strings<F: FnMut(&str) -> Option<String>>(lake: &str, mut f: F) -> String {
+    CARD.replace_all(lake, |cap: &Captures| {
+        CARD_VALUE
+            .replace(&cap[0], |v: &Captures| {
+                let mut decoded = decode_card_value(&v[1]);
+                if map_json_strings(&mut decoded, &mut f) {
+                    format!(r#"value="{}""#, encode_card_value(&decoded))
+                } else {
+                    v[0].to_string()
+                }
+            })
+            .into_owned()
+    })
+    .into_owned()
+}
+
+fn map_json_strings<F: FnMut(&str) -> Option<String>>(value: &mut Value, f: &mut F) -> bool {
+    match value {
+        Value::String(s) => match f(s) {
+            Some(new) => {
+                *s = new;
+                true
+            }
+            None => false,
+        },
+        Value::Array(items) => items.iter_mut().fold(false, |changed, item| map_json_strings(item, f) | changed),
+        Value::Object(map) => map.values_mut().fold(false, |changed, item| map_json_strings(item, f) | changed),
+        _ => false,
+    }
+}
+
+pub fn parse(lake: &str) -> Document {
+    Document { blocks: blocks(&dom::parse(lake)) }
+}
+
+fn str_field(value: &Value, key: &str) -> String {
+    value.get(key).and_then(Value::as_str).unwrap_or_default().to_string()
+}
+
+fn u32_field(value: &Value, key: &str) -> Option<u32> {
+    value.get(key).and_then(Value::as_f64).map(|n| n as u32)
+}
+
+fn card_of(el: &Element) -> Card {
+    Card {
+        name: el.attr("name").unwrap_or_default().to_string(),
+        block: el.attr("type") != Some("inline"),
+        value: decode_card_value(el.attr("value").unwrap_or_default()),
+    }
+}
+
+fn image_of(value: &Value) -> Image {
+    Image {
+        src: str_field(value, "src"),
+        alt: str_field(value, "name"),
+        width: u32_field(value, "width"),
+        height: u32_field(value, "height"),
+    }
+}
+
+fn attachment_of(value: &Value) -> Attachment {
+    Attachment {
+        name: str_field(value, "name"),
+        src: str_field(value, "src"),
+        size: value.get("size").and_then(Value::as_u64),
+    }
+}
+
+fn diagram_of(card: &Card) -> Diagram {
+    let kind = match card.name.as_str() {
+        "board" => DiagramKind::Board,
+        "puml" | "plantuml" => DiagramKind::PlantUml,
+        "mermaid" => DiagramKind::Mermaid,
+        _ => match card.value.get("type").and_then(Value::as_str).unwrap_or_default() {
+            "puml" | "plantuml" => DiagramKind::PlantUml,
+            "mermaid" => DiagramKind::Mermaid,
+            "graphviz" => DiagramKind::Graphviz,
+            other => DiagramKind::Other(other.to_string()),
+        },
+    };
+    let code = card.value.get("code").and_then(Value::as_str).map(str::to_string);
+    let url = card.value.get("url").and_then(Value::as_str).filter(|u| !u.is_empty()).map(str::to_string);
+    Diagram { kind, code, url }
+}
+
+fn card_block(el: &Element) -> Option<Block> {
+    let card = card_of(el);
+    Some(match card.name.as_str() {
+        "codeblock" => Block::CodeBlock {
+            language: str_field(&card.value, "mode"),
+            code: str_field(&card.value, "code"),
+        },
+        "image" => Block::Image(image_of(&card.value)),
+        "file" => Block::Attachment(attachment_of(&card.value)),
+        "diagram" | "board" | "puml" | "plantuml" | "mermaid" => Block::Diagram(diagram_of(&card)),
+        "math" => Block::Math(str_field(&card.value, "code")),
+        "hr" => Block::ThematicBreak,
+        "checkbox" => return None,
+        _ => Block::Card(card),
+    })
+}
+
+fn card_inline(el: &Element) -> Option<Inline> {
+    let card = card_of(el);
+    Some(match card.name.as_str() {
+        "image" => Inline::Image(image_of(&card.value)),
+        "file" => Inline::Attachment(attachment_of(&card.value)),
+        "mention" => Inline::Mention(Mention {
+            name: str_field(&card.value, "name"),
+            login: str_field(&card.value, "login"),
+        }),
+        "math" => Inline::Math(str_field(&card.value, "code")),
+        "checkbox" => return None,
+        _ => Inline::Card(card),
+    })
+}
+
+fn is_block_tag(tag: &str) -> bool {
+    matches!(
+        tag,
+        "p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "ul" | "ol" | "blockquote" | "table" | "pre" | "hr" | "div" | "section" | "article" | "main" | "header" | "footer" | "figure" | "body" | "html"
+    )
+}
+
+fn is_block_node(node: &Node) -> bool {
+    match node {
+        Node::Element(el) if el.tag == "card" => el.attr("type") != Some("inline"),
+        Node::Element(el) => is_block_tag(&el.tag),
+        Node::Text(_) => false,
+    }
+}
+
+fn heading_level(tag: &str) -> Option<u8> {
+    match tag {
+        "h1" => Some(1),
+        "h2" => Some(2),
+        "h3" => Some(3),
+        "h4" => Some(4),
+        "h5" => Some(5),
+        "h6" => Some(6),
+        _ => None,
+    }
+}
+
+pub(crate) fn blocks(nodes: &[Node]) -> Vec<Block> {
+    let mut out = Vec::new();
+    let mut pending: Vec<Node> = Vec::new();
+    for node in nodes {
+        if !is_block_node(node) {
+            if !matches!(node, Node::Element(el) if el.tag == "meta" || el.tag == "head" || el.tag == "title") {
+                pending.push(node.clone());
+            }
+            continue;
+        }
+        flush_paragraph(&mut pending, &mut out);
+        let Node::Element(el) = node else { continue };
+        block(el, &mut out);
+    }
+    flush_paragraph(&mut pending, &mut out);
+    out
+}
+
+fn flush_paragraph(pending: &mut Vec<Node>, out: &mut Vec<Block>) {
+    let content = inlines(pending);
+    pending.clear();
+    if !is_blank(&content) {
+        out.push(Block::Paragraph(content));
+    }
+}
+
+fn is_blank(content: &[Inline]) -> bool {
+    content.iter().all(|inline| matches!(inline, Inline::Text(text) if text.trim().is_empty()))
+}
+
+fn block(el: &Element, out: &mut Vec<Block>) {
+    if let Some(level) = heading_level(&el.tag) {
+        out.push(Block::Heading { level, content: inlines(&el.children) });
+        return;
+    }
+    match el.tag.as_str() {
+        "p" => {
+            if el.children.iter().any(is_block_node) {
+                out.extend(blocks(&el.children));
+            } else {
+                out.push(Block::Paragraph(inlines(&el.children)));
+            }
+        }
+        "ul" | "ol" => list(el, out),
+        "blockquote" => out.push(Block::Quote(blocks(&el.children))),
+        "table" => out.push(Block::Table(table(el))),
+        "pre" => {
+            let language = el
+                .attr("data-language")
+                .map(str::to_string)
+                .or_else(|| code_language(el))
+                .unwrap_or_default();
+            out.push(Block::CodeBlock { language, code: el.text() });
+        }
+        "hr" => out.push(Block::ThematicBreak),
+        "card" => out.extend(card_block(el)),
+        _ if is_callout(el) => out.push(Block::Callout {
+            kind: el.attr("data-type").unwrap_or("info").to_string(),
+            blocks: blocks(&el.children),
+        }),
+        _ => out.extend(blocks(&el.children)),
+    }
+}
+
+fn is_callout(el: &Element) -> bool {
+    el.tag == "div" && el.attr("class").map(|c| c.contains("alert")).unwrap_or(false)
+}
+
+fn code_language(pre: &Element) -> Option<String> {
+    let classes = pre.child_elements().find(|el| el.tag == "code").and_then(|code| code.attr("class")).or(pre.attr("class"))?;
+    classes.split_whitespace().find_map(|c| c.strip_prefix("language-")).map(str::to_string)
+}
+
+fn list(el: &Element, out: &mut Vec<Block>) {
+    let indent = el
+        .attr("data-lake-indent")
+        .or(el.attr("lake-indent"))
+        .and_then(|i| i.parse().ok())
+        .unwrap_or(0);
+    let mut items = Vec::new();
+    let mut nested = Vec::new();
+    for li in el.child_elements().filter(|child| child.tag == "li") {
+        let mut checked = None;
+        let mut content_nodes = Vec::new();
+        for child in &li.children {
+            match child {
+                Node::Element(child) if child.tag == "card" && child.attr("name") == Some("checkbox") => {
+                    checked = Some(card_of(child).value.as_bool().unwrap_or(false));
+                }
+                Node::Element(child) if child.tag == "input" && child.attr("type") == Some("checkbox") => {
+                    checked = Some(child.attr("checked").is_some());
+                }
+                Node::Element(child) if child.tag == "ul" || child.tag == "ol" => nested.push(child.clone()),
+                child => content_nodes.push(child.clone()),
+            }
+        }
+        let content_nodes: Vec<Node> = content_nodes
+            .into_iter()
+            .flat_map(|node| match node {
+                Node::Element(p) if p.tag == "p" => p.children,
+                node => vec![node],
+            })
+            .collect();
+        items.push(ListItem { checked, content: inlines(&content_nodes) });
+    }
+    out.push(Block::List(List {
+        ordered: el.tag == "ol",
+        start: el.attr("start").and_then(|s| s.parse().ok()).unwrap_or(1),
+        indent,
+        items,
+    }));
+    // Nested HTML lists are flattened into Lake's indented form.
+    for mut child in nested {
+        if child.attr("data-lake-indent").is_none() {
+            child.attrs.push(("data-lake-indent".to_string(), (indent + 1).to_string()));
+        }
+        list(&child, out);
+    }
+}
+
+fn table_rows<'a>(el: &'a Element, rows: &mut Vec<&'a Element>) {
+    for child in el.child_elements() {
+        match child.tag.as_str() {
+            "tr" => rows.push(child),
+            "thead" | "tbody" | "tfoot" => table_rows(child, rows),
+            _ => {}
+        }
+    }
+}
+
+fn table(el: &Element) -> Table {
+    let mut rows = Vec::new();
+    table_rows(el, &mut rows);
+    Table {
+        rows: rows
+            .into_iter()
+            .map(|tr| {
+                tr.child_elements()
+                    .filter(|cell| cell.tag == "td" || cell.tag == "th")
+                    .map(|cell| TableCell { blocks: blocks(&cell.children) })
+                    .collect()
+            })
+            .collect(),
+    }
+}
+
+pub(crate) fn inlines(nodes: &[Node]) -> Vec<Inline> {
+    let mut out = Vec::new();
+    for node in nodes {
+        match node {
+            Node::Text(text) => push_text(&mut out, text),
+            Node::Element(el) => inline(el, &mut out),
+        }
+    }
+    out
+}
+
+fn push_text(out: &mut Vec<Inline>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    if let Some(Inline::Text(prev)) = out.last_mut() {
+        prev.push_str(text);
+    } else {
+        out.push(Inline::Text(text.to_string()));
+    }
+}
+
+fn inline(el: &Element, out: &mut Vec<Inline>) {
+    match el.tag.as_str() {
+        "strong" | "b" => out.push(Inline::Strong(inlines(&el.children))),
+        "em" | "i" => out.push(Inline::Emphasis(inlines(&el.children))),
+        "del" | "s" | "strike" => out.push(Inline::Strike(inlines(&el.children))),
+        "u" => out.push(Inline::Underline(inlines(&el.children))),
+        "code" => out.push(Inline::Code(el.text())),
+        "a" => out.push(Inline::Link {
+            href: el.attr("href").unwrap_or_default().to_string(),
+            content: inlines(&el.children),
+        }),
+        "img" => out.push(Inline::Image(Image {
+            src: el.attr("src").unwrap_or_default().to_string(),
+            alt: el.attr("alt").unwrap_or_default().to_string(),
+            width: el.attr("width").and_then(|w| w.parse().ok()),
+            height: el.attr("height").and_then(|h| h.parse().ok()),
+        })),
+        "br" => out.push(Inline::Break),
+        "card" => out.extend(card_inline(el)),
+        "meta" | "script" | "style" | "colgroup" | "col" => {}
+        _ => {
+            for inline in inlines(&el.children) {
+                match inline {
+                    Inline::Text(text) => push_text(out, &text),
+                    inline => out.push(inline),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::lake::{decode_card_value, parse, Block, Diagram, DiagramKind, Image, Inline, List, ListItem, Mention};
+
+    #[test]
+    fn test_decode_card_value() {
+        assert_eq!(decode_card_value("data:%7B%22mode%22%3A%22rust%22%7D"), json!({"mode": "rust"}));
+    }
+
+    #[test]
+    fn test_parse() {
+        let lake = concat!(
+            r#"<!doctype lake><meta name="doc-version" content="1" />"#,
+            r#"<h2 id="a"><span>Title</span></h2>"#,
+            r#"<p>Hi <strong>there</strong> <a href="https://example.com">link</a>"#,
+            r#"<card type="inline" name="mention" value="data:%7B%22name%22%3A%22Cat%22%2C%22login%22%3A%22cat%22%7D"></card></p>"#,
+            r#"<ul data-lake-indent="1"><li>one</li><li><card type="inline" name="checkbox" value="data:true"></card>two</li></ul>"#,
+            r#"<card type="block" name="codeblock" value="data:%7B%22mode%22%3A%22rust%22%2C%22code%22%3A%22fn%20main()%20%7B%7D%22%7D"></card>"#,
+            r#"<p><card type="inline" name="image" value="data:%7B%22src%22%3A%22https%3A%2F%2Fcdn.nlark.com%2Fa.png%22%2C%22width%22%3A100%7D"></card></p>"#,
+            r#"<card type="block" name="diagram" value="data:%7B%22type%22%3A%22mermaid%22%2C%22code%22%3A%22graph%20TD%22%7D"></card>"#,
+            r#"<table><tbody><tr><td><p>a</p></td><td><p>b</p></td></tr><tr><td>c</td><td>d</td></tr></tbody></table>"#,
+        );
+        let doc = parse(lake);
+        assert_eq!(doc.blocks.len(), 7);
+        assert_eq!(doc.blocks[0], Block::Heading { level: 2, content: vec![Inline::Text("Title".to_string())] });
+        assert_eq!(doc.blocks[1], Block::Paragraph(vec![
+            Inline::Text("Hi ".to_string()),
+            Inline::Strong(vec![Inline::Text("there".to_string())]),
+            Inline::Text(" ".to_string()),
+            Inline::Link { href: "https://example.com".to_string(), content: vec![Inline::Text("link".to_string())] },
+            Inline::Mention(Mention { name: "Cat".to_string(), login: "cat".to_string() }),
+        ]));
+        assert_eq!(doc.blocks[2], Block::List(List {
+            ordered: false,
+            start: 1,
+            indent: 1,
+            items: vec![
+                ListItem { checked: None, content: vec![Inline::Text("one".to_string())] },
+                ListItem { checked: Some(true), content: vec![Inline::Text("two".to_string())] },
+            ],
+        }));
+        assert_eq!(doc.blocks[3], Block::CodeBlock { language: "rust".to_string(), code: "fn main() {}".to_string() });
+        assert_eq!(doc.blocks[4], Block::Paragraph(vec![Inline::Image(Image {
+            src: "https://cdn.nlark.com/a.png".to_string(),
+            width: Some(100),
+            ..Image::default()
+        })]));
+        assert_eq!(doc.blocks[5], Block::Diagram(Diagram { kind: DiagramKind::Mermaid, code: Some("graph TD".to_string()), url: None }));
+        let Block::Table(table) = &doc.blocks[6] else { panic!() };
+        assert_eq!(table.rows.len(), 2);
+        assert_eq!(table.rows[1][1].blocks, vec![Block::Paragraph(vec![Inline::Text("d".to_string())])]);
+    }
+}
@@ -0,0 +1,275 @@
+use pulldown_cmark::{BlockQuoteKind, CodeBlockKind, Event, Options, Parser, Tag};
+
+use crate::dom;
+use crate::lake::writer::to_lake;
+use crate::lake::{self, Block, Diagram, DiagramKind, Document, Image, Inline, List, ListItem, Table, TableCell};
+
+/// Markdown parsed into a tree, since the AST conversion needs to look at
+/// whole containers rather than at single events.
+enum Item<'a> {
+    Container(Tag<'a>, Vec<Item<'a>>),
+    Leaf(Event<'a>),
+}
+
+fn tree(markdown: &str) -> Vec<Item<'_>> {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS | Options::ENABLE_MATH | Options::ENABLE_GFM;
+    let mut stack: Vec<(Option<Tag>, Vec<Item>)> = vec![(None, Vec::new())];
+    for event in Parser::new_ext(markdown, options) {
+        match event {
+            Event::Start(tag) => stack.push((Some(tag), Vec::new())),
+            Event::End(_) => {
+                let (tag, children) = stack.pop().unwrap();
+                stack.last_mut().unwrap().1.push(Item::Container(tag.unwrap(), children));
+            }
+            event => stack.last_mut().unwrap().1.push(Item::Leaf(event)),
+        }
+    }
+    stack.pop().unwrap().1
+}
+
+/// Parses CommonMark with the GFM extensions into a Lake document. Fenced
+/// `mermaid`, `plantuml` and `dot` blocks become diagram cards.
+pub fn from_markdown(markdown: &str) -> Document {
+    Document { blocks: blocks(&tree(markdown)) }
+}
+
+fn blocks(items: &[Item]) -> Vec<Block> {
+    let mut out = Vec::new();
+    for item in items {
+        match item {
+            Item::Container(tag, children) => block(tag, children, &mut out),
+            Item::Leaf(Event::Rule) => out.push(Block::ThematicBreak),
+            Item::Leaf(Event::Html(html)) => out.extend(lake::blocks(&dom::parse(html))),
+            Item::Leaf(Event::DisplayMath(code)) => out.push(Block::Math(code.to_string())),
+            Item::Leaf(_) => {}
+        }
+    }
+    out
+}
+
+fn block(tag: &Tag, children: &[Item], out: &mut Vec<Block>) {
+    match tag {
+        Tag::Paragraph => match children {
+            [Item::Leaf(Event::DisplayMath(code))] => out.push(Block::Math(code.to_string())),
+            _ => out.push(Block::Paragraph(inlines(children))),
+        },
+        Tag::Heading { level, .. } => out.push(Block::Heading { level: *level as u8, content: inlines(children) }),
+        Tag::BlockQuote(Some(kind)) => out.push(Block::Callout { kind: callout_kind(*kind).to_string(), blocks: blocks(children) }),
+        Tag::BlockQuote(None) => out.push(Block::Quote(blocks(children))),
+        Tag::CodeBlock(kind) => {
+            let language = match kind {
+                CodeBlockKind::Fenced(info) => info.split_whitespace().next().unwrap_or_default().to_string(),
+                CodeBlockKind::Indented => String::new(),
+            };
+            out.push(code_block(language, text(children)));
+        }
+        Tag::HtmlBlock => {
+            let html: String = children
+                .iter()
+                .filter_map(|item| match item {
+                    Item::Leaf(Event::Html(html)) => Some(html.as_ref()),
+                    _ => None,
+                })
+                .collect();
+            out.extend(lake::blocks(&dom::parse(&html)));
+        }
+        Tag::List(start) => list(*start, children, 0, out),
+        Tag::Table(_) => out.push(Block::Table(table(children))),
+        _ => out.extend(blocks(children)),
+    }
+}
+
+fn code_block(language: String, code: String) -> Block {
+    let kind = match language.to_ascii_lowercase().as_str() {
+        "mermaid" => DiagramKind::Mermaid,
+        "plantuml" | "puml" => DiagramKind::PlantUml,
+        "dot" | "graphviz" => DiagramKind::Graphviz,
+        _ => return Block::CodeBlock { language, code: code.trim_end_matches('\n').to_string() },
+    };
+    Block::Diagram(Diagram { kind, code: Some(code.trim_end_matches('\n').to_string()), url: None })
+}
+
+fn callout_kind(kind: BlockQuoteKind) -> &'static str {
+    match kind {
+        BlockQuoteKind::Note => "info",
+        BlockQuoteKind::Tip => "tips",
+        BlockQuoteKind::Important => "color5",
+        BlockQuoteKind::Warning => "warning",
+        BlockQuoteKind::Caution => "danger",
+    }
+}
+
+/// Emits nested lists as separate Lake lists with a higher indent. Block
+/// content other than paragraphs and lists follows the list it was in.
+fn list(start: Option<u64>, items: &[Item], indent: u32, out: &mut Vec<Block>) {
+    let ordered = start.is_some();
+    let mut current = List { ordered, start: start.unwrap_or(1) as u32, indent, items: Vec::new() };
+    let mut number = current.start;
+    for item in items {
+        let Item::Container(Tag::Item, children) = item else { continue };
+        let mut checked = None;
+        let mut content = Vec::new();
+        let mut after = Vec::new();
+        for child in children {
+            match child {
+                Item::Leaf(Event::TaskListMarker(done)) => checked = Some(*done),
+                Item::Container(Tag::Paragraph, inner) => {
+                    if !content.is_empty() {
+                        content.push(Inline::Break);
+                    }
+                    content.extend(inlines(inner));
+                }
+                Item::Container(..) => after.push(child),
+                Item::Leaf(_) => content.extend(inlines(std::slice::from_ref(child))),
+            }
+        }
+        current.items.push(ListItem { checked, content });
+        number += 1;
+        if after.is_empty() {
+            continue;
+        }
+        out.push(Block::List(std::mem::replace(
+            &mut current,
+            List { ordered, start: if ordered { number } else { 1 }, indent, items: Vec::new() },
+        )));
+        for child in after {
+            match child {
+                Item::Container(Tag::List(start), inner) => list(*start, inner, indent + 1, out),
+                child => out.extend(blocks(std::slice::from_ref(child))),
+            }
+        }
+    }
+    if !current.items.is_empty() {
+        out.push(Block::List(current));
+    }
+}
+
+fn table(children: &[Item]) -> Table {
+    let mut rows = Vec::new();
+    for child in children {
+        let Item::Container(tag, cells) = child else { continue };
+        if !matches!(tag, Tag::TableHead | Tag::TableRow) {
+            continue;
+        }
+        rows.push(
+            cells
+                .iter()
+                .filter_map(|cell| match cell {
+                    Item::Container(Tag::TableCell, content) => Some(TableCell { blocks: vec![Block::Paragraph(inlines(content))] }),
+                    _ => None,
+                })
+                .collect(),
+        );
+    }
+    Table { rows }
+}
+
+fn text(items: &[Item]) -> String {
+    let mut out = String::new();
+    for item in items {
+        match item {
+            Item::Leaf(Event::Text(text) | Event::Code(text) | Event::InlineMath(text) | Event::DisplayMath(text)) => out.push_str(text),
+            Item::Leaf(Event::SoftBreak | Event::HardBreak) => out.push('\n'),
+            Item::Container(_, children) => out.push_str(&text(children)),
+            Item::Leaf(_) => {}
+        }
+    }
+    out
+}
+
+fn inlines(items: &[Item]) -> Vec<Inline> {
+    let mut out = Vec::new();
+    for item in items {
+        match item {
+            Item::Leaf(event) => match event {
+                Event::Text(text) => push_text(&mut out, text),
+                Event::Code(code) => out.push(Inline::Code(code.to_string())),
+                Event::InlineMath(code) | Event::DisplayMath(code) => out.push(Inline::Math(code.to_string())),
+                Event::SoftBreak => push_text(&mut out, "\n"),
+                Event::HardBreak => out.push(Inline::Break),
+                Event::InlineHtml(html) if html.trim_start().starts_with("<br") => out.push(Inline::Break),
+                Event::FootnoteReference(name) => push_text(&mut out, &format!("[^{}]", name)),
+                _ => {}
+            },
+            Item::Container(tag, children) => match tag {
+                Tag::Emphasis => out.push(Inline::Emphasis(inlines(children))),
+                Tag::Strong => out.push(Inline::Strong(inlines(children))),
+                Tag::Strikethrough => out.push(Inline::Strike(inlines(children))),
+                Tag::Link { dest_url, .. } => out.push(Inline::Link { href: dest_url.to_string(), content: inlines(children) }),
+                Tag::Image { dest_url, .. } => out.push(Inline::Image(Image {
+                    src: dest_url.to_string(),
+                    alt: text(children),
+                    ..Image::default()
+                })),
+                _ => {
+                    for inline in inlines(children) {
+                        match inline {
+                            Inline::Text(text) => push_text(&mut out, &text),
+                            inline => out.push(inline),
+                        }
+                    }
+                }
+            },
+        }
+    }
+    out
+}
+
+fn push_text(out: &mut Vec<Inline>, text: &str) {
+    if let Some(Inline::Text(prev)) = out.last_mut() {
+        prev.push_str(text);
+    } else {
+        out.push(Inline::Text(text.to_string()));
+    }
+}
+
+/// Converts Markdown straight to a Lake body, for `DocFormat::Lake` uploads.
+pub fn markdown_to_lake(markdown: &str) -> String {
+    to_lake(&from_markdown(markdown))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lake::from_markdown::from_markdown;
+    use crate::lake::{parse, to_lake, Block, DiagramKind, Inline};
+
+    #[test]
+    fn test_from_markdown() {
+        let markdown = "# Title
+
+- one
+    - nested
+- two
+
+| a | b |
+| --- | --- |
+| x \\| y | z |
+
+```rust
+let a = 1;
+```
+
+```mermaid
+graph TD
+```
+
+> [!TIP]
+> Hint
+";
+        let doc = from_markdown(markdown);
+        let Block::List(outer) = &doc.blocks[1] else { panic!() };
+        assert_eq!((outer.indent, outer.items.len()), (0, 1));
+        let Block::List(nested) = &doc.blocks[2] else { panic!() };
+        assert_eq!((nested.indent, nested.items[0].content.clone()), (1, vec![Inline::Text("nested".to_string())]));
+        let Block::List(rest) = &doc.blocks[3] else { panic!() };
+        assert_eq!((rest.indent, rest.items.len()), (0, 1));
+        let Block::Table(table) = &doc.blocks[4] else { panic!() };
+        assert_eq!(table.rows[1][0].blocks, vec![Block::Paragraph(vec![Inline::Text("x | y".to_string())])]);
+        assert_eq!(doc.blocks[5], Block::CodeBlock { language: "rust".to_string(), code: "let a = 1;".to_string() });
+        let Block::Diagram(diagram) = &doc.blocks[6] else { panic!() };
+        assert_eq!(diagram.kind, DiagramKind::Mermaid);
+        assert!(matches!(&doc.blocks[7], Block::Callout { kind, .. } if kind == "tips"));
+
+        assert_eq!(parse(&to_lake(&doc)), doc);
+    }
+}
@@ -0,0 +1,352 @@
+use crate::lake::writer::card_markup;
+use crate::links::DEFAULT_WEB_BASE;
+use crate::lake::{Block, Card, Diagram, DiagramKind, Document, Inline, List, Table};
+
+/// What to do with cards that have no Markdown equivalent.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum UnknownCardFallback {
+    /// Drop the card.
+    #[default]
+    Skip,
+    /// Keep the card name and value in an HTML comment.
+    Comment,
+    /// Keep the card as raw Lake markup.
+    Raw,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MarkdownOptions {
+    pub unknown_cards: UnknownCardFallback,
+    /// Prefer the rendered image of a diagram over its source.
+    pub diagrams_as_images: bool,
+    /// Used for mention links, defaults to `https://www.yuque.com`.
+    pub base_url: Option<String>,
+}
+
+pub fn to_markdown(doc: &Document, options: &MarkdownOptions) -> String {
+    let mut out = render_blocks(&doc.blocks, options);
+    let trimmed = out.trim_end().len();
+    out.truncate(trimmed);
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+fn render_blocks(blocks: &[Block], options: &MarkdownOptions) -> String {
+    let mut out = String::new();
+    let mut prev_list: Option<&List> = None;
+    for block in blocks {
+        let list = match block {
+            Block::List(list) => Some(list),
+            _ => None,
+        };
+        if let (Some(prev), Some(list)) = (prev_list, list) {
+            // Lake lists that only change the indent form one Markdown list,
+            // separate lists at the top level need something in between.
+            if continues(prev, list) {
+                if out.ends_with("\n\n") {
+                    out.pop();
+                }
+            } else {
+                out.push_str("<!-- -->\n\n");
+            }
+        }
+        prev_list = list;
+        let rendered = render_block(block, options);
+        if rendered.is_empty() {
+            continue;
+        }
+        out.push_str(&rendered);
+        out.push_str("\n\n");
+    }
+    out
+}
+
+fn render_block(block: &Block, options: &MarkdownOptions) -> String {
+    match block {
+        Block::Paragraph(content) => escape_line_starts(render_inlines(content, options).trim()),
+        Block::Heading { level, content } => format!("{} {}", "#".repeat(*level as usize), render_inlines(content, options).trim()),
+        Block::List(list) => render_list(list, options),
+        Block::Quote(blocks) => prefix_lines(render_blocks(blocks, options).trim_end(), "> "),
+        Block::Table(table) => render_table(table, options),
+        Block::CodeBlock { language, code } => fenced(language, code),
+        Block::Image(image) => format!("![{}]({})", escape(&image.alt), escape_url(&image.src)),
+        Block::Attachment(file) => format!("[{}]({})", escape(&file.name), escape_url(&file.src)),
+        Block::Diagram(diagram) => render_diagram(diagram, options),
+        Block::Callout { kind, blocks } => {
+            let body = render_blocks(blocks, options);
+            prefix_lines(&format!("[!{}]\n{}", admonition(kind), body.trim_end()), "> ")
+        }
+        Block::Math(code) => format!("$$\n{}\n$$", code.trim()),
+        Block::ThematicBreak => "---".to_string(),
+        Block::Card(card) => render_card(card, options),
+    }
+}
+
+fn continues(prev: &List, next: &List) -> bool {
+    next.indent > 0 || prev.indent != next.indent || (prev.ordered && next.ordered && next.start as usize == prev.start as usize + prev.items.len())
+}
+
+fn render_list(list: &List, options: &MarkdownOptions) -> String {
+    let indent = "    ".repeat(list.indent as usize);
+    list.items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let marker = if list.ordered { format!("{}.", list.start as usize + i) } else { "-".to_string() };
+            let task = match item.checked {
+                Some(true) => "[x] ",
+                Some(false) => "[ ] ",
+                None => "",
+            };
+            format!("{}{} {}{}", indent, marker, task, escape_line_starts(render_inlines(&item.content, options).trim()))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn render_table(table: &Table, options: &MarkdownOptions) -> String {
+    let columns = table.rows.iter().map(Vec::len).max().unwrap_or(0);
+    if columns == 0 {
+        return String::new();
+    }
+    let mut lines = Vec::new();
+    for (i, row) in table.rows.iter().enumerate() {
+        let cells: Vec<String> = (0..columns)
+            .map(|c| {
+                let cell = row.get(c).map(|cell| render_blocks(&cell.blocks, options)).unwrap_or_default();
+                cell.trim().replace('|', "\\|").replace("\n\n", "<br>").replace('\n', "<br>")
+            })
+            .collect();
+        lines.push(format!("| {} |", cells.join(" | ")));
+        if i == 0 {
+            lines.push(format!("|{}", " --- |".repeat(columns)));
+        }
+    }
+    lines.join("\n")
+}
+
+fn render_diagram(diagram: &Diagram, options: &MarkdownOptions) -> String {
+    let language = match &diagram.kind {
+        DiagramKind::PlantUml => Some("plantuml"),
+        DiagramKind::Mermaid => Some("mermaid"),
+        DiagramKind::Graphviz => Some("dot"),
+        DiagramKind::Board | DiagramKind::Other(_) => None,
+    };
+    let source = language.zip(diagram.code.as_deref()).map(|(language, code)| fenced(language, code));
+    let image = diagram.url.as_ref().map(|url| format!("![diagram]({})", escape_url(url)));
+    let (first, second) = if options.diagrams_as_images { (image, source) } else { (source, image) };
+    first.or(second).unwrap_or_default()
+}
+
+fn render_card(card: &Card, options: &MarkdownOptions) -> String {
+    match options.unknown_cards {
+        UnknownCardFallback::Skip => String::new(),
+        UnknownCardFallback::Comment => format!("<!-- card {}: {} -->", card.name, card.value.to_string().replace("--", "\\u002d\\u002d")),
+        UnknownCardFallback::Raw => card_markup(card),
+    }
+}
+
+fn admonition(kind: &str) -> &'static str {
+    match kind {
+        "tips" | "success" => "TIP",
+        "warning" => "WARNING",
+        "danger" | "error" => "CAUTION",
+        "important" | "color5" => "IMPORTANT",
+        _ => "NOTE",
+    }
+}
+
+fn prefix_lines(text: &str, prefix: &str) -> String {
+    text.lines().map(|line| format!("{}{}", prefix, line).trim_end().to_string()).collect::<Vec<_>>().join("\n")
+}
+
+fn fenced(language: &str, code: &str) -> String {
+    let fence = "`".repeat(longest_run(code, '`').max(2) + 1);
+    format!("{}{}\n{}\n{}", fence, language, code.trim_end_matches('\n'), fence)
+}
+
+fn longest_run(text: &str, ch: char) -> usize {
+    let mut longest = 0;
+    let mut current = 0;
+    for c in text.chars() {
+        if c == ch {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 0;
+        }
+    }
+    longest
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\u{a0}' => out.push(' '),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Escapes what would otherwise start a heading, list, quote or thematic
+/// break at the beginning of a line.
+fn escape_line_starts(text: &str) -> String {
+    text.split('\n')
+        .map(|line| {
+            let rest = line.trim_start_matches(' ');
+            let (pad, rest) = line.split_at(line.len() - rest.len());
+            let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            if rest.starts_with(['#', '-', '+', '>']) {
+                format!("{}\\{}", pad, rest)
+            } else if digits > 0 && rest[digits..].starts_with(['.', ')']) {
+                format!("{}{}\\{}", pad, &rest[..digits], &rest[digits..])
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn escape_url(url: &str) -> String {
+    url.replace(' ', "%20").replace('(', "\\(").replace(')', "\\)")
+}
+
+fn render_inlines(content: &[Inline], options: &MarkdownOptions) -> String {
+    content.iter().map(|inline| render_inline(inline, options)).collect()
+}
+
+fn render_inline(inline: &Inline, options: &MarkdownOptions) -> String {
+    match inline {
+        Inline::Text(text) => escape(text),
+        Inline::Strong(content) => wrap("**", &render_inlines(content, options)),
+        Inline::Emphasis(content) => wrap("*", &render_inlines(content, options)),
+        Inline::Strike(content) => wrap("~~", &render_inlines(content, options)),
+        Inline::Underline(content) => format!("<u>{}</u>", render_inlines(content, options)),
+        Inline::Code(code) => {
+            let fence = "`".repeat(longest_run(code, '`') + 1);
+            let pad = if code.starts_with('`') || code.ends_with('`') { " " } else { "" };
+            format!("{}{}{}{}{}", fence, pad, code, pad, fence)
+        }
+        Inline::Link { href, content } => format!("[{}]({})", render_inlines(content, options), escape_url(href)),
+        Inline::Image(image) => format!("![{}]({})", escape(&image.alt), escape_url(&image.src)),
+        Inline::Attachment(file) => format!("[{}]({})", escape(&file.name), escape_url(&file.src)),
+        Inline::Mention(mention) => {
+            let base = options.base_url.as_deref().unwrap_or(DEFAULT_WEB_BASE).trim_end_matches('/');
+            format!("[@{}]({}/{})", mention.name, base, mention.login)
+        }
+        Inline::Math(code) => format!("${}$", code.trim()),
+        Inline::Break => "  \n".to_string(),
+        Inline::Card(card) => render_card(card, options),
+    }
+}
+
+/// Keeps surrounding whitespace outside of the markers, which CommonMark
+/// requires for them to be recognized.
+fn wrap(marker: &str, text: &str) -> String {
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return text.to_string();
+    }
+    let start = &text[..text.len() - text.trim_start().len()];
+    let end = &text[text.trim_end().len()..];
+    format!("{}{}{}{}{}", start, marker, trimmed, marker, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lake::markdown::{to_markdown, MarkdownOptions, UnknownCardFallback};
+    use crate::lake::{parse, Block, Document, Inline, Mention};
+
+    #[test]
+    fn test_to_markdown() {
+        let lake = concat!(
+            r#"<h1>Title</h1><p>Some <strong>bold </strong>and <code>code</code> with a * star.</p>"#,
+            r#"<ul><li>one</li></ul><ul data-lake-indent="1"><li>nested</li></ul>"#,
+            r#"<card type="block" name="codeblock" value="data:%7B%22mode%22%3A%22rust%22%2C%22code%22%3A%22let%20a%20%3D%201%3B%22%7D"></card>"#,
+            r#"<table><tr><td>a|b</td><td>c</td></tr><tr><td>1</td><td>2</td></tr></table>"#,
+            r#"<card type="block" name="diagram" value="data:%7B%22type%22%3A%22puml%22%2C%22code%22%3A%22A%20-%3E%20B%22%2C%22url%22%3A%22https%3A%2F%2Fx%2Fd.svg%22%7D"></card>"#,
+            r#"<div class="ne-alert" data-type="warning"><p>Careful</p></div>"#,
+            r#"<card type="block" name="youku" value="data:%7B%22id%22%3A1%7D"></card>"#,
+        );
+        let doc = parse(lake);
+        let expected = "# Title
+
+Some **bold** and `code` with a \\* star.
+
+- one
+    - nested
+
+```rust
+let a = 1;
+```
+
+| a\\|b | c |
+| --- | --- |
+| 1 | 2 |
+
+```plantuml
+A -> B
+```
+
+> [!WARNING]
+> Careful
+";
+        assert_eq!(to_markdown(&doc, &MarkdownOptions::default()), expected);
+
+        let options = MarkdownOptions {
+            unknown_cards: UnknownCardFallback::Comment,
+            diagrams_as_images: true,
+            base_url: None,
+        };
+        let markdown = to_markdown(&doc, &options);
+        assert!(markdown.contains("![diagram](https://x/d.svg)"));
+        assert!(markdown.ends_with("<!-- card youku: {\"id\":1} -->\n"));
+
+        let mention = Document { blocks: vec![Block::Paragraph(vec![Inline::Mention(Mention { name: "Cat".to_string(), login: "cat".to_string() })])] };
+        assert_eq!(to_markdown(&mention, &MarkdownOptions::default()), "[@Cat](https://www.yuque.com/cat)\n");
+        let options = MarkdownOptions { base_url: Some("https://corp.yuque.com/".to_string()), ..MarkdownOptions::default() };
+        assert_eq!(to_markdown(&mention, &options), "[@Cat](https://corp.yuque.com/cat)\n");
+    }
+
+    #[test]
+    fn test_escape_markdown() {
+        let lake = concat!(
+            r#"<p># not a heading</p><p>- not a list<br/>2024. a year</p><p>+ plus</p>"#,
+            r#"<p><a href="https://x/a_(b)">link</a> <img src="https://x/a b.png" alt="a]b"/></p>"#,
+            r#"<ul><li>a</li></ul><ul data-lake-indent="1"><li>b</li></ul><ul><li>c</li></ul><ul><li>d</li></ul>"#,
+            r#"<ol><li>one</li></ol><ol start="2"><li>two</li></ol>"#,
+        );
+        let expected = "\\# not a heading
+
+\\- not a list  
+2024\\. a year
+
+\\+ plus
+
+[link](https://x/a_\\(b\\)) ![a\\]b](https://x/a%20b.png)
+
+- a
+    - b
+- c
+
+<!-- -->
+
+- d
+
+<!-- -->
+
+1. one
+2. two
+";
+        assert_eq!(to_markdown(&parse(lake), &MarkdownOptions::default()), expected);
+    }
+}
@@ -0,0 +1,217 @@
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde_json::{json, Value};
+
+use crate::dom::escape;
+use crate::lake::{Attachment, Block, Card, Diagram, DiagramKind, Document, Image, Inline, List, Mention, Table};
+
+static LAKE_HEADER: &str = r#"<!doctype lake><meta name="doc-version" content="1" /><meta name="viewport" content="adapt" />"#;
+
+/// The characters left alone by `encodeURIComponent`, which is what Yuque
+/// uses for card values.
+const CARD_VALUE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'!')
+    .remove(b'~')
+    .remove(b'*')
+    .remove(b'\'')
+    .remove(b'(')
+    .remove(b')');
+
+pub fn encode_card_value(value: &Value) -> String {
+    format!("data:{}", utf8_percent_encode(&value.to_string(), CARD_VALUE))
+}
+
+/// Serializes a document to Lake, ready for `DocFormat::Lake`.
+pub fn to_lake(doc: &Document) -> String {
+    let mut out = String::from(LAKE_HEADER);
+    write_blocks(&doc.blocks, &mut out);
+    out
+}
+
+pub(crate) fn card_markup(card: &Card) -> String {
+    format!(
+        r#"<card type="{}" name="{}" value="{}"></card>"#,
+        if card.block { "block" } else { "inline" },
+        escape(&card.name),
+        encode_card_value(&card.value),
+    )
+}
+
+fn card(name: &str, block: bool, value: Value) -> String {
+    card_markup(&Card { name: name.to_string(), block, value })
+}
+
+fn image_value(image: &Image) -> Value {
+    let mut value = json!({ "src": image.src, "name": image.alt });
+    if let Some(width) = image.width {
+        value["width"] = json!(width);
+    }
+    if let Some(height) = image.height {
+        value["height"] = json!(height);
+    }
+    value
+}
+
+fn attachment_value(file: &Attachment) -> Value {
+    let mut value = json!({ "src": file.src, "name": file.name });
+    if let Some(size) = file.size {
+        value["size"] = json!(size);
+    }
+    value
+}
+
+fn diagram_card(diagram: &Diagram) -> String {
+    let typ = match &diagram.kind {
+        DiagramKind::PlantUml => "puml",
+        DiagramKind::Mermaid => "mermaid",
+        DiagramKind::Graphviz => "graphviz",
+        DiagramKind::Board => return card("board", true, json!({ "url": diagram.url })),
+        DiagramKind::Other(typ) => typ,
+    };
+    let mut value = json!({ "type": typ, "code": diagram.code.clone().unwrap_or_default() });
+    if let Some(url) = &diagram.url {
+        value["url"] = json!(url);
+    }
+    card("diagram", true, value)
+}
+
+fn write_blocks(blocks: &[Block], out: &mut String) {
+    for block in blocks {
+        write_block(block, out);
+    }
+}
+
+fn write_block(block: &Block, out: &mut String) {
+    match block {
+        Block::Paragraph(content) => {
+            out.push_str("<p>");
+            write_inlines(content, out);
+            out.push_str("</p>");
+        }
+        Block::Heading { level, content } => {
+            out.push_str(&format!("<h{}>", level));
+            write_inlines(content, out);
+            out.push_str(&format!("</h{}>", level));
+        }
+        Block::List(list) => write_list(list, out),
+        Block::Quote(blocks) => {
+            out.push_str("<blockquote>");
+            write_blocks(blocks, out);
+            out.push_str("</blockquote>");
+        }
+        Block::Table(table) => write_table(table, out),
+        Block::CodeBlock { language, code } => out.push_str(&card("codeblock", true, json!({ "mode": language, "code": code }))),
+        Block::Image(image) => out.push_str(&format!("<p>{}</p>", card("image", false, image_value(image)))),
+        Block::Attachment(file) => out.push_str(&format!("<p>{}</p>", card("file", false, attachment_value(file)))),
+        Block::Diagram(diagram) => out.push_str(&diagram_card(diagram)),
+        Block::Callout { kind, blocks } => {
+            out.push_str(&format!(r#"<div class="ne-alert" data-type="{}">"#, escape(kind)));
+            write_blocks(blocks, out);
+            out.push_str("</div>");
+        }
+        Block::Math(code) => out.push_str(&card("math", true, json!({ "code": code }))),
+        Block::ThematicBreak => out.push_str(&card("hr", true, json!({}))),
+        Block::Card(c) => out.push_str(&card_markup(c)),
+    }
+}
+
+fn write_list(list: &List, out: &mut String) {
+    let tag = if list.ordered { "ol" } else { "ul" };
+    out.push('<');
+    out.push_str(tag);
+    if list.ordered && list.start != 1 {
+        out.push_str(&format!(r#" start="{}""#, list.start));
+    }
+    if list.indent > 0 {
+        out.push_str(&format!(r#" data-lake-indent="{}""#, list.indent));
+    }
+    if list.items.iter().any(|item| item.checked.is_some()) {
+        out.push_str(r#" class="lake-task-list""#);
+    }
+    out.push('>');
+    for item in &list.items {
+        out.push_str("<li>");
+        if let Some(checked) = item.checked {
+            out.push_str(&card("checkbox", false, json!(checked)));
+        }
+        write_inlines(&item.content, out);
+        out.push_str("</li>");
+    }
+    out.push_str(&format!("</{}>", tag));
+}
+
+fn write_table(table: &Table, out: &mut String) {
+    out.push_str("<table><tbody>");
+    for row in &table.rows {
+        out.push_str("<tr>");
+        for cell in row {
+            out.push_str("<td>");
+            write_blocks(&cell.blocks, out);
+            out.push_str("</td>");
+        }
+        out.push_str("</tr>");
+    }
+    out.push_str("</tbody></table>");
+}
+
+fn write_inlines(content: &[Inline], out: &mut String) {
+    for inline in content {
+        write_inline(inline, out);
+    }
+}
+
+fn write_wrapped(tag: &str, content: &[Inline], out: &mut String) {
+    out.push_str(&format!("<{}>", tag));
+    write_inlines(content, out);
+    out.push_str(&format!("</{}>", tag));
+}
+
+fn write_inline(inline: &Inline, out: &mut String) {
+    match inline {
+        Inline::Text(text) => out.push_str(&escape(text)),
+        Inline::Strong(content) => write_wrapped("strong", content, out),
+        Inline::Emphasis(content) => write_wrapped("em", content, out),
+        Inline::Strike(content) => write_wrapped("del", content, out),
+        Inline::Underline(content) => write_wrapped("u", content, out),
+        Inline::Code(code) => out.push_str(&format!("<code>{}</code>", escape(code))),
+        Inline::Link { href, content } => {
+            out.push_str(&format!(r#"<a href="{}">"#, escape(href)));
+            write_inlines(content, out);
+            out.push_str("</a>");
+        }
+        Inline::Image(image) => out.push_str(&card("image", false, image_value(image))),
+        Inline::Attachment(file) => out.push_str(&card("file", false, attachment_value(file))),
+        Inline::Mention(Mention { name, login }) => out.push_str(&card("mention", false, json!({ "name": name, "login": login }))),
+        Inline::Math(code) => out.push_str(&card("math", false, json!({ "code": code }))),
+        Inline::Break => out.push_str("<br />"),
+        Inline::Card(c) => out.push_str(&card_markup(c)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::lake::writer::{encode_card_value, to_lake};
+    use crate::lake::{parse, Block, Document, Inline};
+
+    #[test]
+    fn test_encode_card_value() {
+        assert_eq!(encode_card_value(&json!({"code": "a b"})), "data:%7B%22code%22%3A%22a%20b%22%7D");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let doc = Document {
+            blocks: vec![
+                Block::Heading { level: 1, content: vec![Inline::Text("A & B".to_string())] },
+                Block::CodeBlock { language: "rust".to_string(), code: "fn main() {}\n".to_string() },
+            ],
+        };
+        let lake = to_lake(&doc);
+        assert!(lake.starts_with("<!doctype lake>"));
+        assert!(lake.contains("<h1>A &amp; B</h1>"));
+        assert_eq!(parse(&lake), doc);
+    }
+}
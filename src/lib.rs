@@ -4,4 +4,26 @@ pub mod group;
 pub mod serializer;
 pub mod repo;
 pub mod doc;
-mod utils;
\ No newline at end of file
+pub mod sync;
+pub mod asset;
+pub mod upload;
+pub mod backup;
+pub mod restore;
+pub mod toc;
+pub mod links;
+pub mod migrate;
+pub mod lake;
+pub mod html;
+pub mod export;
+pub mod site;
+pub mod vault;
+pub mod import;
+#[cfg(feature = "mirror")]
+pub mod mirror;
+pub mod watch;
+pub mod webhook;
+pub mod notify;
+pub mod feed;
+pub mod cache;
+mod utils;
+mod dom;
\ No newline at end of file
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use percent_encoding::percent_decode_str;
+use regex::{Captures, Regex};
+
+use crate::asset::relative_link;
+use crate::lake::map_card_strings;
+
+pub(crate) static DEFAULT_WEB_BASE: &str = "https://www.yuque.com";
+
+/// Describes where docs went when repos are restored or copied, so links
+/// between them can follow.
+#[derive(Debug, Clone, Default)]
+pub struct LinkMap {
+    /// Old repo namespace to new repo namespace.
+    pub namespaces: HashMap<String, String>,
+    /// Old namespace and old slug to new slug, for docs whose slug changed.
+    pub slugs: HashMap<(String, String), String>,
+    /// Old doc id to new namespace and slug, for `/docs/{id}` links.
+    pub ids: HashMap<u32, (String, String)>,
+}
+
+static DOC_LINK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"(?P<prefix>https?://(?:[a-z0-9-]+\.)?yuque\.com|\]\(|href=")/(?P<login>[\w.-]+)/(?P<repo>[\w.-]+)/(?P<slug>[\w.-]+)"#).unwrap());
+
+/// Doc links by id or by path in one pass, so a rewritten link is not
+/// rewritten again.
+static DOC_OR_ID_LINK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?P<prefix>https?://(?:[a-z0-9-]+\.)?yuque\.com|\]\(|href=")/(?:docs/(?P<id>\d+)\b|(?P<login>[\w.-]+)/(?P<repo>[\w.-]+)/(?P<slug>[\w.-]+))"#).unwrap()
+});
+
+impl LinkMap {
+    pub fn insert_namespace(&mut self, old: &str, new: &str) {
+        self.namespaces.insert(old.to_string(), new.to_string());
+    }
+
+    pub fn insert_slug(&mut self, namespace: &str, old: &str, new: &str) {
+        if old != new {
+            self.slugs.insert((namespace.to_string(), old.to_string()), new.to_string());
+        }
+    }
+
+    pub fn insert_id(&mut self, old_id: u32, namespace: &str, slug: &str) {
+        self.ids.insert(old_id, (namespace.to_string(), slug.to_string()));
+    }
+
+    /// Rewrites absolute and root-relative Yuque doc links, keeping anchors.
+    pub fn rewrite(&self, body: &str) -> String {
+        DOC_OR_ID_LINK
+            .replace_all(body, |cap: &Captures| {
+                if let Some(id) = cap.name("id") {
+                    return match id.as_str().parse().ok().and_then(|id: u32| self.ids.get(&id)) {
+                        Some((namespace, slug)) => format!("{}/{}/{}", &cap["prefix"], namespace, slug),
+                        None => cap[0].to_string(),
+                    };
+                }
+                let namespace = format!("{}/{}", &cap["login"], &cap["repo"]);
+                let new_slug = self.slugs.get(&(namespace.clone(), cap["slug"].to_string()));
+                let new_namespace = self.namespaces.get(&namespace);
+                if new_slug.is_none() && new_namespace.is_none() {
+                    return cap[0].to_string();
+                }
+                format!(
+                    "{}/{}/{}",
+                    &cap["prefix"],
+                    new_namespace.unwrap_or(&namespace),
+                    new_slug.map(String::as_str).unwrap_or(&cap["slug"]),
+                )
+            })
+            .into_owned()
+    }
+}
+
+/// Where the docs of an export live on disk, for turning links between
+/// Yuque URLs and relative paths. Paths are `/` separated and relative to
+/// the export root.
+#[derive(Debug, Clone, Default)]
+pub struct PathMap {
+    /// Namespace and slug to path.
+    pub paths: HashMap<(String, String), String>,
+    /// Used for links made on the way back, defaults to `https://www.yuque.com`.
+    pub base_url: Option<String>,
+}
+
+/// Link targets of Markdown inline links, reference definitions and `href`s.
+static LINK_TARGET: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"(?m)(\]\(|href="|^\[[^\]]+\]:[ \t]*)([^)"\s]+)"#).unwrap());
+
+fn split_anchor(target: &str) -> (&str, &str) {
+    match target.find(['#', '?']) {
+        Some(i) => (&target[..i], &target[i..]),
+        None => (target, ""),
+    }
+}
+
+/// Resolves a relative link against the file it appears in.
+fn resolve(from: &str, target: &str) -> String {
+    let mut parts: Vec<&str> = from.split('/').filter(|s| !s.is_empty()).collect();
+    parts.pop();
+    for part in target.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+impl PathMap {
+    pub fn insert(&mut self, namespace: &str, slug: &str, path: &str) {
+        self.paths.insert((namespace.to_string(), slug.to_string()), path.to_string());
+    }
+
+    /// Turns links to mapped docs into links relative to `from`, the path
+    /// of the doc being rewritten. Works on Markdown, Lake and HTML.
+    pub fn to_local(&self, body: &str, from: &str) -> String {
+        let local = |url: &str| -> Option<String> {
+            // Root-relative links are on the main site.
+            let url = if url.starts_with('/') && !url.starts_with("//") { format!("{}{}", DEFAULT_WEB_BASE, url) } else { url.to_string() };
+            let cap = DOC_LINK.captures(&url).filter(|cap| cap.get(0).unwrap().start() == 0)?;
+            let rest = &url[cap.get(0).unwrap().end()..];
+            let path = self.paths.get(&(format!("{}/{}", &cap["login"], &cap["repo"]), cap["slug"].to_string()))?;
+            Some(format!("{}{}", relative_link(from, path), split_anchor(rest).1))
+        };
+        let body = map_card_strings(body, |s| local(s));
+        LINK_TARGET
+            .replace_all(&body, |cap: &Captures| match local(&cap[2]) {
+                Some(link) => format!("{}{}", &cap[1], link),
+                None => cap[0].to_string(),
+            })
+            .into_owned()
+    }
+
+    /// Turns relative links to mapped docs back into Yuque URLs.
+    pub fn to_yuque(&self, body: &str, from: &str) -> String {
+        let docs: HashMap<&str, &(String, String)> = self.paths.iter().map(|(doc, path)| (path.as_str(), doc)).collect();
+        let base = self.base_url.as_deref().unwrap_or(DEFAULT_WEB_BASE).trim_end_matches('/');
+        let yuque = |target: &str| -> Option<String> {
+            if target.contains("://") || target.starts_with('/') || target.starts_with('#') {
+                return None;
+            }
+            let (path, anchor) = split_anchor(target);
+            let path = resolve(from, &percent_decode_str(path).decode_utf8_lossy());
+            let (namespace, slug) = docs.get(path.as_str())?;
+            Some(format!("{}/{}/{}{}", base, namespace, slug, anchor))
+        };
+        let body = map_card_strings(body, |s| yuque(s));
+        LINK_TARGET
+            .replace_all(&body, |cap: &Captures| match yuque(&cap[2]) {
+                Some(url) => format!("{}{}", &cap[1], url),
+                None => cap[0].to_string(),
+            })
+            .into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::links::{LinkMap, PathMap};
+
+    #[test]
+    fn test_rewrite() {
+        let mut links = LinkMap::default();
+        links.insert_namespace("old/kb", "new/kb");
+        links.insert_slug("old/kb", "intro", "intro-1");
+        links.insert_id(7, "new/kb", "intro-1");
+        let body = "[a](https://www.yuque.com/old/kb/intro#setup) [b](/old/kb/faq) <a href=\"/other/kb/x\">x</a> /old/kb/path";
+        assert_eq!(
+            links.rewrite(body),
+            "[a](https://www.yuque.com/new/kb/intro-1#setup) [b](/new/kb/faq) <a href=\"/other/kb/x\">x</a> /old/kb/path",
+        );
+        assert_eq!(
+            links.rewrite("[a](https://www.yuque.com/docs/7#setup) [b](/docs/70) [c](/docs/7)"),
+            "[a](https://www.yuque.com/new/kb/intro-1#setup) [b](/docs/70) [c](/new/kb/intro-1)",
+        );
+
+        // Restoring into the same namespace, a link by id lands on its doc
+        // even when that slug was itself renamed.
+        let mut links = LinkMap::default();
+        links.insert_slug("me/kb", "intro", "intro-1");
+        links.insert_slug("me/kb", "intro-1", "intro-1-1");
+        links.insert_id(7, "me/kb", "intro-1");
+        assert_eq!(links.rewrite("[a](/docs/7) [b](/me/kb/intro) [c](/me/kb/intro-1)"), "[a](/me/kb/intro-1) [b](/me/kb/intro-1) [c](/me/kb/intro-1-1)");
+    }
+
+    #[test]
+    fn test_path_map() {
+        let mut paths = PathMap::default();
+        paths.insert("me/kb", "intro", "kb/intro.md");
+        paths.insert("me/other", "faq", "other/guide/faq.md");
+        let body = "[a](https://www.yuque.com/me/kb/intro#setup) [b](/me/other/faq) <a href=\"https://www.yuque.com/me/kb/missing\">c</a>\n\n[d]: https://me.yuque.com/me/other/faq";
+        let local = paths.to_local(body, "kb/start.md");
+        assert_eq!(local, "[a](intro.md#setup) [b](../other/guide/faq.md) <a href=\"https://www.yuque.com/me/kb/missing\">c</a>\n\n[d]: ../other/guide/faq.md");
+        assert_eq!(
+            paths.to_yuque(&local, "kb/start.md"),
+            "[a](https://www.yuque.com/me/kb/intro#setup) [b](https://www.yuque.com/me/other/faq) <a href=\"https://www.yuque.com/me/kb/missing\">c</a>\n\n[d]: https://www.yuque.com/me/other/faq",
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use anyhow::Result;
+
+use crate::{
+    backup::{BackupArchive, BackupOptions, RepoBackup},
+    doc::{CreateDocRequest, DocFormat, UpdateDocRequest},
+    links::{LinkMap, DEFAULT_WEB_BASE},
+    repo::ObjType,
+    restore::RestoredRepo,
+    serializer::DocSerializer,
+    toc::UpdateTocRequest,
+    utils::unique_slug,
+    yuque::Yuque,
+};
+
+#[derive(Debug, Default)]
+pub struct CopyRepoOptions {
+    /// Slug of the copy, defaults to the source slug.
+    pub slug: Option<String>,
+    /// Name of the copy, defaults to the source name.
+    pub name: Option<String>,
+    /// Delete the source repo once everything has been copied.
+    pub delete_source: bool,
+    /// Add the docs to the target repo when it already exists instead of
+    /// failing.
+    pub merge_existing: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct MoveDocOptions {
+    /// Replace the original with a note linking to the new location instead
+    /// of deleting it.
+    pub leave_redirect: bool,
+    /// Used for the redirect link, defaults to `https://www.yuque.com`.
+    pub base_url: Option<String>,
+}
+
+fn check_copy_target(source_namespace: &str, copied: &RestoredRepo, options: &CopyRepoOptions) -> Result<()> {
+    if copied.namespace == source_namespace {
+        return Err(anyhow::anyhow!("cannot copy {} onto itself", source_namespace));
+    }
+    if !copied.create_repo && !options.merge_existing {
+        return Err(anyhow::anyhow!("{} already exists", copied.namespace));
+    }
+    Ok(())
+}
+
+/// Whether every doc of `source` made it into the copy.
+fn fully_copied(source: &RepoBackup, copied: &RestoredRepo) -> bool {
+    source.docs.iter().all(|doc| copied.docs.iter().any(|c| c.source_id == doc.doc.object.id && c.doc_id.is_some()))
+}
+
+/// Picks the lake body when there is one, so cards survive the copy.
+fn raw_body(doc: &DocSerializer) -> (DocFormat, String) {
+    match &doc.body_lake {
+        Some(body) if !body.is_empty() => (DocFormat::Lake, body.clone()),
+        _ => (DocFormat::Markdown, doc.body.clone().unwrap_or_default()),
+    }
+}
+
+impl Yuque {
+    /// Copies a repo into another user or group, keeping titles, slugs,
+    /// visibility and TOC order, and pointing links between its docs at the
+    /// copy.
+    pub async fn copy_repo<T: Display, U: Display>(&self, source: T, obj_type: ObjType, obj: U, options: &CopyRepoOptions) -> Result<RestoredRepo> {
+        let owner = obj.to_string();
+        let mut book = self.get_repo(&source).await?.data;
+        let source_namespace = book.namespace.clone().unwrap_or_else(|| source.to_string());
+        book.namespace = Some(source_namespace.clone());
+
+        let mut archive = BackupArchive::new(obj_type, &owner);
+        let backup_options = BackupOptions {
+            skip_assets: true,
+            ..BackupOptions::default()
+        };
+        let mut repo = self.backup_repo(&mut archive, &owner, &book, None, &backup_options).await?;
+        if let Some(slug) = &options.slug {
+            repo.book.slug = slug.clone();
+        }
+        if let Some(name) = &options.name {
+            repo.book.name = name.clone();
+        }
+
+        let mut links = LinkMap::default();
+        let namespace = format!("{}/{}", owner, repo.book.slug);
+        let mut copied = self.plan_repo_restore(&archive, &repo, source_namespace.clone(), namespace, &mut links).await?;
+        check_copy_target(&source_namespace, &copied, options)?;
+        self.apply_repo_restore(&archive, &repo, &mut copied, obj_type, &owner, &links).await?;
+
+        if options.delete_source {
+            if !fully_copied(&repo, &copied) {
+                return Err(anyhow::anyhow!("{} was not deleted, {} docs could not be copied", source_namespace, copied.skipped.len()));
+            }
+            self.delete_repo(&source_namespace).await?;
+        }
+        Ok(copied)
+    }
+
+    /// Copies a doc into another repo and appends it to the target TOC. The
+    /// copy gets a `-1`, `-2`, ... suffix when the slug is already taken.
+    pub async fn copy_doc<T: Display, U: Display, V: Display>(&self, from_repo: T, doc: U, to_repo: V) -> Result<DocSerializer> {
+        let source = self.get_doc(from_repo.to_string(), doc.to_string(), Some(true)).await?;
+        let target = self.get_repo(&to_repo).await?.data;
+        let namespace = target.namespace.clone().unwrap_or_else(|| to_repo.to_string());
+
+        let taken: HashSet<String> = self.list_all_docs(&namespace, None).await?.into_iter().map(|doc| doc.slug).collect();
+        let (format, body) = raw_body(&source);
+        let req = CreateDocRequest {
+            title: source.title.clone(),
+            slug: unique_slug(&source.slug, &taken),
+            format: Some(format),
+            body,
+            public: Some(source.public),
+        };
+        let copied = self.create_doc(&namespace, &req).await?;
+        self.update_toc(&namespace, &UpdateTocRequest::append_docs(vec![copied.object.id])).await?;
+        Ok(copied)
+    }
+
+    /// Copies a doc into another repo, then deletes the original or turns it
+    /// into a redirect note.
+    pub async fn move_doc<T: Display, U: Display, V: Display>(&self, from_repo: T, doc: U, to_repo: V, options: &MoveDocOptions) -> Result<DocSerializer> {
+        let source = self.get_doc(from_repo.to_string(), doc.to_string(), None).await?;
+        let target = self.get_repo(&to_repo).await?.data;
+        if source.book_id == target.object.id {
+            return Err(anyhow::anyhow!("{} is already in {}", source.slug, to_repo));
+        }
+        let moved = self.copy_doc(&from_repo, &source.slug, &to_repo).await?;
+        if options.leave_redirect {
+            let namespace = target.namespace.clone().unwrap_or_else(|| to_repo.to_string());
+            let base = options.base_url.as_deref().unwrap_or(DEFAULT_WEB_BASE).trim_end_matches('/');
+            let mut req = UpdateDocRequest {
+                body: Some(format!("This document has moved to [{}]({}/{}/{}).", moved.title, base, namespace, moved.slug)),
+                _force_asl: Some(1),
+                ..UpdateDocRequest::default()
+            };
+            self.update_doc(&from_repo, source.object.id, &mut req).await?;
+        } else {
+            self.delete_doc(&from_repo, source.object.id).await?;
+        }
+        Ok(moved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backup::{BackupArchive, BodyFormat, RepoBackup};
+    use crate::doc::DocFormat;
+    use crate::migrate::{check_copy_target, fully_copied, raw_body, CopyRepoOptions};
+    use crate::repo::ObjType;
+    use crate::restore::{RestoredDoc, RestoredRepo};
+    use crate::serializer::{BookSerializer, DocSerializer};
+
+    #[test]
+    fn test_raw_body() {
+        let doc = DocSerializer {
+            body: Some("# A".to_string()),
+            body_lake: Some("<p>A</p>".to_string()),
+            ..DocSerializer::default()
+        };
+        assert_eq!(raw_body(&doc), (DocFormat::Lake, "<p>A</p>".to_string()));
+        let doc = DocSerializer {
+            body: Some("# A".to_string()),
+            body_lake: Some(String::new()),
+            ..DocSerializer::default()
+        };
+        assert_eq!(raw_body(&doc), (DocFormat::Markdown, "# A".to_string()));
+    }
+
+    fn copied(namespace: &str, create_repo: bool, doc_ids: &[Option<u32>]) -> RestoredRepo {
+        let docs = doc_ids
+            .iter()
+            .enumerate()
+            .map(|(i, doc_id)| RestoredDoc { source_id: i as u32, title: String::new(), slug: format!("doc-{}", i), format: BodyFormat::Markdown, doc_id: *doc_id })
+            .collect();
+        RestoredRepo { source_namespace: "me/kb".to_string(), namespace: namespace.to_string(), create_repo, docs, skipped: Vec::new() }
+    }
+
+    #[test]
+    fn test_check_copy_target() {
+        let options = CopyRepoOptions::default();
+        assert!(check_copy_target("me/kb", &copied("team/kb", true, &[]), &options).is_ok());
+        assert!(check_copy_target("me/kb", &copied("me/kb", false, &[]), &options).is_err());
+        assert!(check_copy_target("me/kb", &copied("team/kb", false, &[]), &options).is_err());
+        let options = CopyRepoOptions { merge_existing: true, ..CopyRepoOptions::default() };
+        assert!(check_copy_target("me/kb", &copied("team/kb", false, &[]), &options).is_ok());
+        assert!(check_copy_target("me/kb", &copied("me/kb", false, &[]), &options).is_err());
+    }
+
+    #[test]
+    fn test_fully_copied() {
+        let mut archive = BackupArchive::new(ObjType::User, "me");
+        let docs = (0..2)
+            .map(|i| {
+                let mut doc = archive.add_doc("kb", DocSerializer { slug: format!("doc-{}", i), body: Some("hi".to_string()), ..DocSerializer::default() });
+                doc.doc.object.id = i;
+                doc
+            })
+            .collect();
+        let source = RepoBackup { book: BookSerializer::default(), docs, deleted_docs: Vec::new() };
+        assert!(fully_copied(&source, &copied("team/kb", true, &[Some(10), Some(11)])));
+        assert!(!fully_copied(&source, &copied("team/kb", true, &[Some(10), None])));
+        assert!(!fully_copied(&source, &copied("team/kb", true, &[Some(10)])));
+    }
+}
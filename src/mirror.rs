@@ -0,0 +1,338 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::Path;
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset, Utc};
+use git2::{Repository, Signature, Time};
+use serde::{Deserialize, Serialize};
+
+use crate::html::{self, doc_markdown};
+use crate::lake::{self, MarkdownOptions};
+use crate::serializer::{DocSerializer, DocVersionSerializer, UserSerializer, VersionUserSerializer};
+use crate::yuque::Yuque;
+
+/// Kept inside the `.git` directory so it never shows up in the history.
+pub static MIRROR_STATE_FILE: &str = "yuque-mirror.json";
+static AUTHOR_EMAIL_DOMAIN: &str = "users.noreply.yuque.com";
+
+/// What has been committed so far, so that later runs only append.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct MirrorState {
+    pub repo: String,
+    /// Keyed by doc id.
+    pub docs: BTreeMap<u32, MirrorEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MirrorEntry {
+    pub path: String,
+    pub last_version: u32,
+    /// Time of the last commit made for the doc.
+    pub updated_at: String,
+}
+
+impl MirrorState {
+    pub fn load(path: &Path) -> Result<MirrorState> {
+        match std::fs::read(path) {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(MirrorState::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MirrorAuthor {
+    pub name: String,
+    pub email: String,
+}
+
+impl MirrorAuthor {
+    fn new(id: u32, login: &str, name: &str) -> MirrorAuthor {
+        let login = if login.is_empty() { id.to_string() } else { login.to_string() };
+        let name = if name.is_empty() { login.clone() } else { name.to_string() };
+        MirrorAuthor { name, email: format!("{}@{}", login, AUTHOR_EMAIL_DOMAIN) }
+    }
+}
+
+impl Default for MirrorAuthor {
+    fn default() -> Self {
+        MirrorAuthor { name: "Yuque".to_string(), email: format!("yuque@{}", AUTHOR_EMAIL_DOMAIN) }
+    }
+}
+
+impl From<&UserSerializer> for MirrorAuthor {
+    fn from(user: &UserSerializer) -> Self {
+        MirrorAuthor::new(user.object.id, &user.login, &user.name)
+    }
+}
+
+impl From<&VersionUserSerializer> for MirrorAuthor {
+    fn from(user: &VersionUserSerializer) -> Self {
+        MirrorAuthor::new(user.id, &user.login, &user.name)
+    }
+}
+
+/// One commit of the mirror. `content` is `None` when the doc was deleted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MirrorCommit {
+    pub doc_id: u32,
+    pub version_id: Option<u32>,
+    /// RFC 3339, used as author and committer time.
+    pub time: String,
+    pub author: MirrorAuthor,
+    pub path: String,
+    pub title: String,
+    pub content: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct MirrorReport {
+    pub commits: usize,
+    /// Versions whose content matched what was already committed.
+    pub unchanged: usize,
+}
+
+fn parse_time(time: &str) -> Result<DateTime<FixedOffset>> {
+    Ok(DateTime::parse_from_rfc3339(time)?)
+}
+
+fn doc_path(slug: &str) -> String {
+    format!("{}.md", slug)
+}
+
+fn version_markdown(version: &DocVersionSerializer) -> String {
+    let populated = |body: &Option<String>| body.clone().filter(|body| !body.trim().is_empty());
+    let options = MarkdownOptions::default();
+    match (populated(&version.body), populated(&version.body_asl), populated(&version.body_html)) {
+        (Some(markdown), _, _) => markdown,
+        (None, Some(lake_body), _) => lake::to_markdown(&lake::parse(&lake_body), &options),
+        (None, None, Some(html_body)) => html::to_markdown(&html_body, &options),
+        (None, None, None) => String::new(),
+    }
+}
+
+fn version_commit(doc: &DocSerializer, version: &DocVersionSerializer) -> MirrorCommit {
+    let slug = if version.slug.is_empty() { &doc.slug } else { &version.slug };
+    MirrorCommit {
+        doc_id: doc.object.id,
+        version_id: Some(version.object.id),
+        time: version.object.created_at.clone(),
+        author: version.user.as_ref().map(MirrorAuthor::from).unwrap_or_default(),
+        path: doc_path(slug),
+        title: if version.title.is_empty() { doc.title.clone() } else { version.title.clone() },
+        content: Some(version_markdown(version)),
+    }
+}
+
+fn current_commit(doc: &DocSerializer) -> MirrorCommit {
+    let author = doc.last_editor.as_ref().or(doc.creator.as_ref());
+    MirrorCommit {
+        doc_id: doc.object.id,
+        version_id: None,
+        time: doc.content_updated_at.clone(),
+        author: author.map(MirrorAuthor::from).unwrap_or_default(),
+        path: doc_path(&doc.slug),
+        title: doc.title.clone(),
+        content: Some(doc_markdown(doc, &MarkdownOptions::default()).unwrap_or_default()),
+    }
+}
+
+/// Orders commits by time, keeping the given order for equal times.
+pub fn sort_commits(commits: &mut [MirrorCommit]) {
+    commits.sort_by_key(|commit| parse_time(&commit.time).map(|t| t.with_timezone(&Utc)).unwrap_or_default());
+}
+
+/// Writes the commit's file into the work tree and commits it on top of
+/// `HEAD`. Returns `None` when the tree did not change.
+pub fn apply_commit(repo: &Repository, state: &mut MirrorState, commit: &MirrorCommit) -> Result<Option<git2::Oid>> {
+    let workdir = repo.workdir().ok_or_else(|| anyhow::anyhow!("cannot mirror into a bare repository"))?.to_path_buf();
+    let mut index = repo.index()?;
+    let old_path = state.docs.get(&commit.doc_id).map(|entry| entry.path.clone());
+    if let Some(old_path) = old_path.filter(|old_path| commit.content.is_none() || *old_path != commit.path) {
+        match std::fs::remove_file(workdir.join(&old_path)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        index.remove_path(Path::new(&old_path))?;
+    }
+    match &commit.content {
+        Some(content) => {
+            let path = workdir.join(&commit.path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, content)?;
+            index.add_path(Path::new(&commit.path))?;
+            let last_version = state.docs.get(&commit.doc_id).map(|entry| entry.last_version).unwrap_or_default();
+            state.docs.insert(commit.doc_id, MirrorEntry {
+                path: commit.path.clone(),
+                last_version: commit.version_id.unwrap_or_default().max(last_version),
+                updated_at: commit.time.clone(),
+            });
+        }
+        None => {
+            state.docs.remove(&commit.doc_id);
+        }
+    }
+    index.write()?;
+    let tree_id = index.write_tree()?;
+
+    let parent = match repo.head() {
+        Ok(head) => Some(head.peel_to_commit()?),
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch => None,
+        Err(e) => return Err(e.into()),
+    };
+    if parent.as_ref().is_some_and(|parent| parent.tree_id() == tree_id) {
+        return Ok(None);
+    }
+    let tree = repo.find_tree(tree_id)?;
+    let time = parse_time(&commit.time)?;
+    let signature = Signature::new(
+        &commit.author.name,
+        &commit.author.email,
+        &Time::new(time.timestamp(), time.offset().local_minus_utc() / 60),
+    )?;
+    let mut message = if commit.content.is_some() { commit.title.clone() } else { format!("Delete {}", commit.title) };
+    message.push_str(&format!("\n\nYuque-Doc: {}", commit.doc_id));
+    if let Some(version_id) = commit.version_id {
+        message.push_str(&format!("\nYuque-Version: {}", version_id));
+    }
+    let parents: Vec<&git2::Commit> = parent.iter().collect();
+    Ok(Some(repo.commit(Some("HEAD"), &signature, &signature, &message, &tree, &parents)?))
+}
+
+impl Yuque {
+    /// Mirrors the docs of `repo` into a git repository at `dir`, one commit
+    /// per doc version, authored by its editor at the version time. Running
+    /// it again only appends the versions saved since.
+    pub async fn mirror_repo<T: Display>(&self, repo: T, dir: &Path) -> Result<MirrorReport> {
+        let repo = repo.to_string();
+        let git = match Repository::open(dir) {
+            Ok(git) => git,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => Repository::init(dir)?,
+            Err(e) => return Err(e.into()),
+        };
+        let state_file = git.path().join(MIRROR_STATE_FILE);
+        let mut state = MirrorState::load(&state_file)?;
+        if !state.repo.is_empty() && state.repo != repo {
+            return Err(anyhow::anyhow!("mirror belongs to repo {}", state.repo));
+        }
+        state.repo = repo.clone();
+
+        let docs = self.list_all_docs(&repo, None).await?;
+        let mut commits = Vec::new();
+        for doc in &docs {
+            let entry = state.docs.get(&doc.object.id).cloned();
+            let last_version = entry.as_ref().map(|entry| entry.last_version).unwrap_or_default();
+            let mut versions = self.list_doc_versions(doc.object.id).await?;
+            versions.retain(|version| version.object.id > last_version);
+            versions.sort_by_key(|version| version.object.id);
+            let mut latest = entry.map(|entry| entry.updated_at);
+            for version in versions {
+                let version = self.get_doc_version(version.object.id).await?;
+                latest = Some(version.object.created_at.clone());
+                commits.push(version_commit(doc, &version));
+            }
+            let outdated = match latest {
+                Some(latest) => parse_time(&doc.content_updated_at)? > parse_time(&latest)?,
+                None => true,
+            };
+            if outdated {
+                let doc = self.get_doc(repo.clone(), doc.object.id.to_string(), Some(true)).await?;
+                commits.push(current_commit(&doc));
+            }
+        }
+        let now = Utc::now().to_rfc3339();
+        for (doc_id, entry) in &state.docs {
+            if !docs.iter().any(|doc| doc.object.id == *doc_id) {
+                let title = entry.path.trim_end_matches(".md").to_string();
+                commits.push(MirrorCommit {
+                    doc_id: *doc_id,
+                    version_id: None,
+                    time: now.clone(),
+                    author: MirrorAuthor::default(),
+                    path: entry.path.clone(),
+                    title,
+                    content: None,
+                });
+            }
+        }
+        sort_commits(&mut commits);
+
+        let mut report = MirrorReport::default();
+        for commit in &commits {
+            match apply_commit(&git, &mut state, commit)? {
+                Some(_) => report.commits += 1,
+                None => report.unchanged += 1,
+            }
+            state.save(&state_file)?;
+        }
+        state.save(&state_file)?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use git2::Repository;
+
+    use crate::mirror::{apply_commit, sort_commits, MirrorAuthor, MirrorCommit, MirrorState};
+
+    fn commit(doc_id: u32, version_id: Option<u32>, time: &str, path: &str, content: Option<&str>) -> MirrorCommit {
+        MirrorCommit {
+            doc_id,
+            version_id,
+            time: time.to_string(),
+            author: MirrorAuthor { name: "Alice".to_string(), email: "alice@users.noreply.yuque.com".to_string() },
+            path: path.to_string(),
+            title: "Guide".to_string(),
+            content: content.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_apply_commit() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let mut state = MirrorState::default();
+
+        let mut commits = vec![
+            commit(1, None, "2024-01-03T00:00:00.000Z", "guide.md", Some("# Guide\n\nv2\n")),
+            commit(1, Some(10), "2024-01-02T08:00:00+08:00", "guide.md", Some("# Guide\n\nv1\n")),
+        ];
+        sort_commits(&mut commits);
+        assert_eq!(commits[0].version_id, Some(10));
+        for commit in &commits {
+            assert!(apply_commit(&repo, &mut state, commit).unwrap().is_some());
+        }
+
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.author().name(), Some("Alice"));
+        assert_eq!(head.author().when().seconds(), 1704240000);
+        assert_eq!(head.message(), Some("Guide\n\nYuque-Doc: 1"));
+        let first = head.parent(0).unwrap();
+        assert_eq!(first.message(), Some("Guide\n\nYuque-Doc: 1\nYuque-Version: 10"));
+        assert_eq!(first.author().when().offset_minutes(), 480);
+        assert_eq!(state.docs[&1].last_version, 10);
+
+        // Nothing new to record.
+        assert!(apply_commit(&repo, &mut state, &commits[1]).unwrap().is_none());
+
+        // A renamed doc moves its file, a deleted one removes it.
+        apply_commit(&repo, &mut state, &commit(1, Some(11), "2024-01-04T00:00:00Z", "handbook.md", Some("v3\n"))).unwrap().unwrap();
+        assert!(!dir.path().join("guide.md").exists());
+        let tree = repo.head().unwrap().peel_to_tree().unwrap();
+        assert_eq!(tree.iter().map(|entry| entry.name().unwrap().to_string()).collect::<Vec<_>>(), vec!["handbook.md"]);
+
+        let oid = apply_commit(&repo, &mut state, &commit(1, None, "2024-01-05T00:00:00Z", "handbook.md", None)).unwrap().unwrap();
+        assert!(repo.find_commit(oid).unwrap().tree().unwrap().is_empty());
+        assert!(state.docs.is_empty());
+        assert_eq!(repo.revwalk().map(|mut walk| { walk.push_head().unwrap(); walk.count() }).unwrap(), 4);
+    }
+}
@@ -0,0 +1,540 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use anyhow::Result;
+use async_trait::async_trait;
+use regex::{Captures, Regex};
+use serde_json::{json, Value};
+
+use crate::links::DEFAULT_WEB_BASE;
+use crate::serializer::DocSerializer;
+use crate::watch::WatchEvent;
+use crate::webhook::{WebhookEvent, WebhookKind};
+
+static MAX_EXCERPT_LINES: usize = 6;
+static PLACEHOLDER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{\{(\w+)\}\}").unwrap());
+static DEFAULT_TEMPLATE: &str = r#"{"event": "{{event}}", "title": "{{title}}", "namespace": "{{namespace}}", "url": "{{url}}", "actor": "{{actor}}", "summary": "{{summary}}"}"#;
+
+/// A change worth telling people about.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Notification {
+    /// What happened, e.g. `Doc updated`.
+    pub event: String,
+    pub title: String,
+    pub namespace: String,
+    pub url: String,
+    pub actor: Option<String>,
+    /// What changed, one item per line.
+    pub summary: String,
+}
+
+impl Notification {
+    pub fn subject(&self) -> String {
+        format!("[{}] {}: {}", self.namespace, self.event, self.title)
+    }
+
+    /// Plain text, used by email and the chat formats without markup.
+    pub fn text(&self) -> String {
+        let mut text = self.subject();
+        if let Some(actor) = &self.actor {
+            text.push_str(&format!("\nBy {}", actor));
+        }
+        if !self.summary.is_empty() {
+            text.push_str(&format!("\n\n{}", self.summary));
+        }
+        text.push_str(&format!("\n\n{}", self.url));
+        text
+    }
+}
+
+/// Counts the lines added and removed, followed by a few of them.
+pub fn diff_summary(before: &str, after: &str) -> String {
+    let mut remaining: HashMap<&str, i32> = HashMap::new();
+    for line in before.lines() {
+        *remaining.entry(line).or_default() += 1;
+    }
+    let mut added = Vec::new();
+    for line in after.lines() {
+        match remaining.get_mut(line) {
+            Some(count) if *count > 0 => *count -= 1,
+            _ => added.push(line),
+        }
+    }
+    let mut removed = Vec::new();
+    for line in before.lines() {
+        if let Some(count) = remaining.get_mut(line).filter(|count| **count > 0) {
+            *count -= 1;
+            removed.push(line);
+        }
+    }
+    let (added, removed): (Vec<_>, Vec<_>) = (added.into_iter().filter(|l| !l.trim().is_empty()).collect(), removed.into_iter().filter(|l| !l.trim().is_empty()).collect());
+    let mut summary = format!("+{} -{} lines", added.len(), removed.len());
+    let excerpt = removed.iter().map(|line| format!("- {}", line)).chain(added.iter().map(|line| format!("+ {}", line)));
+    for line in excerpt.take(MAX_EXCERPT_LINES) {
+        summary.push('\n');
+        summary.push_str(&line);
+    }
+    if added.len() + removed.len() > MAX_EXCERPT_LINES {
+        summary.push_str("\n...");
+    }
+    summary
+}
+
+/// The body diff needs watching with `WatchOptions::bodies`, otherwise only
+/// the word counts are compared.
+fn doc_summary(before: Option<&DocSerializer>, after: &DocSerializer) -> String {
+    let mut lines = Vec::new();
+    if let Some(before) = before {
+        if before.title != after.title {
+            lines.push(format!("Title: {} → {}", before.title, after.title));
+        }
+        match (&before.body, &after.body) {
+            (Some(old), Some(new)) => lines.push(diff_summary(old, new)),
+            _ if before.word_count != after.word_count => lines.push(format!("Words: {} → {}", before.word_count, after.word_count)),
+            _ => {}
+        }
+    } else if after.word_count > 0 {
+        lines.push(format!("Words: {}", after.word_count));
+    }
+    lines.join("\n")
+}
+
+fn doc_url(namespace: &str, slug: &str) -> String {
+    format!("{}/{}/{}", DEFAULT_WEB_BASE, namespace, slug)
+}
+
+fn doc_notification(event: &str, namespace: &str, before: Option<&DocSerializer>, after: &DocSerializer) -> Notification {
+    Notification {
+        event: event.to_string(),
+        title: after.title.clone(),
+        namespace: namespace.to_string(),
+        url: doc_url(namespace, &after.slug),
+        actor: after.last_editor.as_ref().map(|user| user.name.clone()),
+        summary: doc_summary(before, after),
+    }
+}
+
+impl From<&WatchEvent> for Notification {
+    fn from(event: &WatchEvent) -> Self {
+        match event {
+            WatchEvent::DocCreated { namespace, after } => doc_notification("Doc created", namespace, None, after),
+            WatchEvent::DocUpdated { namespace, before, after } => doc_notification("Doc updated", namespace, Some(before), after),
+            WatchEvent::DocPublished { namespace, before, after } => doc_notification("Doc published", namespace, Some(before), after),
+            WatchEvent::DocDeleted { namespace, before } => Notification { summary: String::new(), ..doc_notification("Doc deleted", namespace, None, before) },
+            WatchEvent::RepoCreated { after } | WatchEvent::RepoRenamed { after, .. } => {
+                let namespace = after.namespace.clone().unwrap_or_else(|| after.slug.clone());
+                let (event, summary) = match event {
+                    WatchEvent::RepoRenamed { before, .. } => ("Repo renamed", format!("Name: {} → {}", before.name, after.name)),
+                    _ => ("Repo created", String::new()),
+                };
+                Notification {
+                    event: event.to_string(),
+                    title: after.name.clone(),
+                    url: format!("{}/{}", DEFAULT_WEB_BASE, namespace),
+                    namespace,
+                    actor: None,
+                    summary,
+                }
+            }
+        }
+    }
+}
+
+impl From<&WebhookEvent> for Notification {
+    fn from(event: &WebhookEvent) -> Self {
+        match event {
+            WebhookEvent::Doc(doc) => {
+                let path = doc.path.clone().unwrap_or_default();
+                let namespace = doc.doc.book.as_ref().and_then(|book| book.namespace.clone())
+                    .unwrap_or_else(|| path.rsplit_once('/').map(|(namespace, _)| namespace.to_string()).unwrap_or_default());
+                let event = match doc.webhook_subject_type {
+                    WebhookKind::Publish => "Doc published",
+                    WebhookKind::Delete => "Doc deleted",
+                    _ => "Doc updated",
+                };
+                let mut notification = doc_notification(event, &namespace, None, &doc.doc);
+                if let Some(actor) = &doc.actor {
+                    notification.actor = Some(actor.name.clone());
+                }
+                notification
+            }
+            WebhookEvent::Comment(comment) => {
+                let doc = comment.commentable.as_ref();
+                let path = comment.path.clone().unwrap_or_default();
+                let namespace = doc.and_then(|doc| doc.book.as_ref()).and_then(|book| book.namespace.clone())
+                    .unwrap_or_else(|| path.rsplit_once('/').map(|(namespace, _)| namespace.to_string()).unwrap_or_default());
+                Notification {
+                    event: "New comment".to_string(),
+                    title: doc.map(|doc| doc.title.clone()).unwrap_or_else(|| path.clone()),
+                    url: format!("{}/{}", DEFAULT_WEB_BASE, path),
+                    namespace,
+                    actor: comment.user.as_ref().map(|user| user.name.clone()),
+                    summary: comment.body.clone().unwrap_or_default(),
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, notification: &Notification) -> Result<()>;
+}
+
+/// Sends to every notifier, even when some of them fail.
+pub async fn notify_all(notifiers: &[Box<dyn Notifier>], notification: &Notification) -> Result<()> {
+    let mut errors = Vec::new();
+    for notifier in notifiers {
+        if let Err(e) = notifier.notify(notification).await {
+            errors.push(e.to_string());
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("{}", errors.join("; ")))
+    }
+}
+
+/// Fills `{{event}}`, `{{title}}`, `{{namespace}}`, `{{url}}`, `{{actor}}`,
+/// `{{summary}}`, `{{subject}}` and `{{text}}`, escaping the values as JSON
+/// string content when `json` is set.
+pub fn render_template(template: &str, notification: &Notification, json: bool) -> String {
+    let escape = |value: &str| {
+        if json {
+            let quoted = Value::String(value.to_string()).to_string();
+            quoted[1..quoted.len() - 1].to_string()
+        } else {
+            value.to_string()
+        }
+    };
+    // One pass, so placeholders in the values are left as they are.
+    PLACEHOLDER
+        .replace_all(template, |cap: &Captures| {
+            let value = match &cap[1] {
+                "event" => notification.event.clone(),
+                "title" => notification.title.clone(),
+                "namespace" => notification.namespace.clone(),
+                "url" => notification.url.clone(),
+                "actor" => notification.actor.clone().unwrap_or_default(),
+                "summary" => notification.summary.clone(),
+                "subject" => notification.subject(),
+                "text" => notification.text(),
+                _ => return cap[0].to_string(),
+            };
+            escape(&value)
+        })
+        .into_owned()
+}
+
+/// POSTs a templated body to any URL.
+pub struct WebhookNotifier {
+    pub url: String,
+    pub template: String,
+    pub content_type: String,
+    client: reqwest::Client,
+}
+
+impl WebhookNotifier {
+    /// Sends a JSON object with every field of the notification.
+    pub fn new(url: &str) -> WebhookNotifier {
+        WebhookNotifier::with_template(url, DEFAULT_TEMPLATE, "application/json")
+    }
+
+    pub fn with_template(url: &str, template: &str, content_type: &str) -> WebhookNotifier {
+        WebhookNotifier { url: url.to_string(), template: template.to_string(), content_type: content_type.to_string(), client: reqwest::Client::new() }
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn notify(&self, notification: &Notification) -> Result<()> {
+        let body = render_template(&self.template, notification, self.content_type.contains("json"));
+        let resp = self.client.post(&self.url).header(reqwest::header::CONTENT_TYPE, &self.content_type).body(body).send().await?;
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("{} {}", resp.status(), resp.text().await?))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChatFormat {
+    Slack,
+    DingTalk,
+    Feishu,
+}
+
+/// Posts to an incoming webhook of a chat app.
+pub struct ChatNotifier {
+    pub url: String,
+    pub format: ChatFormat,
+    client: reqwest::Client,
+}
+
+impl ChatNotifier {
+    pub fn new(url: &str, format: ChatFormat) -> ChatNotifier {
+        ChatNotifier { url: url.to_string(), format, client: reqwest::Client::new() }
+    }
+
+    pub fn message(&self, notification: &Notification) -> Value {
+        let by = notification.actor.as_ref().map(|actor| format!(" by {}", actor)).unwrap_or_default();
+        match self.format {
+            ChatFormat::Slack => {
+                let mut text = format!("*{}*{}: <{}|{}>", notification.event, by, notification.url, notification.title);
+                if !notification.summary.is_empty() {
+                    text.push_str(&format!("\n```{}```", notification.summary));
+                }
+                json!({ "text": text })
+            }
+            ChatFormat::DingTalk => {
+                let mut text = format!("#### {}{}\n\n[{}]({})", notification.event, by, notification.title, notification.url);
+                if !notification.summary.is_empty() {
+                    let quoted: Vec<String> = notification.summary.lines().map(|line| format!("> {}", line)).collect();
+                    text.push_str(&format!("\n\n{}", quoted.join("\n\n")));
+                }
+                json!({ "msgtype": "markdown", "markdown": { "title": notification.subject(), "text": text } })
+            }
+            ChatFormat::Feishu => {
+                let mut content = vec![vec![json!({ "tag": "a", "text": notification.title, "href": notification.url })]];
+                if let Some(actor) = &notification.actor {
+                    content.push(vec![json!({ "tag": "text", "text": format!("By {}", actor) })]);
+                }
+                if !notification.summary.is_empty() {
+                    content.push(vec![json!({ "tag": "text", "text": notification.summary })]);
+                }
+                json!({ "msg_type": "post", "content": { "post": { "zh_cn": { "title": notification.subject(), "content": content } } } })
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for ChatNotifier {
+    async fn notify(&self, notification: &Notification) -> Result<()> {
+        let resp = self.client.post(&self.url).json(&self.message(notification)).send().await?;
+        let status = resp.status();
+        let text = resp.text().await?;
+        if !status.is_success() {
+            return Err(anyhow::anyhow!("{} {}", status, text));
+        }
+        // DingTalk and Feishu report errors in the body of a 200 response.
+        let body: Value = serde_json::from_str(&text).unwrap_or_default();
+        match body.get("errcode").or_else(|| body.get("code")).and_then(Value::as_i64) {
+            Some(code) if code != 0 => Err(anyhow::anyhow!("{} {}", status, text)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "email")]
+mod email {
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use lettre::message::Mailbox;
+    use lettre::transport::smtp::authentication::Credentials;
+    use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+    use super::{Notification, Notifier};
+
+    #[derive(Debug, Clone, Default)]
+    pub struct SmtpOptions {
+        pub host: String,
+        /// Defaults to 587 with `starttls`, 25 otherwise.
+        pub port: Option<u16>,
+        pub username: Option<String>,
+        pub password: Option<String>,
+        pub starttls: bool,
+        pub from: String,
+        pub to: Vec<String>,
+    }
+
+    /// Emails the plain text of notifications.
+    pub struct EmailNotifier {
+        transport: AsyncSmtpTransport<Tokio1Executor>,
+        from: Mailbox,
+        to: Vec<Mailbox>,
+    }
+
+    impl EmailNotifier {
+        pub fn new(options: &SmtpOptions) -> Result<EmailNotifier> {
+            let mut builder = if options.starttls {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&options.host)?
+            } else {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&options.host).port(25)
+            };
+            if let Some(port) = options.port {
+                builder = builder.port(port);
+            }
+            if let (Some(username), Some(password)) = (&options.username, &options.password) {
+                builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+            }
+            let to = options.to.iter().map(|to| to.parse()).collect::<Result<Vec<Mailbox>, _>>()?;
+            if to.is_empty() {
+                return Err(anyhow::anyhow!("no recipients"));
+            }
+            Ok(EmailNotifier { transport: builder.build(), from: options.from.parse()?, to })
+        }
+    }
+
+    #[async_trait]
+    impl Notifier for EmailNotifier {
+        async fn notify(&self, notification: &Notification) -> Result<()> {
+            let mut message = Message::builder().from(self.from.clone()).subject(notification.subject());
+            for to in &self.to {
+                message = message.to(to.clone());
+            }
+            self.transport.send(message.body(notification.text())?).await?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "email")]
+pub use email::{EmailNotifier, SmtpOptions};
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+    use crate::notify::{diff_summary, notify_all, render_template, ChatFormat, ChatNotifier, Notification, Notifier, WebhookNotifier};
+    use crate::serializer::DocSerializer;
+    use crate::watch::WatchEvent;
+    use crate::webhook::{DocWebhook, WebhookEvent, WebhookKind};
+
+    /// Answers one HTTP request with `response` and returns the request body.
+    async fn http_stand_in(response: &'static str) -> (String, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).await.unwrap();
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).await.unwrap();
+            let reply = format!("HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}", response.len(), response);
+            reader.get_mut().write_all(reply.as_bytes()).await.unwrap();
+            String::from_utf8(body).unwrap()
+        });
+        (url, handle)
+    }
+
+    /// Accepts one message over SMTP and returns its data.
+    #[cfg(feature = "email")]
+    async fn smtp_stand_in() -> (u16, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(stream);
+            reader.get_mut().write_all(b"220 localhost ready\r\n").await.unwrap();
+            let (mut data, mut in_data) = (String::new(), false);
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).await.unwrap() == 0 {
+                    return data;
+                }
+                let reply: &[u8] = if in_data {
+                    if line == ".\r\n" {
+                        in_data = false;
+                        b"250 queued\r\n"
+                    } else {
+                        data.push_str(&line);
+                        continue;
+                    }
+                } else if line.starts_with("DATA") {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                } else if line.starts_with("QUIT") {
+                    reader.get_mut().write_all(b"221 bye\r\n").await.unwrap();
+                    return data;
+                } else {
+                    b"250 ok\r\n"
+                };
+                reader.get_mut().write_all(reply).await.unwrap();
+            }
+        });
+        (port, handle)
+    }
+
+    fn notification() -> Notification {
+        let before = DocSerializer { title: "Pager".to_string(), slug: "pager".to_string(), body: Some("# Pager\n\nCall Bob.\n".to_string()), ..DocSerializer::default() };
+        let after = DocSerializer { body: Some("# Pager\n\nCall \"Alice\".\n".to_string()), ..before.clone() };
+        Notification::from(&WatchEvent::DocUpdated { namespace: "ops/runbook".to_string(), before: Box::new(before), after: Box::new(after) })
+    }
+
+    #[test]
+    fn test_notification() {
+        let notification = notification();
+        assert_eq!(notification.url, "https://www.yuque.com/ops/runbook/pager");
+        assert_eq!(notification.subject(), "[ops/runbook] Doc updated: Pager");
+        assert_eq!(notification.summary, "+1 -1 lines\n- Call Bob.\n+ Call \"Alice\".");
+
+        let many = diff_summary("a\nb\nc\nd\n", "a\n1\n2\n3\n4\n5\n");
+        assert!(many.starts_with("+5 -3 lines\n- b\n- c\n- d\n+ 1\n+ 2\n+ 3\n..."), "{}", many);
+
+        let body = render_template(r#"{"text": "{{subject}}: {{summary}}"}"#, &notification, true);
+        let value: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(value["text"], "[ops/runbook] Doc updated: Pager: +1 -1 lines\n- Call Bob.\n+ Call \"Alice\".");
+
+        let tricky = Notification { title: "{{url}}".to_string(), ..notification.clone() };
+        assert_eq!(render_template("{{title}} {{url}} {{unknown}}", &tricky, false), "{{url}} https://www.yuque.com/ops/runbook/pager {{unknown}}");
+
+        let doc = DocSerializer { slug: "intro".to_string(), title: "Intro".to_string(), ..DocSerializer::default() };
+        let hook = DocWebhook { doc, webhook_subject_type: WebhookKind::Update, actor_id: None, actor: None, path: Some("team/kb/intro".to_string()) };
+        let from_hook = Notification::from(&WebhookEvent::Doc(Box::new(hook)));
+        assert_eq!((from_hook.namespace.as_str(), from_hook.url.as_str()), ("team/kb", "https://www.yuque.com/team/kb/intro"));
+
+        let feishu = ChatNotifier::new("http://localhost", ChatFormat::Feishu).message(&notification);
+        assert_eq!(feishu["content"]["post"]["zh_cn"]["content"][0][0]["href"], "https://www.yuque.com/ops/runbook/pager");
+        let dingtalk = ChatNotifier::new("http://localhost", ChatFormat::DingTalk).message(&notification);
+        assert!(dingtalk["markdown"]["text"].as_str().unwrap().contains("> - Call Bob."));
+    }
+
+    #[tokio::test]
+    async fn test_http_notifiers() {
+        let (url, request) = http_stand_in("ok").await;
+        WebhookNotifier::new(&url).notify(&notification()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_str(&request.await.unwrap()).unwrap();
+        assert_eq!((body["event"].as_str(), body["url"].as_str()), (Some("Doc updated"), Some("https://www.yuque.com/ops/runbook/pager")));
+
+        let (url, request) = http_stand_in("ok").await;
+        let notifiers: Vec<Box<dyn Notifier>> = vec![Box::new(ChatNotifier::new(&url, ChatFormat::Slack))];
+        notify_all(&notifiers, &notification()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_str(&request.await.unwrap()).unwrap();
+        assert_eq!(body["text"], "*Doc updated*: <https://www.yuque.com/ops/runbook/pager|Pager>\n```+1 -1 lines\n- Call Bob.\n+ Call \"Alice\".```");
+
+        let (url, _) = http_stand_in(r#"{"errcode": 310000, "errmsg": "keywords not in content"}"#).await;
+        assert!(ChatNotifier::new(&url, ChatFormat::DingTalk).notify(&notification()).await.is_err());
+    }
+
+    #[cfg(feature = "email")]
+    #[tokio::test]
+    async fn test_email_notifier() {
+        use crate::notify::{EmailNotifier, SmtpOptions};
+
+        let (port, data) = smtp_stand_in().await;
+        let options = SmtpOptions {
+            host: "127.0.0.1".to_string(),
+            port: Some(port),
+            from: "Yuque <yuque@example.com>".to_string(),
+            to: vec!["oncall@example.com".to_string()],
+            ..SmtpOptions::default()
+        };
+        EmailNotifier::new(&options).unwrap().notify(&notification()).await.unwrap();
+        let data = data.await.unwrap();
+        assert!(data.contains("Subject: [ops/runbook] Doc updated: Pager"), "{}", data);
+        assert!(data.contains("https://www.yuque.com/ops/runbook/pager"));
+    }
+}
@@ -1,9 +1,11 @@
 use std::fmt::{Display, self};
+use std::str::FromStr;
 use anyhow::Result;
 use serde::Serialize;
 
-use crate::{yuque::Yuque, serializer::{BookSerializer, APIResponse}};
+use crate::{cache::repo_patterns, yuque::Yuque, serializer::{BookSerializer, APIResponse}};
 
+#[derive(Debug, Clone, Copy)]
 pub enum ObjType {
     Group,
     User,
@@ -19,7 +21,19 @@ impl Display for ObjType {
     }
 }
 
-#[derive(Debug, Serialize)]
+impl FromStr for ObjType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<ObjType> {
+        match s {
+            "groups" => Ok(ObjType::Group),
+            "users" => Ok(ObjType::User),
+            _ => Err(anyhow::anyhow!("unknown object type {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
 pub enum RepoType {
     Book,
     Design
@@ -35,7 +49,17 @@ impl Display for RepoType {
     }
 }
 
-#[derive(Debug)]
+impl RepoType {
+    /// Parses the `type` of a `BookSerializer`, defaulting to `Book`.
+    pub fn from_type(typ: Option<&str>) -> RepoType {
+        match typ {
+            Some("Design") => RepoType::Design,
+            _ => RepoType::Book,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 pub enum RepoPublic {
     Private, // 私密
     Public, // 所有人可见
@@ -56,6 +80,21 @@ impl From<RepoPublic> for u8 {
     }
 }
 
+impl TryFrom<u8> for RepoPublic {
+    type Error = anyhow::Error;
+
+    fn try_from(p: u8) -> Result<RepoPublic> {
+        match p {
+            0 => Ok(RepoPublic::Private),
+            1 => Ok(RepoPublic::Public),
+            2 => Ok(RepoPublic::GroupMember),
+            3 => Ok(RepoPublic::GroupAll),
+            4 => Ok(RepoPublic::RepoMember),
+            _ => Err(anyhow::anyhow!("unknown repo public value {}", p)),
+        }
+    }
+}
+
 impl Serialize for RepoPublic {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -108,12 +147,18 @@ impl Yuque {
             query.push(("offset", offset.to_string()));
         }
 
-        let resp = self.client.get(api).query(&query).send().await?;
-        if resp.status().is_success() {
-            let resp = resp.json::<APIResponse<Vec<BookSerializer>>>().await?;
-            Ok(resp.data)
-        } else {
-            Err(anyhow::anyhow!("{} {}", resp.status(), resp.text().await?))
+        let resp = self.get_json::<APIResponse<Vec<BookSerializer>>>(self.client.get(api).query(&query)).await?;
+        Ok(resp.data)
+    }
+
+    pub async fn list_all_repos<T: Display>(&self, obj_type: ObjType, obj: T, repo_type: Option<RepoType>) -> Result<Vec<BookSerializer>> {
+        let mut repos = Vec::new();
+        loop {
+            let page = self.list_repos(obj_type, &obj, repo_type, Some(repos.len() as u32)).await?;
+            if page.is_empty() {
+                return Ok(repos);
+            }
+            repos.extend(page);
         }
     }
 
@@ -121,19 +166,15 @@ impl Yuque {
         let endpoint = format!("/repos/{}", repo);
         let api = Yuque::build_api(&endpoint, None);
 
-        let resp = self.client.get(api).send().await?;
-        if resp.status().is_success() {
-            let resp = resp.json::<APIResponse<BookSerializer>>().await?;
-            Ok(resp)
-        } else {
-            Err(anyhow::anyhow!("{} {}", resp.status(), resp.text().await?))
-        }
+        let resp = self.get_json::<APIResponse<BookSerializer>>(self.client.get(api)).await?;
+        Ok(resp)
     }
 
     pub async fn delete_repo<T: Display>(&self, repo: T) -> Result<()> {
         let endpoint = format!("/repos/{}", repo);
         let api = Yuque::build_api(&endpoint, None);
         let resp = self.client.delete(api).send().await?;
+        self.invalidate_cache(&repo_patterns(&repo));
         if resp.status().is_success() {
             Ok(())
         } else {
@@ -145,6 +186,7 @@ impl Yuque {
         let endpoint = format!("/{}/{}/repos", obj_type, obj);
         let api = Yuque::build_api(&endpoint, None);
         let resp = self.client.post(api).json(req).send().await?;
+        self.invalidate_cache(&[format!("/{}/{}/repos", obj_type, obj)]);
         if resp.status().is_success() {
             let resp = resp.json::<APIResponse<BookSerializer>>().await?;
             Ok(resp.data)
@@ -157,6 +199,7 @@ impl Yuque {
         let endpoint = format!("/repos/{}", repo);
         let api = Yuque::build_api(&endpoint, None);
         let resp = self.client.put(api).json(req).send().await?;
+        self.invalidate_cache(&repo_patterns(&repo));
         if resp.status().is_success() {
             let resp = resp.json::<APIResponse<BookSerializer>>().await?;
             Ok(resp.data)
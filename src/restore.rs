@@ -0,0 +1,221 @@
+use std::collections::{HashMap, HashSet};
+use anyhow::Result;
+
+use crate::{
+    backup::{BackupArchive, BodyFormat, DocBackup, RepoBackup},
+    doc::{CreateDocRequest, DocFormat},
+    links::LinkMap,
+    repo::{CreateRepoRequest, ObjType, RepoPublic, RepoType, UpdateRepoRequest},
+    toc::{merge_toc, parse_toc, render_toc_markdown, TocItemType},
+    utils::{is_not_found, unique_slug},
+    yuque::Yuque,
+};
+
+#[derive(Debug, Default)]
+pub struct RestoreOptions {
+    /// Restore into this user or group (by login) instead of the original owner.
+    pub target: Option<(ObjType, String)>,
+    /// Only restore repos with these slugs.
+    pub repos: Option<Vec<String>>,
+    /// Only report what would be created.
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct RestoreReport {
+    pub repos: Vec<RestoredRepo>,
+}
+
+#[derive(Debug)]
+pub struct RestoredRepo {
+    pub source_namespace: String,
+    pub namespace: String,
+    /// `false` when docs are added to an existing repo.
+    pub create_repo: bool,
+    pub docs: Vec<RestoredDoc>,
+    /// Docs of the backup that have no body to restore.
+    pub skipped: Vec<SkippedDoc>,
+}
+
+#[derive(Debug)]
+pub struct SkippedDoc {
+    pub source_id: u32,
+    pub title: String,
+    pub slug: String,
+}
+
+#[derive(Debug)]
+pub struct RestoredDoc {
+    pub source_id: u32,
+    pub title: String,
+    pub slug: String,
+    pub format: BodyFormat,
+    /// `None` on dry runs.
+    pub doc_id: Option<u32>,
+}
+
+/// Picks the richest body stored for a doc.
+pub fn preferred_body(archive: &BackupArchive, doc: &DocBackup) -> Option<(BodyFormat, String)> {
+    [BodyFormat::Lake, BodyFormat::Markdown, BodyFormat::Html]
+        .into_iter()
+        .find_map(|format| archive.body(doc, format).map(|body| (format, body)))
+}
+
+fn doc_format(format: BodyFormat) -> DocFormat {
+    match format {
+        BodyFormat::Markdown => DocFormat::Markdown,
+        BodyFormat::Lake => DocFormat::Lake,
+        BodyFormat::Html => DocFormat::Html,
+    }
+}
+
+impl Yuque {
+    /// Recreates the repos and docs of a backup, rewriting links between
+    /// them and rebuilding each TOC.
+    pub async fn restore(&self, archive: &BackupArchive, options: &RestoreOptions) -> Result<RestoreReport> {
+        let manifest = &archive.manifest;
+        if manifest.base.is_some() {
+            return Err(anyhow::anyhow!("incremental backup must be merged into its base before restoring"));
+        }
+        let (obj_type, owner) = match &options.target {
+            Some((obj_type, owner)) => (*obj_type, owner.clone()),
+            None => (manifest.owner_type.parse()?, manifest.owner.clone()),
+        };
+
+        let mut report = RestoreReport::default();
+        let mut links = LinkMap::default();
+        let mut sources = Vec::new();
+        for repo in &manifest.repos {
+            if let Some(slugs) = &options.repos {
+                if !slugs.contains(&repo.book.slug) {
+                    continue;
+                }
+            }
+            let source_namespace = repo.book.namespace.clone().unwrap_or_else(|| format!("{}/{}", manifest.owner, repo.book.slug));
+            let namespace = format!("{}/{}", owner, repo.book.slug);
+            let restored = self.plan_repo_restore(archive, repo, source_namespace, namespace, &mut links).await?;
+            report.repos.push(restored);
+            sources.push(repo);
+        }
+
+        if options.dry_run {
+            return Ok(report);
+        }
+
+        for (restored, source) in report.repos.iter_mut().zip(sources) {
+            self.apply_repo_restore(archive, source, restored, obj_type, &owner, &links).await?;
+        }
+        Ok(report)
+    }
+
+    /// Decides the slug of every restored doc, avoiding docs already in the
+    /// target repo, and records the moves in `links`.
+    pub(crate) async fn plan_repo_restore(&self, archive: &BackupArchive, repo: &RepoBackup, source_namespace: String, namespace: String, links: &mut LinkMap) -> Result<RestoredRepo> {
+        links.insert_namespace(&source_namespace, &namespace);
+
+        let mut taken = HashSet::new();
+        let create_repo = match self.get_repo(&namespace).await {
+            Ok(_) => {
+                taken.extend(self.list_all_docs(&namespace, None).await?.into_iter().map(|doc| doc.slug));
+                false
+            }
+            Err(e) if is_not_found(&e) => true,
+            Err(e) => return Err(e),
+        };
+
+        let mut docs = Vec::new();
+        let mut skipped = Vec::new();
+        for doc in &repo.docs {
+            let Some((format, _)) = preferred_body(archive, doc) else {
+                skipped.push(SkippedDoc { source_id: doc.doc.object.id, title: doc.doc.title.clone(), slug: doc.doc.slug.clone() });
+                continue;
+            };
+            let slug = unique_slug(&doc.doc.slug, &taken);
+            taken.insert(slug.clone());
+            links.insert_slug(&source_namespace, &doc.doc.slug, &slug);
+            links.insert_id(doc.doc.object.id, &namespace, &slug);
+            docs.push(RestoredDoc {
+                source_id: doc.doc.object.id,
+                title: doc.doc.title.clone(),
+                slug,
+                format,
+                doc_id: None,
+            });
+        }
+        Ok(RestoredRepo { source_namespace, namespace, create_repo, docs, skipped })
+    }
+
+    pub(crate) async fn apply_repo_restore(&self, archive: &BackupArchive, source: &RepoBackup, restored: &mut RestoredRepo, obj_type: ObjType, owner: &str, links: &LinkMap) -> Result<()> {
+        if restored.create_repo {
+            let req = CreateRepoRequest {
+                name: source.book.name.clone(),
+                slug: source.book.slug.clone(),
+                description: source.book.description.clone().unwrap_or_default(),
+                public: RepoPublic::try_from(source.book.public).unwrap_or(RepoPublic::Private),
+                typ: RepoType::from_type(source.book.object.typ.as_deref()),
+            };
+            self.create_repo(obj_type, owner, &req).await?;
+        }
+
+        let backups: HashMap<u32, &DocBackup> = source.docs.iter().map(|doc| (doc.doc.object.id, doc)).collect();
+        for doc in restored.docs.iter_mut() {
+            let backup = backups[&doc.source_id];
+            let Some((format, body)) = preferred_body(archive, backup) else {
+                continue;
+            };
+            let req = CreateDocRequest {
+                title: doc.title.clone(),
+                slug: doc.slug.clone(),
+                format: Some(doc_format(format)),
+                body: links.rewrite(&body),
+                public: Some(backup.doc.public),
+            };
+            let created = self.create_doc(&restored.namespace, &req).await?;
+            doc.doc_id = Some(created.object.id);
+        }
+
+        if let Some(toc_yml) = &source.book.toc_yml {
+            let mut items = parse_toc(toc_yml)?;
+            for item in items.iter_mut().filter(|item| item.typ == TocItemType::Doc) {
+                if let Some(slug) = links.slugs.get(&(restored.source_namespace.clone(), item.url.clone())) {
+                    item.url = slug.clone();
+                }
+            }
+            // Docs restored into an existing repo go after its own TOC.
+            if !restored.create_repo {
+                let existing = self.get_repo(&restored.namespace).await?.data;
+                items = merge_toc(parse_toc(existing.toc_yml.as_deref().unwrap_or_default())?, &items);
+            }
+            let req = UpdateRepoRequest {
+                toc: Some(render_toc_markdown(&items)),
+                ..UpdateRepoRequest::default()
+            };
+            self.update_repo(&restored.namespace, &req).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backup::{BackupArchive, BodyFormat};
+    use crate::repo::ObjType;
+    use crate::restore::preferred_body;
+    use crate::serializer::DocSerializer;
+
+    #[test]
+    fn test_preferred_body() {
+        let mut archive = BackupArchive::new(ObjType::User, "me");
+        let doc = DocSerializer {
+            slug: "a".to_string(),
+            body: Some("# A".to_string()),
+            body_lake: Some("<p>A</p>".to_string()),
+            ..DocSerializer::default()
+        };
+        let doc = archive.add_doc("kb", doc);
+        assert_eq!(preferred_body(&archive, &doc), Some((BodyFormat::Lake, "<p>A</p>".to_string())));
+
+        let doc = archive.add_doc("kb", DocSerializer { slug: "b".to_string(), body: Some("# B".to_string()), ..DocSerializer::default() });
+        assert_eq!(preferred_body(&archive, &doc), Some((BodyFormat::Markdown, "# B".to_string())));
+    }
+}
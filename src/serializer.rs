@@ -1,4 +1,4 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize)]
 pub struct APIResponse<T> {
@@ -27,17 +27,17 @@ pub struct BaseAbilities {
     pub destroy: Option<bool>,
 }
 
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Object {
     pub id: u32,
-    #[serde(rename(deserialize = "type"), default)]
+    #[serde(rename = "type", default)]
     pub typ: Option<String>,
     pub created_at: String,
     pub updated_at: String,
     pub _serializer: String,
 }
 
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct UserSerializer {
     #[serde(flatten)]
     pub object: Object,
@@ -56,7 +56,7 @@ pub struct UserSerializer {
     pub description: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct GroupSerializer {
     #[serde(flatten)]
     pub object: Object,
@@ -75,7 +75,7 @@ pub struct GroupSerializer {
     pub description: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct GroupUserSerializer {
     #[serde(flatten)]
     pub object: Object,
@@ -89,7 +89,7 @@ pub struct GroupUserSerializer {
     pub status: u8,
 }
 
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct BookSerializer {
     #[serde(flatten)]
     pub object: Object,
@@ -121,7 +121,7 @@ pub struct BookSerializer {
     pub archived_at: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct DocSerializer {
     #[serde(flatten)]
     pub object: Object,
@@ -177,3 +177,37 @@ pub struct DocSerializer {
     #[serde(default)]
     pub deleted_at: Option<String>,
 }
+
+/// Author of a doc version, as embedded in `DocVersionSerializer`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct VersionUserSerializer {
+    pub id: u32,
+    #[serde(default)]
+    pub login: String,
+    #[serde(default)]
+    pub name: String,
+}
+
+/// A saved version of a doc. Bodies are only filled in by `get_doc_version`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct DocVersionSerializer {
+    #[serde(flatten)]
+    pub object: Object,
+    pub doc_id: u32,
+    #[serde(default)]
+    pub slug: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub format: Option<String>,
+    #[serde(default)]
+    pub user_id: Option<u32>,
+    #[serde(default)]
+    pub user: Option<VersionUserSerializer>,
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default)]
+    pub body_html: Option<String>,
+    #[serde(default)]
+    pub body_asl: Option<String>,
+}
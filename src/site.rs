@@ -0,0 +1,214 @@
+use std::fmt::Display;
+use std::path::Path;
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::backup::{BackupArchive, BackupOptions};
+use crate::dom::{self, escape, Node};
+use crate::export::{write_file, ExportRepo};
+use crate::html::sanitize;
+use crate::links::PathMap;
+use crate::toc::{toc_tree, TocItemType, TocNode};
+use crate::yuque::Yuque;
+
+pub static SEARCH_INDEX_FILE: &str = "search-index.json";
+pub static SITEMAP_FILE: &str = "sitemap.xml";
+
+pub(crate) static STYLE: &str = "body{margin:0;font:16px/1.6 -apple-system,BlinkMacSystemFont,\"Segoe UI\",sans-serif;color:#262626;display:flex}\
+nav{width:280px;min-height:100vh;padding:16px;box-sizing:border-box;border-right:1px solid #e8e8e8;background:#fafafa}\
+nav ul{list-style:none;padding-left:12px}nav a{color:inherit;text-decoration:none}nav a.active{color:#00b96b;font-weight:600}\
+main{flex:1;max-width:860px;padding:24px 48px}img{max-width:100%}pre{background:#f6f8fa;padding:12px;overflow:auto}\
+table{border-collapse:collapse}td,th{border:1px solid #d9d9d9;padding:4px 8px}#search{width:100%;box-sizing:border-box}";
+
+static SEARCH_SCRIPT: &str = "(function(){var input=document.getElementById('search'),list=document.getElementById('results'),index=null;\
+input.addEventListener('input',function(){var q=input.value.trim().toLowerCase();\
+if(!index){fetch('search-index.json').then(function(r){return r.json()}).then(function(d){index=d;input.dispatchEvent(new Event('input'))});return}\
+list.innerHTML='';if(!q)return;index.filter(function(p){return (p.title+' '+p.text).toLowerCase().indexOf(q)>=0}).slice(0,20)\
+.forEach(function(p){var li=document.createElement('li'),a=document.createElement('a');a.href=p.url;a.textContent=p.title;li.appendChild(a);list.appendChild(li)})})})();";
+
+#[derive(Debug, Clone, Default)]
+pub struct SiteOptions {
+    /// Public URL of the site, used for the sitemap. Without it the sitemap
+    /// holds relative URLs.
+    pub base_url: Option<String>,
+    /// Defaults to the repo name.
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct SearchEntry {
+    title: String,
+    url: String,
+    text: String,
+}
+
+fn page_path(slug: &str) -> String {
+    format!("{}.html", slug)
+}
+
+/// Whether a TOC link can be used as an `href`: http, https, mailto or a
+/// relative URL, so `javascript:` and the like end up as text.
+fn safe_href(url: &str) -> bool {
+    let url = url.trim_start_matches(|c: char| c.is_whitespace() || c.is_control());
+    match url.find([':', '/', '?', '#']) {
+        Some(i) if url[i..].starts_with(':') => {
+            let scheme = url[..i].to_ascii_lowercase();
+            ["http", "https", "mailto"].contains(&scheme.as_str())
+        }
+        _ => true,
+    }
+}
+
+/// Nested list of the TOC, `link` giving the href of a doc by slug.
+pub(crate) fn render_nav(nodes: &[TocNode], current: Option<&str>, link: &dyn Fn(&str) -> String, out: &mut String) {
+    out.push_str("<ul>");
+    for node in nodes {
+        let item = &node.item;
+        out.push_str("<li>");
+        match item.typ {
+            TocItemType::Doc => {
+                let class = if current == Some(item.url.as_str()) { r#" class="active" aria-current="page""# } else { "" };
+                out.push_str(&format!(r#"<a href="{}"{}>{}</a>"#, escape(&link(&item.url)), class, escape(&item.title)));
+            }
+            TocItemType::Link if safe_href(&item.url) => out.push_str(&format!(r#"<a href="{}">{}</a>"#, escape(&item.url), escape(&item.title))),
+            _ => out.push_str(&format!("<span>{}</span>", escape(&item.title))),
+        }
+        if !node.children.is_empty() {
+            render_nav(&node.children, current, link, out);
+        }
+        out.push_str("</li>");
+    }
+    out.push_str("</ul>");
+}
+
+fn render_page(site_title: &str, title: &str, nav: &str, content: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>{} - {}</title>\n<style>{}</style>\n</head>\n<body>\n<nav><a href=\"index.html\"><strong>{}</strong></a>\n<input id=\"search\" type=\"search\" placeholder=\"Search\"><ul id=\"results\"></ul>\n{}\n</nav>\n<main>\n<h1>{}</h1>\n{}\n</main>\n<script src=\"search.js\"></script>\n</body>\n</html>\n",
+        escape(title),
+        escape(site_title),
+        STYLE,
+        escape(site_title),
+        nav,
+        escape(title),
+        content,
+    )
+}
+
+/// Plain text of an HTML body, for the search index.
+fn plain_text(html: &str) -> String {
+    let text: String = dom::parse(html)
+        .iter()
+        .map(|node| match node {
+            Node::Text(text) => text.clone(),
+            Node::Element(el) => format!("{} ", el.text()),
+        })
+        .collect();
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn sitemap(base_url: &str, entries: &[(String, String)]) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for (path, lastmod) in entries {
+        out.push_str(&format!("  <url><loc>{}{}</loc>", escape(base_url), escape(path)));
+        if let Some(date) = lastmod.get(..10) {
+            out.push_str(&format!("<lastmod>{}</lastmod>", date));
+        }
+        out.push_str("</url>\n");
+    }
+    out.push_str("</urlset>\n");
+    out
+}
+
+/// Renders a repo to a static HTML site under `out`. The output only depends
+/// on the repo, so rebuilding from the same backup gives the same files.
+pub fn build_site(repo: &ExportRepo, out: &Path, options: &SiteOptions) -> Result<()> {
+    let site_title = options.title.clone().unwrap_or_else(|| repo.book.name.clone());
+    let tree = toc_tree(&repo.toc);
+    let mut paths = PathMap::default();
+    for doc in &repo.docs {
+        paths.insert(&repo.namespace, &doc.doc.slug, &page_path(&doc.doc.slug));
+    }
+
+    let mut search = Vec::new();
+    let mut pages = Vec::new();
+    for doc in &repo.docs {
+        let path = page_path(&doc.doc.slug);
+        let content = sanitize(&repo.localize(&doc.html, &path, &paths, "assets"));
+        let mut nav = String::new();
+        render_nav(&tree, Some(&doc.doc.slug), &page_path, &mut nav);
+        write_file(out, &path, render_page(&site_title, &doc.doc.title, &nav, &content).as_bytes())?;
+        search.push(SearchEntry { title: doc.doc.title.clone(), url: path.clone(), text: plain_text(&content) });
+        pages.push((path, doc.doc.content_updated_at.clone()));
+    }
+
+    let mut nav = String::new();
+    render_nav(&tree, None, &page_path, &mut nav);
+    let description = repo.book.description.as_deref().map(|d| format!("<p>{}</p>", escape(d))).unwrap_or_default();
+    write_file(out, "index.html", render_page(&site_title, &site_title, &nav, &format!("{}\n{}", description, nav)).as_bytes())?;
+    pages.insert(0, ("index.html".to_string(), repo.book.content_updated_at.clone().unwrap_or_default()));
+
+    write_file(out, "search.js", SEARCH_SCRIPT.as_bytes())?;
+    write_file(out, SEARCH_INDEX_FILE, &serde_json::to_vec(&search)?)?;
+    let base_url = options.base_url.as_deref().map(|url| format!("{}/", url.trim_end_matches('/'))).unwrap_or_default();
+    write_file(out, SITEMAP_FILE, sitemap(&base_url, &pages).as_bytes())?;
+    repo.write_assets(&out.join("assets"))
+}
+
+/// Builds the site of the repo with `slug` from a backup, without network access.
+pub fn build_site_from_backup(archive: &BackupArchive, slug: &str, out: &Path, options: &SiteOptions) -> Result<()> {
+    build_site(&ExportRepo::from_backup(archive, slug)?, out, options)
+}
+
+impl Yuque {
+    pub async fn export_site<T: Display>(&self, namespace: T, out: &Path, options: &SiteOptions) -> Result<()> {
+        let namespace = namespace.to_string();
+        let archive = self.backup_one_repo(&namespace, &BackupOptions::default()).await?;
+        let slug = namespace.rsplit('/').next().unwrap_or_default();
+        build_site_from_backup(&archive, slug, out, options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::tests::sample_archive;
+    use crate::site::{build_site_from_backup, render_nav, safe_href, SiteOptions};
+    use crate::toc::{toc_tree, TocItem, TocItemType};
+
+    #[test]
+    fn test_build_site() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = SiteOptions { base_url: Some("https://docs.example.com".to_string()), title: None };
+        build_site_from_backup(&sample_archive(), "kb", dir.path(), &options).unwrap();
+
+        let intro = std::fs::read_to_string(dir.path().join("intro.html")).unwrap();
+        assert!(intro.contains(r#"<a href="guide.html#setup""#));
+        assert!(intro.contains(r#"src="assets/"#));
+        assert!(intro.contains(r#"<a href="intro.html" class="active" aria-current="page">Intro</a>"#));
+        assert!(intro.contains("<span>Start</span><ul><li><a href=\"guide.html\">Guide</a>"));
+
+        let index = std::fs::read_to_string(dir.path().join("search-index.json")).unwrap();
+        assert!(index.contains(r#""url":"guide.html","text":"Setup Run make.""#));
+        let sitemap = std::fs::read_to_string(dir.path().join("sitemap.xml")).unwrap();
+        assert!(sitemap.contains("<loc>https://docs.example.com/intro.html</loc><lastmod>2024-01-01</lastmod>"));
+        assert_eq!(std::fs::read_dir(dir.path().join("assets")).unwrap().count(), 1);
+
+        let again = tempfile::tempdir().unwrap();
+        build_site_from_backup(&sample_archive(), "kb", again.path(), &options).unwrap();
+        assert_eq!(std::fs::read(again.path().join("intro.html")).unwrap(), intro.into_bytes());
+    }
+
+    #[test]
+    fn test_safe_href() {
+        for url in ["https://example.com", "HTTP://example.com", "mailto:a@example.com", "guide.html", "../a:b", "#top", "?q=a:b"] {
+            assert!(safe_href(url), "{}", url);
+        }
+        for url in ["javascript:alert(1)", " JavaScript:alert(1)", "data:text/html,x", "vbscript:x"] {
+            assert!(!safe_href(url), "{}", url);
+        }
+
+        let link = |url: &str| TocItem { typ: TocItemType::Link, url: url.to_string(), ..TocItem::doc("X", "", 0) };
+        let mut out = String::new();
+        render_nav(&toc_tree(&[link("javascript:alert(1)"), link("https://example.com")]), None, &|slug| slug.to_string(), &mut out);
+        assert_eq!(out, r#"<ul><li><span>X</span></li><li><a href="https://example.com">X</a></li></ul>"#);
+    }
+}
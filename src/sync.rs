@@ -0,0 +1,382 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::path::Path;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{doc::{CreateDocRequest, UpdateDocRequest}, serializer::DocSerializer, utils::gen_rand_str, yuque::Yuque};
+
+pub static STATE_FILE: &str = ".yuque-sync.json";
+static DOC_EXT: &str = "md";
+
+/// Persisted between runs so that both sides can be compared with the last
+/// synced version instead of with each other.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct SyncState {
+    pub repo: String,
+    /// Keyed by the doc path relative to the synced directory.
+    pub docs: HashMap<String, SyncEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SyncEntry {
+    pub doc_id: u32,
+    pub slug: String,
+    pub content_updated_at: String,
+    pub hash: String,
+}
+
+impl SyncState {
+    pub async fn load(path: &Path) -> Result<SyncState> {
+        match tokio::fs::read(path).await {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SyncState::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        let data = serde_json::to_vec_pretty(self)?;
+        tokio::fs::write(path, data).await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ConflictStrategy {
+    /// Leave both sides untouched and report the conflict.
+    #[default]
+    Report,
+    PreferLocal,
+    PreferRemote,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictKind {
+    BothModified,
+    BothCreated,
+    ModifiedLocallyDeletedRemotely,
+    DeletedLocallyModifiedRemotely,
+    /// A new remote doc maps to a path already synced with another doc.
+    /// Never resolved by a strategy.
+    PathTaken,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncAction {
+    /// Write the remote doc to the local file.
+    Pull { path: String, doc_id: u32 },
+    /// Upload the local file, creating the doc when `doc_id` is `None`.
+    Push { path: String, doc_id: Option<u32> },
+    DeleteLocal { path: String },
+    DeleteRemote { path: String, doc_id: u32 },
+    /// Both sides are gone, only the state entry is left.
+    Forget { path: String },
+    Conflict { path: String, doc_id: Option<u32>, kind: ConflictKind },
+}
+
+#[derive(Debug, Default)]
+pub struct SyncOptions {
+    pub strategy: ConflictStrategy,
+    pub dry_run: bool,
+    /// Defaults to `STATE_FILE` inside the synced directory.
+    pub state_file: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    pub applied: Vec<SyncAction>,
+    pub conflicts: Vec<SyncAction>,
+}
+
+pub fn hash_content(content: &str) -> String {
+    hex::encode(Sha256::digest(content.as_bytes()))
+}
+
+fn doc_path(slug: &str) -> String {
+    format!("{}.{}", slug, DOC_EXT)
+}
+
+/// Compares the last synced state with the current local hashes (keyed by
+/// path) and remote docs, and returns what has to happen on each side.
+pub fn plan(state: &SyncState, local: &HashMap<String, String>, remote: &[DocSerializer]) -> Vec<SyncAction> {
+    let remote_by_id: HashMap<u32, &DocSerializer> = remote.iter().map(|d| (d.object.id, d)).collect();
+    let mut actions = Vec::new();
+    let mut seen_paths: HashSet<String> = HashSet::new();
+    let mut seen_ids = HashSet::new();
+
+    let mut tracked: Vec<_> = state.docs.iter().collect();
+    tracked.sort_by(|a, b| a.0.cmp(b.0));
+    for (path, entry) in tracked {
+        seen_paths.insert(path.clone());
+        seen_ids.insert(entry.doc_id);
+        let path = path.clone();
+        let doc_id = entry.doc_id;
+        let local_changed = local.get(&path).map(|hash| hash != &entry.hash);
+        let remote_changed = remote_by_id.get(&doc_id).map(|doc| doc.content_updated_at != entry.content_updated_at);
+        let action = match (local_changed, remote_changed) {
+            (Some(false), Some(false)) => continue,
+            (Some(true), Some(false)) => SyncAction::Push { path, doc_id: Some(doc_id) },
+            (Some(false), Some(true)) => SyncAction::Pull { path, doc_id },
+            (Some(true), Some(true)) => SyncAction::Conflict { path, doc_id: Some(doc_id), kind: ConflictKind::BothModified },
+            (None, Some(false)) => SyncAction::DeleteRemote { path, doc_id },
+            (None, Some(true)) => SyncAction::Conflict { path, doc_id: Some(doc_id), kind: ConflictKind::DeletedLocallyModifiedRemotely },
+            (Some(false), None) => SyncAction::DeleteLocal { path },
+            (Some(true), None) => SyncAction::Conflict { path, doc_id: None, kind: ConflictKind::ModifiedLocallyDeletedRemotely },
+            (None, None) => SyncAction::Forget { path },
+        };
+        actions.push(action);
+    }
+
+    let mut new_remote: Vec<_> = remote.iter().filter(|d| !seen_ids.contains(&d.object.id)).collect();
+    new_remote.sort_by(|a, b| a.slug.cmp(&b.slug));
+    for doc in new_remote {
+        let path = doc_path(&doc.slug);
+        let action = if seen_paths.contains(&path) {
+            SyncAction::Conflict { path: path.clone(), doc_id: Some(doc.object.id), kind: ConflictKind::PathTaken }
+        } else if local.contains_key(&path) {
+            SyncAction::Conflict { path: path.clone(), doc_id: Some(doc.object.id), kind: ConflictKind::BothCreated }
+        } else {
+            SyncAction::Pull { path: path.clone(), doc_id: doc.object.id }
+        };
+        seen_paths.insert(path);
+        actions.push(action);
+    }
+
+    let mut new_local: Vec<_> = local.keys().filter(|p| !seen_paths.contains(*p)).collect();
+    new_local.sort();
+    for path in new_local {
+        actions.push(SyncAction::Push { path: path.clone(), doc_id: None });
+    }
+    actions
+}
+
+/// Turns a conflict into a regular action according to the strategy.
+pub fn resolve(action: SyncAction, strategy: ConflictStrategy) -> SyncAction {
+    let (path, doc_id, kind) = match action {
+        SyncAction::Conflict { path, doc_id, kind } => (path, doc_id, kind),
+        action => return action,
+    };
+    match (strategy, kind, doc_id) {
+        (_, ConflictKind::PathTaken, doc_id) => SyncAction::Conflict { path, doc_id, kind },
+        (ConflictStrategy::PreferLocal, ConflictKind::DeletedLocallyModifiedRemotely, Some(doc_id)) => SyncAction::DeleteRemote { path, doc_id },
+        (ConflictStrategy::PreferLocal, _, doc_id) => SyncAction::Push { path, doc_id },
+        (ConflictStrategy::PreferRemote, ConflictKind::ModifiedLocallyDeletedRemotely, _) => SyncAction::DeleteLocal { path },
+        (ConflictStrategy::PreferRemote, _, Some(doc_id)) => SyncAction::Pull { path, doc_id },
+        (_, kind, doc_id) => SyncAction::Conflict { path, doc_id, kind },
+    }
+}
+
+pub(crate) fn title_from_markdown(content: &str) -> Option<String> {
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("# "))
+        .map(|title| title.trim().to_string())
+}
+
+fn slug_from_path(path: &str) -> String {
+    let stem = path.trim_end_matches(&format!(".{}", DOC_EXT));
+    let valid = !stem.is_empty() && stem.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+    if valid {
+        stem.to_string()
+    } else {
+        gen_rand_str()
+    }
+}
+
+async fn scan_local(dir: &Path, state_file: &Path) -> Result<HashMap<String, String>> {
+    let mut local = HashMap::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path == state_file || !entry.file_type().await?.is_file() {
+            continue;
+        }
+        if path.extension().and_then(|e| e.to_str()) != Some(DOC_EXT) {
+            continue;
+        }
+        let content = tokio::fs::read_to_string(&path).await?;
+        local.insert(entry.file_name().to_string_lossy().into_owned(), hash_content(&content));
+    }
+    Ok(local)
+}
+
+impl Yuque {
+    /// Keeps the markdown files in `dir` and the docs of `repo` in sync. Each
+    /// doc is stored as `<slug>.md`; files added locally are created remotely
+    /// with the file stem as slug.
+    pub async fn sync_repo<T: Display>(&self, repo: T, dir: &Path, options: &SyncOptions) -> Result<SyncReport> {
+        let repo = repo.to_string();
+        let state_file = match &options.state_file {
+            Some(state_file) => Path::new(state_file).to_path_buf(),
+            None => dir.join(STATE_FILE),
+        };
+        let mut state = SyncState::load(&state_file).await?;
+        if !state.repo.is_empty() && state.repo != repo {
+            return Err(anyhow::anyhow!("state file belongs to repo {}", state.repo));
+        }
+        state.repo = repo.clone();
+
+        let local = scan_local(dir, &state_file).await?;
+        let remote = self.list_all_docs(&repo, None).await?;
+
+        let mut report = SyncReport::default();
+        for action in plan(&state, &local, &remote) {
+            let action = resolve(action, options.strategy);
+            if let SyncAction::Conflict { .. } = action {
+                report.conflicts.push(action);
+                continue;
+            }
+            if !options.dry_run {
+                self.apply_sync_action(&repo, dir, &mut state, &action).await?;
+                state.save(&state_file).await?;
+            }
+            report.applied.push(action);
+        }
+        Ok(report)
+    }
+
+    async fn apply_sync_action(&self, repo: &str, dir: &Path, state: &mut SyncState, action: &SyncAction) -> Result<()> {
+        match action {
+            SyncAction::Pull { path, doc_id } => {
+                let doc = self.get_doc(repo.to_string(), doc_id.to_string(), Some(true)).await?;
+                let body = doc.body.unwrap_or_default();
+                tokio::fs::write(dir.join(path), &body).await?;
+                state.docs.insert(path.clone(), SyncEntry {
+                    doc_id: doc.object.id,
+                    slug: doc.slug,
+                    content_updated_at: doc.content_updated_at,
+                    hash: hash_content(&body),
+                });
+            }
+            SyncAction::Push { path, doc_id } => {
+                let body = tokio::fs::read_to_string(dir.join(path)).await?;
+                let title = title_from_markdown(&body);
+                let doc = match doc_id {
+                    Some(doc_id) => {
+                        let mut req = UpdateDocRequest {
+                            title,
+                            body: Some(body.clone()),
+                            _force_asl: Some(1),
+                            ..UpdateDocRequest::default()
+                        };
+                        self.update_doc(repo, *doc_id, &mut req).await?
+                    }
+                    None => {
+                        let req = CreateDocRequest {
+                            title: title.unwrap_or_else(|| path.trim_end_matches(&format!(".{}", DOC_EXT)).to_string()),
+                            slug: slug_from_path(path),
+                            body: body.clone(),
+                            ..CreateDocRequest::default()
+                        };
+                        self.create_doc(repo, &req).await?
+                    }
+                };
+                state.docs.insert(path.clone(), SyncEntry {
+                    doc_id: doc.object.id,
+                    slug: doc.slug,
+                    content_updated_at: doc.content_updated_at,
+                    hash: hash_content(&body),
+                });
+            }
+            SyncAction::DeleteLocal { path } => {
+                match tokio::fs::remove_file(dir.join(path)).await {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                    _ => {}
+                }
+                state.docs.remove(path);
+            }
+            SyncAction::DeleteRemote { path, doc_id } => {
+                self.delete_doc(repo, *doc_id).await?;
+                state.docs.remove(path);
+            }
+            SyncAction::Forget { path } => {
+                state.docs.remove(path);
+            }
+            SyncAction::Conflict { .. } => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::serializer::DocSerializer;
+    use crate::sync::{plan, resolve, hash_content, ConflictKind, ConflictStrategy, SyncAction, SyncEntry, SyncState};
+
+    fn remote_doc(id: u32, slug: &str, updated_at: &str) -> DocSerializer {
+        let mut doc = DocSerializer {
+            slug: slug.to_string(),
+            content_updated_at: updated_at.to_string(),
+            ..DocSerializer::default()
+        };
+        doc.object.id = id;
+        doc
+    }
+
+    fn synced_state() -> SyncState {
+        let mut state = SyncState::default();
+        state.docs.insert("a.md".to_string(), SyncEntry {
+            doc_id: 1,
+            slug: "a".to_string(),
+            content_updated_at: "t1".to_string(),
+            hash: hash_content("a"),
+        });
+        state
+    }
+
+    #[test]
+    fn test_plan_detects_changed_side() {
+        let state = synced_state();
+        let local = HashMap::from([("a.md".to_string(), hash_content("a"))]);
+
+        let remote = vec![remote_doc(1, "a", "t1")];
+        assert_eq!(plan(&state, &local, &remote), vec![]);
+
+        let remote = vec![remote_doc(1, "a", "t2")];
+        assert_eq!(plan(&state, &local, &remote), vec![SyncAction::Pull { path: "a.md".to_string(), doc_id: 1 }]);
+
+        let local = HashMap::from([("a.md".to_string(), hash_content("b"))]);
+        let remote = vec![remote_doc(1, "a", "t1")];
+        assert_eq!(plan(&state, &local, &remote), vec![SyncAction::Push { path: "a.md".to_string(), doc_id: Some(1) }]);
+    }
+
+    #[test]
+    fn test_plan_reports_conflicts() {
+        let state = synced_state();
+        let local = HashMap::from([("a.md".to_string(), hash_content("b"))]);
+        let remote = vec![remote_doc(1, "a", "t2")];
+        let actions = plan(&state, &local, &remote);
+        assert_eq!(actions, vec![SyncAction::Conflict { path: "a.md".to_string(), doc_id: Some(1), kind: ConflictKind::BothModified }]);
+
+        let resolved = resolve(actions[0].clone(), ConflictStrategy::PreferRemote);
+        assert_eq!(resolved, SyncAction::Pull { path: "a.md".to_string(), doc_id: 1 });
+        let resolved = resolve(actions[0].clone(), ConflictStrategy::Report);
+        assert_eq!(resolved, actions[0]);
+    }
+
+    #[test]
+    fn test_plan_new_and_deleted_docs() {
+        let state = synced_state();
+        let local = HashMap::from([("c.md".to_string(), hash_content("c"))]);
+        let remote = vec![remote_doc(1, "a", "t1"), remote_doc(2, "b", "t1")];
+        assert_eq!(plan(&state, &local, &remote), vec![
+            SyncAction::DeleteRemote { path: "a.md".to_string(), doc_id: 1 },
+            SyncAction::Pull { path: "b.md".to_string(), doc_id: 2 },
+            SyncAction::Push { path: "c.md".to_string(), doc_id: None },
+        ]);
+    }
+
+    #[test]
+    fn test_plan_reports_taken_paths() {
+        let state = synced_state();
+        let local = HashMap::from([("a.md".to_string(), hash_content("a"))]);
+        let remote = vec![remote_doc(1, "b", "t1"), remote_doc(3, "a", "t1")];
+        let actions = plan(&state, &local, &remote);
+        assert_eq!(actions, vec![SyncAction::Conflict { path: "a.md".to_string(), doc_id: Some(3), kind: ConflictKind::PathTaken }]);
+        assert_eq!(resolve(actions[0].clone(), ConflictStrategy::PreferRemote), actions[0]);
+        assert_eq!(resolve(actions[0].clone(), ConflictStrategy::PreferLocal), actions[0]);
+    }
+}
@@ -0,0 +1,260 @@
+use std::fmt::Display;
+use anyhow::Result;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{cache::repo_patterns, serializer::APIResponse, yuque::Yuque};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TocItemType {
+    Meta,
+    Doc,
+    Title,
+    Link,
+    #[serde(other)]
+    Unknown,
+}
+
+/// One line of `BookSerializer.toc_yml`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TocItem {
+    #[serde(rename = "type")]
+    pub typ: TocItemType,
+    #[serde(default)]
+    pub title: String,
+    /// Doc slug for `DOC` items, target URL for `LINK` items.
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub uuid: String,
+    #[serde(default, deserialize_with = "lenient_u32")]
+    pub doc_id: Option<u32>,
+    #[serde(default)]
+    pub level: u32,
+}
+
+impl TocItem {
+    pub fn doc(title: &str, slug: &str, level: u32) -> TocItem {
+        TocItem {
+            typ: TocItemType::Doc,
+            title: title.to_string(),
+            url: slug.to_string(),
+            uuid: String::new(),
+            doc_id: None,
+            level,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TocAction {
+    AppendNode,
+    PrependNode,
+    EditNode,
+    RemoveNode,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TocActionMode {
+    Sibling,
+    Child,
+}
+
+/// Changes a single TOC node, leaving the uuids of the others alone.
+#[derive(Debug, Serialize)]
+pub struct UpdateTocRequest {
+    pub action: TocAction,
+    pub action_mode: TocActionMode,
+    /// The node to act relative to, the root when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_uuid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_uuid: Option<String>,
+    #[serde(rename = "type")]
+    pub typ: TocItemType,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub doc_ids: Vec<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl UpdateTocRequest {
+    /// Appends docs at the end of the TOC.
+    pub fn append_docs(doc_ids: Vec<u32>) -> UpdateTocRequest {
+        UpdateTocRequest {
+            action: TocAction::AppendNode,
+            action_mode: TocActionMode::Child,
+            target_uuid: None,
+            node_uuid: None,
+            typ: TocItemType::Doc,
+            doc_ids,
+            title: None,
+            url: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TocNode {
+    pub item: TocItem,
+    pub children: Vec<TocNode>,
+}
+
+fn lenient_u32<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = serde_yaml::Value::deserialize(deserializer)?;
+    Ok(match value {
+        serde_yaml::Value::Number(n) => n.as_u64().map(|n| n as u32),
+        serde_yaml::Value::String(s) => s.parse().ok(),
+        _ => None,
+    })
+}
+
+/// Parses `toc_yml`, dropping the leading `META` item.
+pub fn parse_toc(toc_yml: &str) -> Result<Vec<TocItem>> {
+    if toc_yml.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let items: Vec<TocItem> = serde_yaml::from_str(toc_yml)?;
+    Ok(items.into_iter().filter(|item| item.typ != TocItemType::Meta).collect())
+}
+
+/// Nests the flat TOC by `level`.
+pub fn toc_tree(items: &[TocItem]) -> Vec<TocNode> {
+    fn build(items: &[TocItem], pos: &mut usize, level: u32) -> Vec<TocNode> {
+        let mut nodes = Vec::new();
+        while *pos < items.len() && items[*pos].level >= level {
+            let item = items[*pos].clone();
+            *pos += 1;
+            let children = build(items, pos, item.level + 1);
+            nodes.push(TocNode { item, children });
+        }
+        nodes
+    }
+    let level = items.iter().map(|item| item.level).min().unwrap_or_default();
+    build(items, &mut 0, level)
+}
+
+/// Appends `added` to `existing`, leaving out docs that are already listed
+/// and the titles that would be left without any of their docs.
+pub fn merge_toc(mut existing: Vec<TocItem>, added: &[TocItem]) -> Vec<TocItem> {
+    let listed: Vec<String> = existing.iter().filter(|item| item.typ == TocItemType::Doc).map(|item| item.url.clone()).collect();
+    let mut keep: Vec<bool> = added.iter().map(|item| item.typ != TocItemType::Doc || !listed.contains(&item.url)).collect();
+    for i in (0..added.len()).rev() {
+        if added[i].typ == TocItemType::Title {
+            let mut children = added[i + 1..].iter().zip(&keep[i + 1..]).take_while(|(item, _)| item.level > added[i].level).peekable();
+            keep[i] = children.peek().is_none() || children.any(|(_, kept)| *kept);
+        }
+    }
+    existing.extend(added.iter().zip(keep).filter(|(_, kept)| *kept).map(|(item, _)| item.clone()));
+    existing
+}
+
+pub(crate) fn escape_title(title: &str) -> String {
+    title.replace('[', "\\[").replace(']', "\\]")
+}
+
+/// Renders the TOC as the markdown list accepted by `UpdateRepoRequest.toc`.
+pub fn render_toc_markdown(items: &[TocItem]) -> String {
+    let mut out = String::new();
+    for item in items {
+        let indent = "  ".repeat(item.level as usize);
+        let title = escape_title(&item.title);
+        match item.typ {
+            TocItemType::Doc | TocItemType::Link => out.push_str(&format!("{}- [{}]({})\n", indent, title, item.url)),
+            TocItemType::Title => out.push_str(&format!("{}- {}\n", indent, title)),
+            TocItemType::Meta | TocItemType::Unknown => {}
+        }
+    }
+    out
+}
+
+impl Yuque {
+    /// Edits the TOC of a repo node by node and returns the new TOC.
+    pub async fn update_toc<T: Display>(&self, repo: T, req: &UpdateTocRequest) -> Result<Vec<TocItem>> {
+        let endpoint = format!("/repos/{}/toc", repo);
+        let api = Yuque::build_api(&endpoint, None);
+        let resp = self.client.put(api).json(req).send().await?;
+        self.invalidate_cache(&repo_patterns(&repo));
+        if resp.status().is_success() {
+            let resp = resp.json::<APIResponse<Vec<TocItem>>>().await?;
+            Ok(resp.data)
+        } else {
+            Err(anyhow::anyhow!("{} {}", resp.status(), resp.text().await?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::toc::{merge_toc, parse_toc, render_toc_markdown, toc_tree, TocItem, TocItemType, UpdateTocRequest};
+
+    static TOC_YML: &str = "- type: META
+  count: 3
+  display_level: 1
+- type: TITLE
+  title: Guide
+  uuid: u1
+  url: ''
+  doc_id: ''
+  level: 0
+- type: DOC
+  title: Install
+  uuid: u2
+  url: install
+  doc_id: 12
+  level: 1
+- type: DOC
+  title: FAQ
+  uuid: u3
+  url: faq
+  doc_id: 13
+  level: 0
+";
+
+    #[test]
+    fn test_parse_toc() {
+        let items = parse_toc(TOC_YML).unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].typ, TocItemType::Title);
+        assert_eq!(items[0].doc_id, None);
+        assert_eq!(items[1].doc_id, Some(12));
+
+        let tree = toc_tree(&items);
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree[0].children[0].item.url, "install");
+
+        assert_eq!(render_toc_markdown(&items), "- Guide\n  - [Install](install)\n- [FAQ](faq)\n");
+
+        let mut items = items;
+        items.push(TocItem::doc("Moved", "moved", 0));
+        assert!(render_toc_markdown(&items).ends_with("- [Moved](moved)\n"));
+        items.push(TocItem::doc("[WIP] Notes", "notes", 0));
+        assert!(render_toc_markdown(&items).ends_with("- [\\[WIP\\] Notes](notes)\n"));
+
+        let req = serde_json::to_value(UpdateTocRequest::append_docs(vec![7])).unwrap();
+        assert_eq!(req, serde_json::json!({"action": "appendNode", "action_mode": "child", "type": "DOC", "doc_ids": [7]}));
+    }
+
+    #[test]
+    fn test_merge_toc() {
+        let existing = parse_toc(TOC_YML).unwrap();
+        let title = |title: &str| TocItem { typ: TocItemType::Title, ..TocItem::doc(title, "", 0) };
+        let added = vec![
+            TocItem::doc("FAQ", "faq", 0),
+            TocItem::doc("New", "new", 0),
+            TocItem::doc("Child", "child", 1),
+            title("Guide"),
+            TocItem::doc("Install", "install", 1),
+            title("Empty"),
+        ];
+        let merged = merge_toc(existing, &added);
+        assert_eq!(render_toc_markdown(&merged), "- Guide\n  - [Install](install)\n- [FAQ](faq)\n- [New](new)\n  - [Child](child)\n- Empty\n");
+    }
+}
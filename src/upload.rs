@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::LazyLock;
+use anyhow::Result;
+use async_trait::async_trait;
+use percent_encoding::percent_decode_str;
+use regex::{Captures, Regex};
+use reqwest::multipart::{Form, Part};
+use serde::Deserialize;
+
+use crate::asset::media_type;
+use crate::doc::{CreateDocRequest, UpdateDocRequest};
+use crate::serializer::{APIResponse, DocSerializer};
+use crate::yuque::Yuque;
+
+/// Not part of the v2 API, it is what the Yuque editor uploads files to.
+static UPLOAD_API: &str = "https://www.yuque.com/api/upload/attach";
+
+/// Stores a local file somewhere reachable and returns its URL.
+#[async_trait]
+pub trait Uploader: Send + Sync {
+    async fn upload(&self, name: &str, data: Vec<u8>) -> Result<String>;
+}
+
+/// Uploads files as attachments of a repo.
+pub struct YuqueUploader<'a> {
+    yuque: &'a Yuque,
+    book_id: u32,
+}
+
+impl<'a> YuqueUploader<'a> {
+    pub fn new(yuque: &'a Yuque, book_id: u32) -> YuqueUploader<'a> {
+        YuqueUploader { yuque, book_id }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct UploadedFile {
+    url: String,
+}
+
+#[async_trait]
+impl Uploader for YuqueUploader<'_> {
+    async fn upload(&self, name: &str, data: Vec<u8>) -> Result<String> {
+        let book_id = self.book_id.to_string();
+        let typ = if media_type(name).starts_with("image/") { "image" } else { "attachment" };
+        let query = [("attachable_type", "Book"), ("attachable_id", book_id.as_str()), ("type", typ)];
+        let form = Form::new().part("file", Part::bytes(data).file_name(name.to_string()));
+        let resp = self.yuque.client.post(UPLOAD_API).query(&query).multipart(form).send().await?;
+        if resp.status().is_success() {
+            let resp = resp.json::<APIResponse<UploadedFile>>().await?;
+            Ok(resp.data.url)
+        } else {
+            Err(anyhow::anyhow!("{} {}", resp.status(), resp.text().await?))
+        }
+    }
+}
+
+static IMAGE_LINK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"(!\[[^\]]*\]\()\s*(<[^>]*>|[^)\s]+)([^)]*\))|(<img\b[^>]*\ssrc=")([^"]+)(")"#).unwrap());
+
+/// The local path of a link target, if it is one. Root-relative links like
+/// `/img/a.png` belong to a site and are left alone.
+fn local_path(target: &str) -> Option<String> {
+    let target = target.trim_start_matches('<').trim_end_matches('>');
+    if target.is_empty() || target.starts_with('#') || target.contains("://") || target.starts_with("//") || target.starts_with("data:") {
+        return None;
+    }
+    let path = target.split(['?', '#']).next().unwrap_or_default();
+    let path = percent_decode_str(path).decode_utf8_lossy().into_owned();
+    if path.starts_with(['/', '\\']) || Path::new(&path).components().any(|c| matches!(c, Component::RootDir | Component::Prefix(_))) {
+        return None;
+    }
+    Some(path)
+}
+
+/// The file `path` points at from `base_dir`, unless it lies outside `root`.
+fn contained(base_dir: &Path, root: &Path, path: &str) -> Result<Option<PathBuf>> {
+    let file = base_dir.join(path);
+    let file = file.canonicalize().map_err(|e| anyhow::anyhow!("{}: {}", file.display(), e))?;
+    Ok(file.starts_with(root.canonicalize()?).then_some(file))
+}
+
+fn link_target<'a>(cap: &'a Captures) -> &'a str {
+    cap.get(2).or(cap.get(5)).map(|m| m.as_str()).unwrap_or_default()
+}
+
+/// Returns the local image paths referenced by a Markdown body, as written.
+pub fn local_images(markdown: &str) -> Vec<String> {
+    let mut paths = Vec::new();
+    for cap in IMAGE_LINK.captures_iter(markdown) {
+        if let Some(path) = local_path(link_target(&cap)) {
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+    }
+    paths
+}
+
+/// Points local image links at the URLs in `urls`, keyed by path as
+/// returned by `local_images`.
+pub fn rewrite_images(markdown: &str, urls: &HashMap<String, String>) -> String {
+    IMAGE_LINK
+        .replace_all(markdown, |cap: &Captures| {
+            let Some(url) = local_path(link_target(cap)).and_then(|path| urls.get(&path)) else {
+                return cap[0].to_string();
+            };
+            match cap.get(1) {
+                Some(open) => format!("{}{}{}", open.as_str(), url, &cap[3]),
+                None => format!("{}{}{}", &cap[4], url, &cap[6]),
+            }
+        })
+        .into_owned()
+}
+
+/// Uploads the local images of a Markdown body, resolving paths against
+/// `base_dir`, and returns the body with the links rewritten. Images outside
+/// of `root` are left alone.
+pub async fn upload_local_images(uploader: &dyn Uploader, markdown: &str, base_dir: &Path, root: &Path) -> Result<String> {
+    let mut urls = HashMap::new();
+    for path in local_images(markdown) {
+        let Some(file) = contained(base_dir, root, &path)? else { continue };
+        let data = tokio::fs::read(&file).await.map_err(|e| anyhow::anyhow!("{}: {}", file.display(), e))?;
+        let name = file.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_else(|| path.clone());
+        let url = uploader.upload(&name, data).await?;
+        urls.insert(path, url);
+    }
+    Ok(rewrite_images(markdown, &urls))
+}
+
+static FILE_LINK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"(!?)(\[[^\]]*\]\()(<[^>]*>|[^)\s]+)"#).unwrap());
+
+/// Links to other pages, which an import turns into links to docs.
+fn is_page(path: &str) -> bool {
+    let extension = Path::new(path).extension().map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+    matches!(extension.as_deref(), Some("md" | "markdown" | "html" | "htm"))
+}
+
+/// Uploads the files that plain links of a Markdown body point at on disk,
+/// like attachments, and returns the body with the links rewritten. Links
+/// to pages, to missing files, to directories and to files outside of
+/// `root` are left alone.
+pub async fn upload_local_files(uploader: &dyn Uploader, markdown: &str, base_dir: &Path, root: &Path) -> Result<String> {
+    let mut urls = HashMap::new();
+    for cap in FILE_LINK.captures_iter(markdown) {
+        let Some(path) = local_path(&cap[3]).filter(|path| cap[1].is_empty() && !is_page(path) && !urls.contains_key(path)) else { continue };
+        let Ok(Some(file)) = contained(base_dir, root, &path) else { continue };
+        if !file.is_file() {
+            continue;
+        }
+        let name = file.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_else(|| path.clone());
+        let url = uploader.upload(&name, tokio::fs::read(&file).await?).await?;
+        urls.insert(path, url);
+    }
+    Ok(FILE_LINK
+        .replace_all(markdown, |cap: &Captures| match local_path(&cap[3]).and_then(|path| urls.get(&path)).filter(|_| cap[1].is_empty()) {
+            Some(url) => format!("{}{}", &cap[2], url),
+            None => cap[0].to_string(),
+        })
+        .into_owned())
+}
+
+impl Yuque {
+    /// Like `create_doc`, but uploads the images the body links to on disk first.
+    pub async fn create_doc_with_images<T: std::fmt::Display>(&self, repo: T, req: &mut CreateDocRequest, base_dir: &Path, uploader: &dyn Uploader) -> Result<DocSerializer> {
+        req.body = upload_local_images(uploader, &req.body, base_dir, base_dir).await?;
+        self.create_doc(repo, req).await
+    }
+
+    /// Like `update_doc`, but uploads the images the body links to on disk first.
+    pub async fn update_doc_with_images<T: std::fmt::Display>(&self, repo: T, doc_id: u32, req: &mut UpdateDocRequest, base_dir: &Path, uploader: &dyn Uploader) -> Result<DocSerializer> {
+        if let Some(body) = &req.body {
+            req.body = Some(upload_local_images(uploader, body, base_dir, base_dir).await?);
+        }
+        self.update_doc(repo, doc_id, req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use crate::upload::{local_images, upload_local_files, upload_local_images, Uploader};
+
+    struct MemoryUploader {
+        names: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl Uploader for MemoryUploader {
+        async fn upload(&self, name: &str, _data: Vec<u8>) -> Result<String> {
+            self.names.lock().unwrap().push(name.to_string());
+            Ok(format!("https://cdn.example.com/{}", name))
+        }
+    }
+
+    #[test]
+    fn test_local_images() {
+        let markdown = r#"![a](./img/arch.png "Arch") ![b](https://cdn.nlark.com/b.png) <img src="img/my%20c.png"> ![a again](./img/arch.png) [not an image](./doc.md)"#;
+        assert_eq!(local_images(markdown), vec!["./img/arch.png".to_string(), "img/my c.png".to_string()]);
+        assert!(local_images("![a](/img/a.png) ![d](/etc/passwd) ![e](%2Fetc%2Fpasswd)").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_upload_local_images() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("img")).unwrap();
+        std::fs::write(dir.path().join("img/arch.png"), b"png").unwrap();
+        let uploader = MemoryUploader { names: Mutex::new(Vec::new()) };
+        let markdown = "![a](./img/arch.png \"Arch\")\n\n<img src=\"./img/arch.png\" width=\"10\">";
+        let body = upload_local_images(&uploader, markdown, dir.path(), dir.path()).await.unwrap();
+        assert_eq!(body, "![a](https://cdn.example.com/arch.png \"Arch\")\n\n<img src=\"https://cdn.example.com/arch.png\" width=\"10\">");
+        assert_eq!(uploader.names.lock().unwrap().len(), 1);
+        assert!(upload_local_images(&uploader, "![x](missing.png)", dir.path(), dir.path()).await.is_err());
+        assert_eq!(upload_local_images(&uploader, "![x](/img/x.png)", dir.path(), dir.path()).await.unwrap(), "![x](/img/x.png)");
+
+        std::fs::create_dir(dir.path().join("docs")).unwrap();
+        let nested = dir.path().join("docs");
+        let body = upload_local_images(&uploader, "![a](../img/arch.png) ![b](../img/arch.png)", &nested, dir.path()).await.unwrap();
+        assert_eq!(body, "![a](https://cdn.example.com/arch.png) ![b](https://cdn.example.com/arch.png)");
+        let body = upload_local_images(&uploader, "![a](../img/arch.png)", &nested, &nested).await.unwrap();
+        assert_eq!(body, "![a](../img/arch.png)");
+    }
+
+    #[tokio::test]
+    async fn test_upload_local_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("spec v2.pdf"), b"pdf").unwrap();
+        let uploader = MemoryUploader { names: Mutex::new(Vec::new()) };
+        std::fs::write(dir.path().join("guide.md"), b"# Guide").unwrap();
+        let markdown = "[spec](spec%20v2.pdf) [missing](gone.pdf) ![img](spec%20v2.pdf) [dir](.) [guide](guide.md) [up](../spec.pdf)";
+        let body = upload_local_files(&uploader, markdown, dir.path(), dir.path()).await.unwrap();
+        assert_eq!(body, "[spec](https://cdn.example.com/spec v2.pdf) [missing](gone.pdf) ![img](spec%20v2.pdf) [dir](.) [guide](guide.md) [up](../spec.pdf)");
+        assert_eq!(*uploader.names.lock().unwrap(), vec!["spec v2.pdf"]);
+    }
+}
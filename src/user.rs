@@ -6,25 +6,15 @@ use std::fmt::Display;
 impl Yuque {
     pub async fn get_auth_user(&self) -> Result<UserSerializer> {
         let api = Yuque::build_api("/user", None);
-        let resp = self.client.get(api).send().await?;
-        if resp.status().is_success() {
-            let resp = resp.json::<APIResponse<UserSerializer>>().await?;
-            Ok(resp.data)
-        } else {
-            Err(anyhow::anyhow!("{} {}", resp.status(), resp.text().await?))
-        }
+        let resp = self.get_json::<APIResponse<UserSerializer>>(self.client.get(api)).await?;
+        Ok(resp.data)
     }
 
     pub async fn get_user<T: Display>(&self, id: T) -> Result<UserSerializer> {
         let endpoint = format!("/users/{}", id);
         let api = Yuque::build_api(&endpoint, None);
-        let resp = self.client.get(api).send().await?;
-        if resp.status().is_success() {
-            let resp = resp.json::<APIResponse<UserSerializer>>().await?;
-            Ok(resp.data)
-        } else {
-            Err(anyhow::anyhow!("{} {}", resp.status(), resp.text().await?))
-        }
+        let resp = self.get_json::<APIResponse<UserSerializer>>(self.client.get(api)).await?;
+        Ok(resp.data)
     }
 }
 
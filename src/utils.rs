@@ -1,4 +1,7 @@
+use std::collections::HashSet;
+use std::fmt;
 use rand::Rng;
+use reqwest::{Response, StatusCode};
 
 const CHARSET: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
 const PASSWORD_LEN: usize = 10;
@@ -11,4 +14,60 @@ pub fn gen_rand_str() -> String {
             CHARSET[idx] as char
         })
         .collect()
-}
\ No newline at end of file
+}
+
+/// Appends `-1`, `-2`, ... to `slug` until it is not in `taken`.
+pub fn unique_slug(slug: &str, taken: &HashSet<String>) -> String {
+    if !taken.contains(slug) {
+        return slug.to_string();
+    }
+    (1..)
+        .map(|i| format!("{}-{}", slug, i))
+        .find(|candidate| !taken.contains(candidate))
+        .unwrap()
+}
+
+/// A response with a non-success status, displayed as `<status> <body>`
+/// like the other request errors.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+impl ApiError {
+    pub async fn from_response(resp: Response) -> ApiError {
+        let status = resp.status();
+        let body = resp.text().await.unwrap_or_default();
+        ApiError { status, body }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.status, self.body)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// The status of a failed request, when the error came from a response.
+pub fn error_status(err: &anyhow::Error) -> Option<StatusCode> {
+    err.downcast_ref::<ApiError>().map(|e| e.status)
+}
+
+/// Whether a request failed with a 404.
+pub fn is_not_found(err: &anyhow::Error) -> bool {
+    error_status(err) == Some(StatusCode::NOT_FOUND)
+}
+
+/// Lowercase ASCII letters and digits joined by `-`, for file names made
+/// from titles. Empty when the title has none.
+pub fn slugify(title: &str) -> String {
+    title
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
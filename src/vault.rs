@@ -0,0 +1,507 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use anyhow::Result;
+use regex::{Captures, Regex};
+use serde_yaml::{Mapping, Value};
+
+use crate::asset::relative_link;
+use crate::backup::BackupOptions;
+use crate::doc::{CreateDocRequest, DocFormat, UpdateDocRequest};
+use crate::export::{front_matter, write_file, ExportRepo};
+use crate::links::PathMap;
+use crate::repo::UpdateRepoRequest;
+use crate::serializer::DocSerializer;
+use crate::toc::{merge_toc, parse_toc, render_toc_markdown, toc_tree, TocItem, TocItemType, TocNode};
+use crate::upload::{upload_local_images, YuqueUploader};
+use crate::utils::{slugify, unique_slug};
+use crate::yuque::Yuque;
+
+/// Placeholder path of doc links until they are turned into wikilinks.
+static WIKI_TARGET: &str = "yuque-wiki:";
+static LOGSEQ_PAGES: &str = "pages";
+
+static WIKI_TARGET_LINK: LazyLock<Regex> = LazyLock::new(|| Regex::new(&format!(r#"\[([^\]]*)\]\({}([^)#\s]+)(#[^)\s]*)?\)"#, WIKI_TARGET)).unwrap());
+static WIKI_TARGET_BARE: LazyLock<Regex> = LazyLock::new(|| Regex::new(&format!(r#"{}([^)#"\s]+)"#, WIKI_TARGET)).unwrap());
+static LOGSEQ_LINK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"\[([^\]]*)\]\(\[\[([^\]]+)\]\]\)"#).unwrap());
+static WIKILINK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"(!?)\[\[([^\]|#]*)(#[^\]|]*)?(?:\|([^\]]*))?\]\]"#).unwrap());
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum VaultFlavor {
+    /// Notes nested in folders, links as `[[Folder/Note#anchor|text]]`.
+    #[default]
+    Obsidian,
+    /// Flat `pages/` with namespaced names like `Folder___Note.md`, links as
+    /// `[text]([[Folder/Note]])`.
+    Logseq,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VaultOptions {
+    pub flavor: VaultFlavor,
+    /// Folder for images and attachments, relative to the vault. Defaults to
+    /// `attachments` for Obsidian and `assets` for Logseq.
+    pub asset_dir: Option<String>,
+}
+
+impl VaultOptions {
+    fn asset_dir(&self) -> &str {
+        match (&self.asset_dir, self.flavor) {
+            (Some(dir), _) => dir.trim_matches('/'),
+            (None, VaultFlavor::Obsidian) => "attachments",
+            (None, VaultFlavor::Logseq) => "assets",
+        }
+    }
+
+    /// The file of a note, relative to the vault.
+    fn note_path(&self, name: &str) -> String {
+        match self.flavor {
+            VaultFlavor::Obsidian => format!("{}.md", name),
+            VaultFlavor::Logseq => format!("{}/{}.md", LOGSEQ_PAGES, name.replace('/', "___")),
+        }
+    }
+}
+
+/// A Markdown file of a vault.
+#[derive(Debug, Clone)]
+pub struct VaultNote {
+    /// `/` separated path of the note without `.md`, as wikilinks name it.
+    pub name: String,
+    /// The file, relative to the vault.
+    pub path: String,
+    pub properties: Mapping,
+    pub body: String,
+}
+
+impl VaultNote {
+    pub fn property(&self, key: &str) -> Option<&Value> {
+        self.properties.get(key)
+    }
+
+    /// The `title` property, or the last part of the name.
+    pub fn title(&self) -> String {
+        match self.property("title").and_then(Value::as_str) {
+            Some(title) => title.to_string(),
+            None => self.name.rsplit('/').next().unwrap_or_default().to_string(),
+        }
+    }
+}
+
+/// A title usable as a note name: no path separators or characters that
+/// wikilinks reserve.
+fn note_name(title: &str) -> String {
+    let name: String = title.chars().filter(|c| !"\\/:*?\"<>|#^[]".contains(*c)).collect();
+    name.trim().trim_start_matches('.').to_string()
+}
+
+/// A relative link that stays one Markdown link target.
+pub(crate) fn link_path(from: &str, to: &str) -> String {
+    relative_link(from, to).replace(' ', "%20").replace('(', "%28").replace(')', "%29")
+}
+
+/// Names notes after their titles, TOC titles becoming folders. Docs with
+/// children get a folder of the same name next to their note.
+fn note_names(nodes: &[TocNode], dir: &str, taken: &mut HashSet<String>, out: &mut HashMap<String, String>) {
+    for node in nodes {
+        let item = &node.item;
+        let name = match note_name(&item.title) {
+            name if !name.is_empty() => name,
+            _ if item.typ == TocItemType::Doc => item.url.clone(),
+            _ => "Untitled".to_string(),
+        };
+        let name = match item.typ {
+            TocItemType::Doc => {
+                let name = unique_slug(&format!("{}{}", dir, name), taken);
+                taken.insert(name.clone());
+                out.insert(item.url.clone(), name.clone());
+                name
+            }
+            TocItemType::Title => format!("{}{}", dir, name),
+            _ => continue,
+        };
+        note_names(&node.children, &format!("{}/", name), taken, out);
+    }
+}
+
+fn properties(doc: &DocSerializer, flavor: VaultFlavor) -> Vec<(&'static str, Value)> {
+    let mut fields = vec![("id", doc.object.id.into()), ("slug", doc.slug.clone().into())];
+    // Logseq names the page after the `title` property, which would drop the namespace.
+    if flavor == VaultFlavor::Obsidian {
+        fields.push(("title", doc.title.clone().into()));
+    }
+    if let Some(creator) = &doc.creator {
+        fields.push(("creator", creator.login.clone().into()));
+    }
+    fields.push(("created_at", doc.object.created_at.clone().into()));
+    fields.push(("updated_at", doc.content_updated_at.clone().into()));
+    fields
+}
+
+fn wikilink(flavor: VaultFlavor, name: &str, anchor: &str, text: &str) -> String {
+    let basename = name.rsplit('/').next().unwrap_or_default();
+    match flavor {
+        VaultFlavor::Obsidian if text.is_empty() || text == basename => format!("[[{}{}]]", name, anchor),
+        VaultFlavor::Obsidian => format!("[[{}{}|{}]]", name, anchor, text),
+        // Logseq has no heading links.
+        VaultFlavor::Logseq if text.is_empty() || text == name => format!("[[{}]]", name),
+        VaultFlavor::Logseq => format!("[{}]([[{}]])", text, name),
+    }
+}
+
+/// Writes a repo as a vault under `out`, docs linking each other through
+/// wikilinks and carrying their metadata as properties.
+pub fn write_vault(repo: &ExportRepo, out: &Path, options: &VaultOptions) -> Result<()> {
+    let mut names = HashMap::new();
+    note_names(&toc_tree(&repo.toc), "", &mut HashSet::new(), &mut names);
+    let mut paths = PathMap::default();
+    for slug in names.keys() {
+        paths.insert(&repo.namespace, slug, &format!("{}{}", WIKI_TARGET, slug));
+    }
+    let asset_dir = options.asset_dir();
+    for doc in &repo.docs {
+        let Some(name) = names.get(&doc.doc.slug) else { continue };
+        let path = options.note_path(name);
+        // Doc links are resolved from the vault root, so the placeholders stay whole.
+        let body = repo.localize_with(&doc.markdown, "note.md", &paths, |asset, _| link_path(&path, &format!("{}/{}", asset_dir, asset)));
+        let body = WIKI_TARGET_LINK.replace_all(&body, |cap: &Captures| match names.get(&cap[2]) {
+            Some(target) => wikilink(options.flavor, target, cap.get(3).map_or("", |m| m.as_str()), &cap[1]),
+            None => cap[0].to_string(),
+        });
+        // Links outside Markdown link syntax, like html anchors, stay plain.
+        let body = WIKI_TARGET_BARE.replace_all(&body, |cap: &Captures| match names.get(&cap[1]) {
+            Some(target) => link_path(&path, &options.note_path(target)),
+            None => cap[0].to_string(),
+        });
+        let content = format!("{}{}", front_matter(&properties(&doc.doc, options.flavor))?, body);
+        write_file(out, &path, content.as_bytes())?;
+    }
+    repo.write_assets(&out.join(asset_dir))
+}
+
+/// Splits YAML front matter, or Logseq `key:: value` lines, off a note.
+pub(crate) fn split_properties(text: &str) -> Result<(Mapping, String)> {
+    let text = text.trim_start_matches('\u{feff}');
+    if let Some(rest) = text.strip_prefix("---\n").or_else(|| text.strip_prefix("---\r\n")) {
+        if let Some(end) = rest.find("\n---") {
+            let yaml = &rest[..end];
+            let body = rest[end + 4..].trim_start_matches(['\r', '\n']);
+            let properties = match serde_yaml::from_str(yaml)? {
+                Value::Mapping(mapping) => mapping,
+                _ => Mapping::new(),
+            };
+            return Ok((properties, body.to_string()));
+        }
+    }
+    let mut properties = Mapping::new();
+    let mut lines = text.lines().peekable();
+    while let Some((key, value)) = lines.peek().and_then(|line| line.split_once(":: ")) {
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            break;
+        }
+        let value: Value = serde_yaml::from_str(value.trim()).unwrap_or_else(|_| value.trim().into());
+        properties.insert(key.into(), value);
+        lines.next();
+    }
+    if properties.is_empty() {
+        return Ok((properties, text.to_string()));
+    }
+    let body: Vec<&str> = lines.collect();
+    Ok((properties, body.join("\n").trim_start_matches('\n').to_string()))
+}
+
+pub(crate) fn collect_notes(root: &Path, dir: &Path, skip: &Path, out: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let hidden = path.file_name().map(|name| name.to_string_lossy().starts_with('.')).unwrap_or_default();
+        if hidden || path == skip {
+            continue;
+        }
+        if path.is_dir() {
+            collect_notes(root, &path, skip, out)?;
+        } else if path.extension().is_some_and(|ext| ext == "md") {
+            out.push(path.strip_prefix(root)?.to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+/// Reads the notes of a vault, folders before the notes in them and
+/// otherwise by name.
+pub fn read_vault(dir: &Path, options: &VaultOptions) -> Result<Vec<VaultNote>> {
+    let root = match options.flavor {
+        VaultFlavor::Obsidian => dir.to_path_buf(),
+        VaultFlavor::Logseq => dir.join(LOGSEQ_PAGES),
+    };
+    let mut files = Vec::new();
+    collect_notes(&root, &root, &dir.join(options.asset_dir()), &mut files)?;
+    let mut notes = Vec::new();
+    for file in files {
+        let stem: Vec<String> = file.with_extension("").components().map(|c| c.as_os_str().to_string_lossy().into_owned()).collect();
+        let name = match options.flavor {
+            VaultFlavor::Obsidian => stem.join("/"),
+            VaultFlavor::Logseq => stem.join("/").replace("___", "/").replace("%2F", "/"),
+        };
+        let (properties, body) = split_properties(&std::fs::read_to_string(root.join(&file))?)?;
+        notes.push(VaultNote { path: options.note_path(&name), name, properties, body });
+    }
+    notes.sort_by(|a, b| a.name.split('/').cmp(b.name.split('/')));
+    Ok(notes)
+}
+
+/// Where each note goes in the repo: the slug and, for notes that came
+/// from it, the doc to update.
+fn plan_slugs(notes: &[VaultNote], existing: &[DocSerializer]) -> Vec<(String, Option<u32>)> {
+    let by_id: HashMap<u32, &DocSerializer> = existing.iter().map(|doc| (doc.object.id, doc)).collect();
+    let by_slug: HashMap<&str, &DocSerializer> = existing.iter().map(|doc| (doc.slug.as_str(), doc)).collect();
+    let mut matched: Vec<Option<&DocSerializer>> = notes
+        .iter()
+        .map(|note| {
+            // A note with the id of a doc elsewhere only shares the slug by
+            // chance.
+            match note.property("id").and_then(Value::as_u64) {
+                Some(id) => by_id.get(&(id as u32)).copied(),
+                None => note.property("slug").and_then(Value::as_str).and_then(|slug| by_slug.get(slug)).copied(),
+            }
+        })
+        .collect();
+    // A doc copied into two notes is only updated from the first.
+    let mut seen = HashSet::new();
+    for doc in matched.iter_mut() {
+        if doc.is_some_and(|d| !seen.insert(d.object.id)) {
+            *doc = None;
+        }
+    }
+    let mut taken: HashSet<String> = existing.iter().map(|doc| doc.slug.clone()).collect();
+    notes
+        .iter()
+        .zip(matched)
+        .map(|(note, doc)| match doc {
+            Some(doc) => (doc.slug.clone(), Some(doc.object.id)),
+            None => {
+                let wanted = note.property("slug").and_then(Value::as_str).map(str::to_string).unwrap_or_else(|| slugify(&note.title()));
+                let slug = unique_slug(if wanted.is_empty() { "note" } else { &wanted }, &taken);
+                taken.insert(slug.clone());
+                (slug, None)
+            }
+        })
+        .collect()
+}
+
+/// The notes of a vault by full name and, when unique, by last part.
+struct NoteIndex<'a> {
+    by_name: HashMap<&'a str, &'a VaultNote>,
+    by_basename: HashMap<&'a str, Option<&'a VaultNote>>,
+}
+
+impl<'a> NoteIndex<'a> {
+    fn new(notes: &'a [VaultNote]) -> NoteIndex<'a> {
+        let mut index = NoteIndex { by_name: HashMap::new(), by_basename: HashMap::new() };
+        for note in notes {
+            index.by_name.entry(note.name.as_str()).or_insert(note);
+            let basename = note.name.rsplit('/').next().unwrap_or_default();
+            index.by_basename.entry(basename).and_modify(|found| *found = None).or_insert(Some(note));
+        }
+        index
+    }
+
+    fn find(&self, name: &str) -> Option<&'a VaultNote> {
+        self.by_name.get(name).copied().or_else(|| self.by_basename.get(name).copied().flatten())
+    }
+}
+
+/// Turns wikilinks and embeds into Markdown links relative to the note.
+fn unwiki(note: &VaultNote, notes: &NoteIndex, asset_dir: &str) -> String {
+    let find = |name: &str| notes.find(name);
+    let page = |name: &str, anchor: &str, text: &str| -> Option<String> {
+        let target = find(name.trim())?;
+        let text = if text.is_empty() { name.rsplit('/').next().unwrap_or_default() } else { text };
+        Some(format!("[{}]({}{})", text, link_path(&note.path, &target.path), anchor))
+    };
+    let body = LOGSEQ_LINK.replace_all(&note.body, |cap: &Captures| page(&cap[2], "", &cap[1]).unwrap_or_else(|| cap[1].to_string()));
+    WIKILINK.replace_all(&body, |cap: &Captures| {
+        let name = &cap[2];
+        let anchor = cap.get(3).map_or("", |m| m.as_str());
+        let text = cap.get(4).map_or("", |m| m.as_str());
+        if &cap[1] == "!" && find(name).is_none() {
+            // Embedded files are looked up in the asset folder unless given a path.
+            let file = if name.contains('/') { name.to_string() } else { format!("{}/{}", asset_dir, name) };
+            return format!("![{}]({})", text, link_path(&note.path, &file));
+        }
+        page(name, anchor, text).unwrap_or_else(|| if text.is_empty() { name.to_string() } else { text.to_string() })
+    })
+    .into_owned()
+}
+
+/// Folders become `TITLE` items, unless a note has the same name, in which
+/// case the notes in it nest under that note.
+fn vault_toc(notes: &[VaultNote], slugs: &[(String, Option<u32>)]) -> Vec<TocItem> {
+    let names: HashSet<&str> = notes.iter().map(|note| note.name.as_str()).collect();
+    let mut folders = HashSet::new();
+    let mut items = Vec::new();
+    for (note, (slug, _)) in notes.iter().zip(slugs) {
+        let parts: Vec<&str> = note.name.split('/').collect();
+        for depth in 1..parts.len() {
+            let folder = parts[..depth].join("/");
+            if !names.contains(folder.as_str()) && folders.insert(folder) {
+                items.push(TocItem {
+                    typ: TocItemType::Title,
+                    title: parts[depth - 1].to_string(),
+                    url: String::new(),
+                    uuid: String::new(),
+                    doc_id: None,
+                    level: depth as u32 - 1,
+                });
+            }
+        }
+        items.push(TocItem::doc(&note.title(), slug, parts.len() as u32 - 1));
+    }
+    items
+}
+
+#[derive(Debug, Default)]
+pub struct VaultImportReport {
+    pub notes: Vec<ImportedNote>,
+}
+
+#[derive(Debug)]
+pub struct ImportedNote {
+    pub name: String,
+    pub slug: String,
+    pub doc_id: u32,
+    /// `false` when an existing doc was updated.
+    pub created: bool,
+}
+
+impl Yuque {
+    /// Exports a live repo as a vault.
+    pub async fn export_vault<T: Display>(&self, namespace: T, out: &Path, options: &VaultOptions) -> Result<()> {
+        let namespace = namespace.to_string();
+        let archive = self.backup_one_repo(&namespace, &BackupOptions::default()).await?;
+        let slug = namespace.rsplit('/').next().unwrap_or_default();
+        write_vault(&ExportRepo::from_backup(&archive, slug)?, out, options)
+    }
+
+    /// Pushes the notes of a vault into an existing repo. Notes exported from
+    /// it update their doc, found by the `id` or `slug` property; the others
+    /// become new docs. Local images are uploaded and new notes are added to
+    /// the TOC following the folders.
+    pub async fn import_vault<T: Display>(&self, namespace: T, dir: &Path, options: &VaultOptions) -> Result<VaultImportReport> {
+        let namespace = namespace.to_string();
+        let book = self.get_repo(&namespace).await?.data;
+        let existing = self.list_all_docs(&namespace, None).await?;
+        let notes = read_vault(dir, options)?;
+        let slugs = plan_slugs(&notes, &existing);
+
+        let mut paths = PathMap::default();
+        for (note, (slug, _)) in notes.iter().zip(&slugs) {
+            paths.insert(&namespace, slug, &note.path);
+        }
+        let index = NoteIndex::new(&notes);
+        let uploader = YuqueUploader::new(self, book.object.id);
+        let mut report = VaultImportReport::default();
+        for (note, (slug, doc_id)) in notes.iter().zip(&slugs) {
+            let body = paths.to_yuque(&unwiki(note, &index, options.asset_dir()), &note.path);
+            let base_dir = dir.join(&note.path);
+            let base_dir = base_dir.parent().unwrap_or(dir);
+            // Notes in folders reach the attachments of the vault with `../`.
+            let body = upload_local_images(&uploader, &body, base_dir, dir).await?;
+            let doc = match doc_id {
+                Some(doc_id) => {
+                    let mut req = UpdateDocRequest { title: Some(note.title()), body: Some(body), _force_asl: Some(1), ..UpdateDocRequest::default() };
+                    self.update_doc(&namespace, *doc_id, &mut req).await?
+                }
+                None => {
+                    let req = CreateDocRequest { title: note.title(), slug: slug.clone(), format: Some(DocFormat::Markdown), body, public: None };
+                    self.create_doc(&namespace, &req).await?
+                }
+            };
+            report.notes.push(ImportedNote { name: note.name.clone(), slug: slug.clone(), doc_id: doc.object.id, created: doc_id.is_none() });
+        }
+
+        let toc = merge_toc(parse_toc(book.toc_yml.as_deref().unwrap_or_default())?, &vault_toc(&notes, &slugs));
+        let req = UpdateRepoRequest { toc: Some(render_toc_markdown(&toc)), ..UpdateRepoRequest::default() };
+        self.update_repo(&namespace, &req).await?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::tests::sample_archive;
+    use crate::export::ExportRepo;
+    use crate::links::PathMap;
+    use crate::serializer::{DocSerializer, Object};
+    use crate::toc::render_toc_markdown;
+    use crate::vault::{plan_slugs, read_vault, unwiki, vault_toc, write_vault, NoteIndex, VaultFlavor, VaultNote, VaultOptions};
+
+    #[test]
+    fn test_write_vault() {
+        let repo = ExportRepo::from_backup(&sample_archive(), "kb").unwrap();
+        let read = |dir: &tempfile::TempDir, path: &str| std::fs::read_to_string(dir.path().join(path)).unwrap();
+
+        let obsidian = tempfile::tempdir().unwrap();
+        write_vault(&repo, obsidian.path(), &VaultOptions::default()).unwrap();
+        let intro = read(&obsidian, "Intro.md");
+        assert!(intro.starts_with("---\nid: 0\nslug: intro\ntitle: Intro\n"));
+        assert!(intro.contains("See the [[Start/Guide#setup|guide]]."));
+        assert!(intro.contains("![arch](attachments/"));
+        assert!(read(&obsidian, "Start/Guide.md").contains("## Setup"));
+        assert_eq!(std::fs::read_dir(obsidian.path().join("attachments")).unwrap().count(), 1);
+
+        let logseq = tempfile::tempdir().unwrap();
+        write_vault(&repo, logseq.path(), &VaultOptions { flavor: VaultFlavor::Logseq, asset_dir: None }).unwrap();
+        let intro = read(&logseq, "pages/Intro.md");
+        assert!(intro.contains("See the [guide]([[Start/Guide]])."));
+        assert!(intro.contains("![arch](../assets/"));
+        assert!(!intro.contains("title:"));
+        assert!(logseq.path().join("pages/Start___Guide.md").exists());
+    }
+
+    #[test]
+    fn test_import_plan() {
+        let repo = ExportRepo::from_backup(&sample_archive(), "kb").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let options = VaultOptions::default();
+        write_vault(&repo, dir.path(), &options).unwrap();
+        std::fs::write(dir.path().join("Start/New note.md"), "See [[Intro|the intro]] and ![[shot.png]].\n").unwrap();
+        std::fs::create_dir(dir.path().join(".obsidian")).unwrap();
+        std::fs::write(dir.path().join(".obsidian/ignored.md"), "").unwrap();
+
+        let notes = read_vault(dir.path(), &options).unwrap();
+        let names: Vec<_> = notes.iter().map(|note| note.name.as_str()).collect();
+        assert_eq!(names, vec!["Intro", "Start/Guide", "Start/New note"]);
+        assert_eq!(notes[0].title(), "Intro");
+        assert!(notes[0].body.starts_with("# Intro"));
+
+        let existing = vec![DocSerializer { slug: "intro".to_string(), ..DocSerializer::default() }];
+        let slugs = plan_slugs(&notes, &existing);
+        assert_eq!(slugs[0], ("intro".to_string(), Some(0)));
+        assert_eq!(slugs[1], ("guide".to_string(), None));
+        assert_eq!(slugs[2], ("new-note".to_string(), None));
+
+        let mut paths = PathMap::default();
+        for (note, (slug, _)) in notes.iter().zip(&slugs) {
+            paths.insert("me/kb", slug, &note.path);
+        }
+        let index = NoteIndex::new(&notes);
+        let intro = paths.to_yuque(&unwiki(&notes[0], &index, "attachments"), &notes[0].path);
+        assert!(intro.contains("See the [guide](https://www.yuque.com/me/kb/guide#setup)."));
+        let new = paths.to_yuque(&unwiki(&notes[2], &index, "attachments"), &notes[2].path);
+        assert_eq!(new, "See [the intro](https://www.yuque.com/me/kb/intro) and ![](../attachments/shot.png).\n");
+
+        assert_eq!(render_toc_markdown(&vault_toc(&notes, &slugs)), "- [Intro](intro)\n- Start\n  - [Guide](guide)\n  - [New note](new-note)\n");
+    }
+
+    #[test]
+    fn test_plan_slugs() {
+        let note = |properties: &str| VaultNote { name: "Intro".to_string(), path: "Intro.md".to_string(), properties: serde_yaml::from_str(properties).unwrap(), body: String::new() };
+        let existing = vec![DocSerializer { object: Object { id: 7, ..Object::default() }, slug: "intro".to_string(), ..DocSerializer::default() }];
+        let notes = vec![note("id: 5\nslug: intro"), note("slug: intro"), note("id: 7")];
+        assert_eq!(plan_slugs(&notes, &existing), vec![
+            ("intro-1".to_string(), None),
+            ("intro".to_string(), Some(7)),
+            ("intro-2".to_string(), None),
+        ]);
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::path::Path;
+use std::time::Duration;
+use anyhow::Result;
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+
+use crate::repo::ObjType;
+use crate::serializer::{BookSerializer, DocSerializer};
+use crate::yuque::Yuque;
+
+static DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The repos and docs seen by the last poll.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct WatchState {
+    /// Unset until the first poll, which only records a baseline.
+    pub polled_at: Option<String>,
+    /// Keyed by repo id.
+    pub repos: BTreeMap<u32, BookSerializer>,
+    /// Keyed by repo id, then doc id.
+    pub docs: BTreeMap<u32, BTreeMap<u32, DocSerializer>>,
+}
+
+impl WatchState {
+    pub async fn load(path: &Path) -> Result<WatchState> {
+        match tokio::fs::read(path).await {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(WatchState::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        let data = serde_json::to_vec_pretty(self)?;
+        tokio::fs::write(path, data).await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    /// Watch every repo of a user or group.
+    pub owner: Option<(ObjType, String)>,
+    /// Namespaces watched in addition to those of `owner`.
+    pub repos: Vec<String>,
+    pub interval: Duration,
+    /// Keeps the snapshot between restarts; without it the first poll after
+    /// a restart only records a baseline.
+    pub state_file: Option<String>,
+    /// Keeps the doc bodies so updates carry a diff summary, at the cost of
+    /// one request per changed doc.
+    pub bodies: bool,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions { owner: None, repos: Vec::new(), interval: DEFAULT_INTERVAL, state_file: None, bodies: false }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum WatchEvent {
+    DocCreated { namespace: String, after: Box<DocSerializer> },
+    DocUpdated { namespace: String, before: Box<DocSerializer>, after: Box<DocSerializer> },
+    DocDeleted { namespace: String, before: Box<DocSerializer> },
+    DocPublished { namespace: String, before: Box<DocSerializer>, after: Box<DocSerializer> },
+    RepoCreated { after: Box<BookSerializer> },
+    RepoRenamed { before: Box<BookSerializer>, after: Box<BookSerializer> },
+}
+
+fn repo_namespace(repo: &BookSerializer) -> String {
+    match (&repo.namespace, &repo.user) {
+        (Some(namespace), _) => namespace.clone(),
+        (None, Some(user)) => format!("{}/{}", user.login, repo.slug),
+        (None, None) => repo.slug.clone(),
+    }
+}
+
+/// Only the first publish counts, later ones are updates.
+fn published(before: &DocSerializer, after: &DocSerializer) -> bool {
+    before.published_at.is_none() && ((before.status == 0 && after.status == 1) || after.published_at.is_some())
+}
+
+fn updated(before: &DocSerializer, after: &DocSerializer) -> bool {
+    before.content_updated_at != after.content_updated_at || before.title != after.title || before.slug != after.slug || before.status != after.status
+}
+
+/// Copies the bodies of docs unchanged since `before` into `after` and
+/// returns the repo and doc ids of those that still need one.
+fn reuse_bodies(before: &WatchState, after: &mut WatchState) -> Vec<(u32, u32)> {
+    let mut missing = Vec::new();
+    for (repo_id, docs) in after.docs.iter_mut() {
+        for (doc_id, doc) in docs.iter_mut() {
+            let old = before.docs.get(repo_id).and_then(|docs| docs.get(doc_id));
+            match old {
+                Some(old) if old.body.is_some() && old.content_updated_at == doc.content_updated_at => doc.body = old.body.clone(),
+                _ => missing.push((*repo_id, *doc_id)),
+            }
+        }
+    }
+    missing
+}
+
+/// The events that lead from one snapshot to the next. Repos that are
+/// missing from `after` are not reported, nor are their docs.
+pub fn diff(before: &WatchState, after: &WatchState) -> Vec<WatchEvent> {
+    let mut events = Vec::new();
+    if before.polled_at.is_none() {
+        return events;
+    }
+    let no_docs = BTreeMap::new();
+    for (id, repo) in &after.repos {
+        match before.repos.get(id) {
+            None => events.push(WatchEvent::RepoCreated { after: Box::new(repo.clone()) }),
+            Some(old) if old.name != repo.name || old.slug != repo.slug || old.namespace != repo.namespace => {
+                events.push(WatchEvent::RepoRenamed { before: Box::new(old.clone()), after: Box::new(repo.clone()) });
+            }
+            Some(_) => {}
+        }
+        let namespace = repo_namespace(repo);
+        let old_docs = before.docs.get(id).unwrap_or(&no_docs);
+        let new_docs = after.docs.get(id).unwrap_or(&no_docs);
+        for (doc_id, doc) in new_docs {
+            let event = match old_docs.get(doc_id) {
+                None => WatchEvent::DocCreated { namespace: namespace.clone(), after: Box::new(doc.clone()) },
+                Some(old) if published(old, doc) => {
+                    WatchEvent::DocPublished { namespace: namespace.clone(), before: Box::new(old.clone()), after: Box::new(doc.clone()) }
+                }
+                Some(old) if updated(old, doc) => {
+                    WatchEvent::DocUpdated { namespace: namespace.clone(), before: Box::new(old.clone()), after: Box::new(doc.clone()) }
+                }
+                Some(_) => continue,
+            };
+            events.push(event);
+        }
+        for (doc_id, old) in old_docs {
+            if !new_docs.contains_key(doc_id) {
+                events.push(WatchEvent::DocDeleted { namespace: namespace.clone(), before: Box::new(old.clone()) });
+            }
+        }
+    }
+    events
+}
+
+struct Watcher<'a> {
+    yuque: &'a Yuque,
+    options: WatchOptions,
+    state: Option<WatchState>,
+    pending: VecDeque<WatchEvent>,
+    polled: bool,
+}
+
+impl Watcher<'_> {
+    async fn poll(&mut self) -> Result<()> {
+        if self.polled {
+            tokio::time::sleep(self.options.interval).await;
+        }
+        self.polled = true;
+        let state_file = self.options.state_file.as_ref().map(Path::new);
+        let state = match (self.state.take(), state_file) {
+            (Some(state), _) => state,
+            (None, Some(state_file)) => WatchState::load(state_file).await?,
+            (None, None) => WatchState::default(),
+        };
+        let next = match self.next_state(&state).await {
+            Ok(next) => next,
+            Err(e) => {
+                self.state = Some(state);
+                return Err(e);
+            }
+        };
+        self.pending.extend(diff(&state, &next));
+        // The events are queued, so a failed save must not bring them back.
+        let next = self.state.insert(next);
+        if let Some(state_file) = state_file {
+            next.save(state_file).await?;
+        }
+        Ok(())
+    }
+
+    async fn next_state(&self, state: &WatchState) -> Result<WatchState> {
+        let mut next = self.yuque.poll_watch_state(&self.options).await?;
+        if !self.options.bodies {
+            return Ok(next);
+        }
+        for (repo_id, doc_id) in reuse_bodies(state, &mut next) {
+            let namespace = repo_namespace(&next.repos[&repo_id]);
+            let Some(doc) = next.docs.get_mut(&repo_id).and_then(|docs| docs.get_mut(&doc_id)) else { continue };
+            doc.body = self.yuque.get_doc(namespace.as_str(), doc.slug.as_str(), Some(true)).await?.body;
+        }
+        Ok(next)
+    }
+}
+
+impl Yuque {
+    /// A snapshot of the watched repos and their docs.
+    pub async fn poll_watch_state(&self, options: &WatchOptions) -> Result<WatchState> {
+        let mut repos = Vec::new();
+        if let Some((obj_type, login)) = &options.owner {
+            repos = self.list_all_repos(*obj_type, login, None).await?;
+        }
+        for namespace in &options.repos {
+            if !repos.iter().any(|repo| repo_namespace(repo) == *namespace) {
+                repos.push(self.get_repo(namespace).await?.data);
+            }
+        }
+        let mut state = WatchState { polled_at: Some(chrono::Utc::now().to_rfc3339()), ..WatchState::default() };
+        for repo in repos {
+            let docs = self.list_all_docs(repo_namespace(&repo), None).await?;
+            state.docs.insert(repo.object.id, docs.into_iter().map(|doc| (doc.object.id, doc)).collect());
+            state.repos.insert(repo.object.id, repo);
+        }
+        Ok(state)
+    }
+
+    /// Polls the watched repos every `options.interval` and yields what
+    /// changed since the previous poll. A failed poll yields its error and
+    /// the next one is tried after the interval.
+    pub fn watch(&self, options: WatchOptions) -> impl Stream<Item = Result<WatchEvent>> + '_ {
+        let watcher = Watcher { yuque: self, options, state: None, pending: VecDeque::new(), polled: false };
+        futures::stream::unfold(watcher, |mut watcher| async move {
+            loop {
+                if let Some(event) = watcher.pending.pop_front() {
+                    return Some((Ok(event), watcher));
+                }
+                if let Err(e) = watcher.poll().await {
+                    return Some((Err(e), watcher));
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use crate::serializer::{BookSerializer, DocSerializer};
+    use crate::watch::{diff, reuse_bodies, WatchEvent, WatchState};
+
+    fn repo(id: u32, slug: &str) -> BookSerializer {
+        let mut repo = BookSerializer { slug: slug.to_string(), name: slug.to_string(), namespace: Some(format!("team/{}", slug)), ..BookSerializer::default() };
+        repo.object.id = id;
+        repo
+    }
+
+    fn doc(id: u32, updated_at: &str, published_at: Option<&str>) -> DocSerializer {
+        let mut doc = DocSerializer {
+            slug: format!("doc-{}", id),
+            content_updated_at: updated_at.to_string(),
+            status: 1,
+            published_at: published_at.map(str::to_string),
+            ..DocSerializer::default()
+        };
+        doc.object.id = id;
+        doc
+    }
+
+    fn state(repos: Vec<BookSerializer>, docs: Vec<DocSerializer>) -> WatchState {
+        let mut state = WatchState { polled_at: Some("t".to_string()), ..WatchState::default() };
+        let repo_id = repos[0].object.id;
+        state.docs.insert(repo_id, docs.into_iter().map(|doc| (doc.object.id, doc)).collect::<BTreeMap<_, _>>());
+        state.repos = repos.into_iter().map(|repo| (repo.object.id, repo)).collect();
+        state
+    }
+
+    #[test]
+    fn test_diff() {
+        let before = state(vec![repo(1, "kb")], vec![doc(1, "t1", Some("t1")), doc(2, "t1", Some("t1")), doc(3, "t1", Some("t1")), doc(6, "t1", Some("t1"))]);
+        let mut draft = doc(4, "t1", None);
+        draft.status = 0;
+        // Published again after being unpublished, which is an update.
+        let mut unpublished = doc(7, "t1", Some("t1"));
+        unpublished.status = 0;
+        let mut before_with_draft = before;
+        before_with_draft.docs.get_mut(&1).unwrap().insert(4, draft);
+        before_with_draft.docs.get_mut(&1).unwrap().insert(7, unpublished);
+
+        let mut renamed = repo(1, "handbook");
+        renamed.namespace = Some("team/handbook".to_string());
+        let after = state(
+            vec![renamed, repo(2, "new")],
+            vec![doc(1, "t1", Some("t1")), doc(2, "t2", Some("t1")), doc(4, "t2", Some("t2")), doc(5, "t2", Some("t2")), doc(6, "t2", Some("t2")), doc(7, "t1", Some("t1"))],
+        );
+
+        let events = diff(&before_with_draft, &after);
+        let names: Vec<String> = events
+            .iter()
+            .map(|event| match event {
+                WatchEvent::DocCreated { namespace, after } => format!("created {} {}", namespace, after.object.id),
+                WatchEvent::DocUpdated { namespace, before, after } => format!("updated {} {} {}", namespace, before.content_updated_at, after.content_updated_at),
+                WatchEvent::DocDeleted { namespace, before } => format!("deleted {} {}", namespace, before.object.id),
+                WatchEvent::DocPublished { namespace, after, .. } => format!("published {} {}", namespace, after.object.id),
+                WatchEvent::RepoCreated { after } => format!("repo created {}", after.slug),
+                WatchEvent::RepoRenamed { before, after } => format!("repo renamed {} {}", before.slug, after.slug),
+            })
+            .collect();
+        assert_eq!(names, vec![
+            "repo renamed kb handbook",
+            "updated team/handbook t1 t2",
+            "published team/handbook 4",
+            "created team/handbook 5",
+            "updated team/handbook t1 t2",
+            "updated team/handbook t1 t1",
+            "deleted team/handbook 3",
+            "repo created new",
+        ]);
+
+        // The first poll is only a baseline.
+        assert!(diff(&WatchState::default(), &after).is_empty());
+    }
+
+    #[test]
+    fn test_reuse_bodies() {
+        let mut kept = doc(1, "t1", Some("t1"));
+        kept.body = Some("kept".to_string());
+        let mut edited = doc(2, "t1", Some("t1"));
+        edited.body = Some("old".to_string());
+        let before = state(vec![repo(1, "kb")], vec![kept, edited, doc(3, "t1", Some("t1"))]);
+        let mut after = state(vec![repo(1, "kb")], vec![doc(1, "t1", Some("t1")), doc(2, "t2", Some("t1")), doc(3, "t1", Some("t1")), doc(4, "t2", None)]);
+
+        assert_eq!(reuse_bodies(&before, &mut after), vec![(1, 2), (1, 3), (1, 4)]);
+        assert_eq!(after.docs[&1][&1].body.as_deref(), Some("kept"));
+        assert_eq!(after.docs[&1][&2].body, None);
+    }
+}
@@ -0,0 +1,262 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::serializer::{DocSerializer, Object, UserSerializer};
+
+/// The `webhook_subject_type` of each supported event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookKind {
+    Publish,
+    Update,
+    Delete,
+    CommentCreate,
+    CommentReplyCreate,
+    CommentUpdate,
+}
+
+/// A doc event: the doc as it is after the change, with its repo in `book`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocWebhook {
+    #[serde(flatten)]
+    pub doc: DocSerializer,
+    pub webhook_subject_type: WebhookKind,
+    #[serde(default)]
+    pub actor_id: Option<u32>,
+    /// Who triggered the event, which is not necessarily the last editor.
+    #[serde(default)]
+    pub actor: Option<UserSerializer>,
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommentWebhook {
+    #[serde(flatten)]
+    pub object: Object,
+    pub webhook_subject_type: WebhookKind,
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default)]
+    pub body_html: Option<String>,
+    #[serde(default)]
+    pub user_id: Option<u32>,
+    #[serde(default)]
+    pub user: Option<UserSerializer>,
+    #[serde(default)]
+    pub parent_id: Option<u32>,
+    #[serde(default)]
+    pub commentable_type: Option<String>,
+    #[serde(default)]
+    pub commentable_id: Option<u32>,
+    /// The commented doc.
+    #[serde(default)]
+    pub commentable: Option<DocSerializer>,
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum WebhookEvent {
+    Doc(Box<DocWebhook>),
+    Comment(Box<CommentWebhook>),
+}
+
+impl WebhookEvent {
+    pub fn kind(&self) -> WebhookKind {
+        match self {
+            WebhookEvent::Doc(doc) => doc.webhook_subject_type,
+            WebhookEvent::Comment(comment) => comment.webhook_subject_type,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct WebhookPayload {
+    data: Value,
+}
+
+/// Parses the body of a webhook request, `{"data": {...}}`.
+pub fn parse_webhook(body: &[u8]) -> Result<WebhookEvent> {
+    let payload: WebhookPayload = serde_json::from_slice(body)?;
+    let subject = payload.data.get("webhook_subject_type").cloned().ok_or_else(|| anyhow::anyhow!("missing webhook_subject_type"))?;
+    let kind: WebhookKind = serde_json::from_value(subject.clone()).map_err(|_| anyhow::anyhow!("unsupported webhook subject {}", subject))?;
+    Ok(match kind {
+        WebhookKind::Publish | WebhookKind::Update | WebhookKind::Delete => WebhookEvent::Doc(Box::new(serde_json::from_value(payload.data)?)),
+        _ => WebhookEvent::Comment(Box::new(serde_json::from_value(payload.data)?)),
+    })
+}
+
+type Handler = Box<dyn Fn(&WebhookEvent) + Send + Sync>;
+
+/// Validates webhook requests and hands the events to the registered
+/// handlers.
+///
+/// Yuque does not sign its webhooks, so a secret can be put in the webhook
+/// URL as `?token=...` and checked here.
+#[derive(Default)]
+pub struct WebhookDispatcher {
+    secret: Option<String>,
+    handlers: Vec<(Option<WebhookKind>, Handler)>,
+}
+
+impl WebhookDispatcher {
+    pub fn new() -> WebhookDispatcher {
+        WebhookDispatcher::default()
+    }
+
+    pub fn secret(mut self, secret: &str) -> WebhookDispatcher {
+        self.secret = Some(secret.to_string());
+        self
+    }
+
+    /// Calls `handler` for events of `kind`.
+    pub fn on<F: Fn(&WebhookEvent) + Send + Sync + 'static>(mut self, kind: WebhookKind, handler: F) -> WebhookDispatcher {
+        self.handlers.push((Some(kind), Box::new(handler)));
+        self
+    }
+
+    /// Calls `handler` for every event.
+    pub fn on_any<F: Fn(&WebhookEvent) + Send + Sync + 'static>(mut self, handler: F) -> WebhookDispatcher {
+        self.handlers.push((None, Box::new(handler)));
+        self
+    }
+
+    pub fn verify(&self, token: Option<&str>) -> bool {
+        match (&self.secret, token) {
+            (None, _) => true,
+            (Some(secret), Some(token)) => secret.len() == token.len() && secret.bytes().zip(token.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0,
+            (Some(_), None) => false,
+        }
+    }
+
+    /// Parses `body` and runs the matching handlers.
+    pub fn dispatch(&self, body: &[u8]) -> Result<WebhookEvent> {
+        let event = parse_webhook(body)?;
+        let kind = event.kind();
+        for (filter, handler) in &self.handlers {
+            if filter.is_none_or(|filter| filter == kind) {
+                handler(&event);
+            }
+        }
+        Ok(event)
+    }
+}
+
+#[cfg(feature = "webhook-server")]
+mod server {
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use anyhow::Result;
+    use axum::{body::Bytes, extract::{Query, State}, http::StatusCode, routing::post, Router};
+
+    use super::WebhookDispatcher;
+
+    async fn receive(State(dispatcher): State<Arc<WebhookDispatcher>>, Query(query): Query<HashMap<String, String>>, body: Bytes) -> StatusCode {
+        if !dispatcher.verify(query.get("token").map(String::as_str)) {
+            return StatusCode::UNAUTHORIZED;
+        }
+        match dispatcher.dispatch(&body) {
+            Ok(_) => StatusCode::OK,
+            Err(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    /// A router that accepts webhooks with `POST` at `path`.
+    pub fn webhook_router(path: &str, dispatcher: WebhookDispatcher) -> Router {
+        Router::new().route(path, post(receive)).with_state(Arc::new(dispatcher))
+    }
+
+    /// Serves webhooks at `/` until the task is dropped.
+    pub async fn serve_webhooks(addr: SocketAddr, dispatcher: WebhookDispatcher) -> Result<()> {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        axum::serve(listener, webhook_router("/", dispatcher)).await?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "webhook-server")]
+pub use server::{serve_webhooks, webhook_router};
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use crate::webhook::{parse_webhook, WebhookDispatcher, WebhookEvent, WebhookKind};
+
+    static USER: &str = r#"{"id": 2, "type": "User", "login": "alice", "name": "Alice", "avatar_url": "https://cdn.nlark.com/a.png", "followers_count": 1, "following_count": 2, "created_at": "2023-01-01T00:00:00.000Z", "updated_at": "2023-01-01T00:00:00.000Z", "_serializer": "v2.user"}"#;
+
+    fn doc(subject: &str) -> String {
+        format!(
+            r#"{{"data": {{"id": 30, "slug": "intro", "title": "Intro", "book_id": 10, "user_id": 2, "format": "markdown", "body": "hi", "body_html": "<p>hi</p>",
+"public": 1, "status": 1, "view_status": 0, "read_status": 1, "word_count": 1, "content_updated_at": "2024-05-01T08:00:00.000Z",
+"created_at": "2024-05-01T08:00:00.000Z", "updated_at": "2024-05-01T08:00:00.000Z", "_serializer": "v2.doc_detail",
+"book": {{"id": 10, "type": "Book", "slug": "kb", "name": "KB", "namespace": "team/kb", "user_id": 1, "public": 1, "items_count": 1, "likes_count": 0, "watches_count": 0,
+"created_at": "2023-01-01T00:00:00.000Z", "updated_at": "2023-01-01T00:00:00.000Z", "_serializer": "v2.book"}},
+"actor_id": 2, "actor": {USER}, "webhook_subject_type": "{subject}", "path": "team/kb/intro"}}}}"#
+        )
+    }
+
+    fn comment(subject: &str) -> String {
+        format!(
+            r#"{{"data": {{"id": 500, "body": "Nice", "body_html": "<p>Nice</p>", "user_id": 2, "user": {USER}, "parent_id": null,
+"commentable_type": "Doc", "commentable_id": 30, "created_at": "2024-05-02T08:00:00.000Z", "updated_at": "2024-05-02T08:00:00.000Z", "_serializer": "v2.comment",
+"webhook_subject_type": "{subject}", "path": "team/kb/intro"}}}}"#
+        )
+    }
+
+    #[test]
+    fn test_parse_webhook() {
+        let WebhookEvent::Doc(event) = parse_webhook(doc("publish").as_bytes()).unwrap() else { panic!("not a doc event") };
+        assert_eq!(event.webhook_subject_type, WebhookKind::Publish);
+        assert_eq!(event.doc.slug, "intro");
+        assert_eq!(event.doc.book.as_ref().and_then(|book| book.namespace.as_deref()), Some("team/kb"));
+        assert_eq!(event.actor.as_ref().map(|actor| actor.login.as_str()), Some("alice"));
+
+        let WebhookEvent::Comment(event) = parse_webhook(comment("comment_reply_create").as_bytes()).unwrap() else { panic!("not a comment event") };
+        assert_eq!(event.webhook_subject_type, WebhookKind::CommentReplyCreate);
+        assert_eq!((event.object.id, event.commentable_id), (500, Some(30)));
+        assert_eq!(event.body.as_deref(), Some("Nice"));
+
+        assert!(parse_webhook(doc("unknown").as_bytes()).is_err());
+        assert!(parse_webhook(b"{}").is_err());
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let (published, any) = (seen.clone(), seen.clone());
+        let dispatcher = WebhookDispatcher::new()
+            .secret("s3cret")
+            .on(WebhookKind::Publish, move |event| published.lock().unwrap().push(format!("publish {:?}", event.kind())))
+            .on_any(move |event| any.lock().unwrap().push(format!("any {:?}", event.kind())));
+
+        assert!(dispatcher.verify(Some("s3cret")));
+        assert!(!dispatcher.verify(Some("s3cre")));
+        assert!(!dispatcher.verify(None));
+
+        dispatcher.dispatch(doc("publish").as_bytes()).unwrap();
+        dispatcher.dispatch(comment("comment_create").as_bytes()).unwrap();
+        assert_eq!(*seen.lock().unwrap(), vec!["publish Publish", "any Publish", "any CommentCreate"]);
+    }
+
+    #[cfg(feature = "webhook-server")]
+    #[tokio::test]
+    async fn test_webhook_router() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let handled = seen.clone();
+        let dispatcher = WebhookDispatcher::new().secret("s3cret").on(WebhookKind::Delete, move |event| handled.lock().unwrap().push(event.kind()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, crate::webhook::webhook_router("/hooks", dispatcher)).await });
+
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/hooks", addr);
+        let status = |resp: reqwest::Response| resp.status().as_u16();
+        assert_eq!(status(client.post(&url).body(doc("delete")).send().await.unwrap()), 401);
+        assert_eq!(status(client.post(format!("{}?token=s3cret", url)).body("{").send().await.unwrap()), 400);
+        assert_eq!(status(client.post(format!("{}?token=s3cret", url)).body(doc("delete")).send().await.unwrap()), 200);
+        assert_eq!(*seen.lock().unwrap(), vec![WebhookKind::Delete]);
+    }
+}
@@ -1,5 +1,8 @@
 use reqwest::{Client, header::HeaderMap};
 use anyhow::Result;
+use sha2::{Digest, Sha256};
+
+use crate::cache::ResponseCache;
 
 static DEFAULT_BASE_API: &str = "https://www.yuque.com/api/v2";
 static DEFAULT_USER_AGENT: &str = "Yuque-Rust-Client";
@@ -7,6 +10,12 @@ static DEFAULT_USER_AGENT: &str = "Yuque-Rust-Client";
 #[derive(Debug)]
 pub struct Yuque {
     pub client: Client,
+    /// Set with `with_cache`.
+    pub cache: Option<ResponseCache>,
+    /// Hash of the token, which keeps the cached responses of tokens apart.
+    pub(crate) token_hash: String,
+    /// For hosts other than Yuque's, which must not see the token.
+    pub(crate) anonymous: Client,
 }
 
 impl Yuque {
@@ -17,7 +26,8 @@ impl Yuque {
             .user_agent(DEFAULT_USER_AGENT)
             .default_headers(headers)
             .build()?;
-        Ok(Yuque { client })
+        let anonymous = Client::builder().user_agent(DEFAULT_USER_AGENT).build()?;
+        Ok(Yuque { client, cache: None, token_hash: hex::encode(Sha256::digest(token.as_bytes())), anonymous })
     }
 
     pub fn build_api(endpoint: &str, space: Option<&str>) -> String {